@@ -0,0 +1 @@
+(function_item name: (identifier) @name) @definition
@@ -0,0 +1,215 @@
+use crate::count::Counts;
+use crate::error::{Error, Result};
+use crate::output::Column;
+use serde_json::{json, Map, Value};
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::path::Path;
+
+/// Version of the snapshot format written by --save-baseline. This should be bumped whenever the
+/// format changes in a way older versions of tcount can't read.
+const VERSION: u64 = 1;
+
+/// Counts saved with --save-baseline to compare against later with --compare
+#[derive(Debug, PartialEq)]
+pub struct Baseline {
+    pub groupby: String,
+    /// Counts for each group by the key of their column, see `Column::key`
+    pub groups: Vec<(String, HashMap<String, u64>)>,
+}
+
+/// A Baseline along with the groups in it which aren't in the current counts
+#[derive(Debug)]
+pub struct Comparison {
+    pub baseline: Baseline,
+    pub removed: Vec<String>,
+}
+
+impl Baseline {
+    pub fn new(groupby: &str, columns: &[Column], counts: &[(String, Counts)]) -> Baseline {
+        Baseline {
+            groupby: groupby.to_string(),
+            groups: counts
+                .iter()
+                .map(|(group, counts)| {
+                    (
+                        group.clone(),
+                        columns
+                            .iter()
+                            .map(Column::key)
+                            .zip(counts.values())
+                            .collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    fn to_json(&self) -> Value {
+        json!({
+            "version": VERSION,
+            "groupby": self.groupby,
+            "groups": self
+                .groups
+                .iter()
+                .map(|(group, counts)| {
+                    json!({
+                        "group": group,
+                        "counts": counts
+                            .iter()
+                            .map(|(key, value)| (key.clone(), Value::from(*value)))
+                            .collect::<Map<String, Value>>(),
+                    })
+                })
+                .collect::<Vec<Value>>(),
+        })
+    }
+
+    fn from_json(json: &Value) -> std::result::Result<Baseline, String> {
+        match json.get("version").and_then(Value::as_u64) {
+            Some(VERSION) => {}
+            Some(version) => {
+                return Err(format!(
+                    "version {} isn't supported, only version {} is",
+                    version, VERSION
+                ))
+            }
+            None => return Err(String::from("missing version")),
+        }
+        let groupby = json
+            .get("groupby")
+            .and_then(Value::as_str)
+            .ok_or("missing groupby")?;
+        let groups = json
+            .get("groups")
+            .and_then(Value::as_array)
+            .ok_or("missing groups")?
+            .iter()
+            .map(|group| {
+                let name = group
+                    .get("group")
+                    .and_then(Value::as_str)
+                    .ok_or("missing group name")?;
+                let counts = group
+                    .get("counts")
+                    .and_then(Value::as_object)
+                    .ok_or("missing counts")?
+                    .iter()
+                    .map(|(key, value)| {
+                        value
+                            .as_u64()
+                            .map(|value| (key.clone(), value))
+                            .ok_or_else(|| format!("count {} is not a number", key))
+                    })
+                    .collect::<std::result::Result<HashMap<String, u64>, String>>()?;
+                Ok((name.to_string(), counts))
+            })
+            .collect::<std::result::Result<Vec<_>, String>>()?;
+        Ok(Baseline {
+            groupby: groupby.to_string(),
+            groups,
+        })
+    }
+
+    /// Write the baseline to @path, replacing it if it exists. It's indented so changes to it are
+    /// easy to review when it's committed.
+    pub fn save(&self, path: impl AsRef<Path>) -> Result<()> {
+        let json = serde_json::to_string_pretty(&self.to_json())
+            .map_err(|e| Error::Baseline(e.to_string()))?;
+        fs::write(path, json + "\n")?;
+        Ok(())
+    }
+
+    /// Read the baseline at @path which must have been saved with the same --groupby as @groupby
+    pub fn load(path: impl AsRef<Path>, groupby: &str) -> Result<Baseline> {
+        let path = path.as_ref();
+        let err = |e: String| Error::Baseline(format!("{}: {}", path.display(), e));
+        let json: Value =
+            serde_json::from_str(&fs::read_to_string(path)?).map_err(|e| err(e.to_string()))?;
+        let baseline = Baseline::from_json(&json).map_err(err)?;
+        if baseline.groupby != groupby {
+            return Err(err(format!(
+                "it was saved with --groupby={} but --groupby is {}",
+                baseline.groupby, groupby
+            )));
+        }
+        Ok(baseline)
+    }
+
+    /// The count for @key in @group, if the group and column were counted
+    pub fn get(&self, group: &str, key: &str) -> Option<u64> {
+        self.groups
+            .iter()
+            .find(|(name, _)| name == group)
+            .and_then(|(_, counts)| counts.get(key).copied())
+    }
+
+    /// The total of the counts for @key across every group, if the column was counted
+    pub fn total(&self, key: &str) -> Option<u64> {
+        self.groups
+            .iter()
+            .map(|(_, counts)| counts.get(key))
+            .sum::<Option<u64>>()
+    }
+
+    /// Compare the baseline with every group in @counts
+    pub fn compare(self, counts: &[(String, Counts)]) -> Comparison {
+        let current: HashSet<&String> = counts.iter().map(|(group, _)| group).collect();
+        let removed = self
+            .groups
+            .iter()
+            .map(|(group, _)| group)
+            .filter(|group| !current.contains(group))
+            .cloned()
+            .collect();
+        Comparison {
+            baseline: self,
+            removed,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn baseline_round_trip() {
+        let mut counts = Counts::empty(1, 0, &[]);
+        counts.nfiles = 2;
+        counts.ntokens = 10;
+        counts.nkinds = vec![3];
+        let columns = vec![
+            Column::Files,
+            Column::Tokens,
+            Column::Kind(String::from("identifier")),
+        ];
+        let baseline = Baseline::new("language", &columns, &[(String::from("Rust"), counts)]);
+        assert_eq!(Some(3), baseline.get("Rust", "kind:identifier"));
+        assert_eq!(None, baseline.get("Go", "tokens"));
+        assert_eq!(Some(10), baseline.total("tokens"));
+        assert_eq!(None, baseline.total("query:comment"));
+        assert_eq!(Ok(baseline), Baseline::from_json(&baseline_json()));
+    }
+
+    fn baseline_json() -> Value {
+        json!({
+            "version": 1,
+            "groupby": "language",
+            "groups": [
+                {"group": "Rust", "counts": {"files": 2, "tokens": 10, "kind:identifier": 3}},
+            ],
+        })
+    }
+
+    #[test]
+    fn baseline_versions() {
+        let mut json = baseline_json();
+        json["version"] = Value::from(2);
+        assert_eq!(
+            Err(String::from("version 2 isn't supported, only version 1 is")),
+            Baseline::from_json(&json)
+        );
+        assert!(Baseline::from_json(&json!({})).is_err());
+    }
+}
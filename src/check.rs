@@ -0,0 +1,754 @@
+use crate::count::{Counts, FileCounts};
+use crate::error::{Error, Result};
+use crate::language::Language;
+use crate::output::{escape_xml, Column};
+use crate::query::{Query, QueryKind};
+use crate::tree::TreeIterator;
+use glob::Pattern;
+use once_cell::sync::Lazy;
+use rayon::prelude::*;
+use regex::Regex;
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+use std::format;
+use std::path::Path;
+use std::str::FromStr;
+use tree_sitter::{Node, Parser, QueryCursor};
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Format {
+    Human,
+    Junit,
+    Checkstyle,
+    Github,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "human" => Ok(Format::Human),
+            "junit" => Ok(Format::Junit),
+            "checkstyle" => Ok(Format::Checkstyle),
+            "github" => Ok(Format::Github),
+            _ => Err(format!(
+                "\"{}\" is not supported by --check. Use one of human|junit|checkstyle|github",
+                s
+            )),
+        }
+    }
+}
+
+/// What a rule's maximum applies to
+#[derive(Debug, PartialEq, Eq, Clone, Copy)]
+pub enum Per {
+    File,
+    Group,
+    /// Each node captured as @definition by the "definition" query
+    Definition,
+}
+
+impl fmt::Display for Per {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Per::File => write!(f, "file"),
+            Per::Group => write!(f, "group"),
+            Per::Definition => write!(f, "definition"),
+        }
+    }
+}
+
+/// A limit on a column, e.g. "max tokens per file = 4000". Rules can be scoped to the files of a
+/// language or the files matching a glob with "in", e.g. "max tokens per file in src/legacy/** =
+/// 8000".
+#[derive(Debug)]
+pub struct Rule {
+    /// Key of the column which is limited
+    pub column: String,
+    pub per: Per,
+    pub max: u64,
+    /// The language name or glob the rule is scoped to, and the glob itself
+    scope: Option<(String, Pattern)>,
+}
+
+static RULE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"^\s*max\s+(\S+)\s+per\s+(file|group|definition)(?:\s+in\s+(.+?))?\s*=\s*(\d+)\s*$")
+        .expect("Unexpected internal error compiling the rule syntax")
+});
+
+impl FromStr for Rule {
+    type Err = String;
+
+    /// Parse a rule with the syntax "max {column} per {file|group|definition} (in {scope})? = {n}".
+    /// "complexity" is short for "query:complexity".
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let caps = RULE.captures(s).ok_or_else(|| {
+            format!(
+                "Invalid rule \"{}\". Rules look like \"max {{column}} per {{file|group|definition}} = {{n}}\", optionally with \"in {{language or glob}}\" before the \"=\"",
+                s
+            )
+        })?;
+        let scope = caps
+            .get(3)
+            .map(|scope| {
+                Pattern::new(scope.as_str())
+                    .map(|pattern| (scope.as_str().to_string(), pattern))
+                    .map_err(|err| format!("Invalid glob \"{}\": {}", scope.as_str(), err))
+            })
+            .transpose()?;
+        Ok(Rule {
+            column: match &caps[1] {
+                "complexity" => String::from("query:complexity"),
+                column => column.to_string(),
+            },
+            per: match &caps[2] {
+                "file" => Per::File,
+                "group" => Per::Group,
+                _ => Per::Definition,
+            },
+            max: caps[4]
+                .parse()
+                .map_err(|err| format!("Invalid maximum \"{}\": {}", &caps[4], err))?,
+            scope,
+        })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "max {} per {}", self.column, self.per)?;
+        if let Some((scope, _)) = &self.scope {
+            write!(f, " in {}", scope)?;
+        }
+        write!(f, " = {}", self.max)
+    }
+}
+
+impl Rule {
+    /// Whether the rule applies to @path, a file of @lang. The scope is either the name of a
+    /// language or a glob for the path relative to the current directory.
+    fn applies_to(&self, lang: &Language, path: &Path) -> bool {
+        match &self.scope {
+            Some((scope, pattern)) => *scope == lang.to_string() || pattern.matches_path(path),
+            None => true,
+        }
+    }
+}
+
+/// A file, group, or definition which has more of a column than a rule allows
+#[derive(Debug)]
+pub struct Violation<'a> {
+    pub rule: &'a Rule,
+    /// The file, or the group when the rule is per group
+    pub subject: String,
+    /// Line and name of the definition when the rule is per definition
+    pub definition: Option<(usize, String)>,
+    pub value: u64,
+}
+
+impl Violation<'_> {
+    fn message(&self) -> String {
+        let within = match &self.definition {
+            Some((_, name)) => format!(" in {}", name),
+            None => String::new(),
+        };
+        format!(
+            "{} {}{} is more than the maximum of {}",
+            self.value, self.rule.column, within, self.rule.max
+        )
+    }
+
+    /// Where the violation is, as the file and line of a definition, a file, or a group
+    fn location(&self) -> String {
+        match &self.definition {
+            Some((line, _)) => format!("{}:{}", self.subject, line),
+            None => self.subject.clone(),
+        }
+    }
+
+    /// The path of the file the violation is in, without a leading "./"
+    fn path(&self) -> Option<String> {
+        match self.rule.per {
+            Per::Group => None,
+            _ => Some(self.subject.clone()),
+        }
+    }
+}
+
+/// @path relative to the current directory, which is how rules and reports refer to files
+fn relative(path: &Path) -> &Path {
+    path.strip_prefix(".").unwrap_or(path)
+}
+
+/// A definition found by the definition query, where @name is the text of its @name capture or
+/// its kind without one, and @values are the counts of each column within it
+#[derive(Debug, PartialEq, Eq)]
+struct Definition {
+    line: usize,
+    name: String,
+    values: Vec<u64>,
+}
+
+/// Count @column within each definition in @text, a file of @lang. Definitions are the nodes
+/// captured as @definition by @definitions and include any definitions nested inside them.
+fn count_definitions(
+    text: &str,
+    lang: &Language,
+    definitions: &Query,
+    columns: &[(&Rule, &Column)],
+    kind_patterns: &[Regex],
+    queries: &[Query],
+) -> Result<Vec<Definition>> {
+    let ts_query = match definitions.langs.get(lang) {
+        Some(ts_query) => ts_query,
+        None => return Ok(Vec::new()),
+    };
+    let mut parser = Parser::new();
+    parser
+        .set_language(lang.get_treesitter_language()?)
+        .expect("Unexpected internal error setting parser language");
+    let tree = match parser.parse(text, None) {
+        Some(tree) => tree,
+        None => return Ok(Vec::new()),
+    };
+    let text_callback = |n: Node| &text[n.byte_range()];
+    let names = ts_query.capture_names();
+    let mut qcursor = QueryCursor::new();
+    let found: Vec<(Node, String)> = qcursor
+        .matches(ts_query, tree.root_node(), text_callback)
+        .filter_map(|qmatch| {
+            let capture = |name: &str| {
+                qmatch
+                    .captures
+                    .iter()
+                    .find(|capture| names[capture.index as usize] == name)
+                    .map(|capture| capture.node)
+            };
+            capture("definition").map(|node| {
+                let name = capture("name")
+                    .map(|name| text[name.byte_range()].to_string())
+                    .unwrap_or_else(|| node.kind().to_string());
+                (node, name)
+            })
+        })
+        .collect();
+
+    let mut qcursor = QueryCursor::new();
+    Ok(found
+        .iter()
+        .map(|(node, name)| {
+            let node = *node;
+            let values = columns
+                .iter()
+                .map(|(_, column)| {
+                    let nodes = || TreeIterator::from_node(node).filter(|node| !node.is_missing());
+                    let n = match column {
+                        Column::Tokens => nodes()
+                            .filter(|node| node.child_count() == 0 && !node.is_extra())
+                            .count(),
+                        Column::Kind(kind) => nodes().filter(|node| node.kind() == kind).count(),
+                        Column::Pattern(pattern) => {
+                            match kind_patterns.iter().find(|re| re.as_str() == pattern) {
+                                Some(re) => nodes().filter(|node| re.is_match(node.kind())).count(),
+                                None => 0,
+                            }
+                        }
+                        Column::Query(name) => {
+                            match queries
+                                .iter()
+                                .find(|query| query.name == *name && query.kind == QueryKind::Match)
+                                .and_then(|query| query.langs.get(lang))
+                            {
+                                Some(ts_query) => {
+                                    qcursor.matches(ts_query, node, text_callback).count()
+                                }
+                                None => 0,
+                            }
+                        }
+                        Column::Capture(name, capture) => {
+                            match queries
+                                .iter()
+                                .find(|query| query.name == *name)
+                                .and_then(|query| query.langs.get(lang))
+                            {
+                                Some(ts_query) => {
+                                    let index = ts_query
+                                        .capture_names()
+                                        .iter()
+                                        .position(|name| name == capture);
+                                    qcursor
+                                        .captures(ts_query, node, text_callback)
+                                        .filter(|(qmatch, i)| {
+                                            Some(qmatch.captures[*i].index as usize) == index
+                                        })
+                                        .count()
+                                }
+                                None => 0,
+                            }
+                        }
+                        // rejected before any definitions are counted
+                        Column::Files | Column::MedianAge | Column::PercentOld => 0,
+                    };
+                    n as u64
+                })
+                .collect();
+            Definition {
+                line: node.start_position().row + 1,
+                name: name.clone(),
+                values,
+            }
+        })
+        .collect())
+}
+
+/// Find every violation of @rules in @files, where @columns are the columns of their counts.
+/// @definitions is the query which finds definitions for rules per definition, and @read reads
+/// the text of a file to find its definitions.
+pub fn check<'a>(
+    rules: &'a [Rule],
+    columns: &'a [Column],
+    files: &[FileCounts],
+    (kind_patterns, queries): (&[Regex], &[Query]),
+    definitions: Option<&Query>,
+    read: impl Fn(&Path) -> Result<String> + Sync,
+) -> Result<Vec<Violation<'a>>> {
+    let indexed: Vec<(&Rule, usize)> = rules
+        .iter()
+        .map(|rule| {
+            let i = columns
+                .iter()
+                .position(|column| column.key() == rule.column)
+                .ok_or_else(|| Error::UnknownColumn(rule.column.clone()))?;
+            if rule.per == Per::Definition
+                && matches!(
+                    columns[i],
+                    Column::Files | Column::MedianAge | Column::PercentOld
+                )
+            {
+                return Err(Error::Check(format!(
+                    "{} can't be counted per definition, in \"{}\"",
+                    rule.column, rule
+                )));
+            }
+            Ok((rule, i))
+        })
+        .collect::<Result<_>>()?;
+
+    // files can be counted more than once when they're split between authors
+    let mut by_file: BTreeMap<&Path, (&Language, Counts)> = BTreeMap::new();
+    files
+        .iter()
+        .for_each(|file| match by_file.get_mut(file.path.as_path()) {
+            Some((_, counts)) => *counts += file.counts.clone(),
+            None => {
+                by_file.insert(&file.path, (&file.lang, file.counts.clone()));
+            }
+        });
+
+    let mut violations = Vec::new();
+    for (rule, i) in indexed.iter().filter(|(rule, _)| rule.per == Per::File) {
+        by_file
+            .iter()
+            .filter(|(path, (lang, _))| rule.applies_to(lang, relative(path)))
+            .for_each(|(path, (_, counts))| {
+                let value = counts.values()[*i];
+                if value > rule.max {
+                    violations.push(Violation {
+                        rule,
+                        subject: relative(path).display().to_string(),
+                        definition: None,
+                        value,
+                    });
+                }
+            });
+    }
+
+    for (rule, i) in indexed.iter().filter(|(rule, _)| rule.per == Per::Group) {
+        let mut groups: BTreeMap<&String, Counts> = BTreeMap::new();
+        files
+            .iter()
+            .filter(|file| rule.applies_to(&file.lang, relative(&file.path)))
+            .for_each(|file| match groups.get_mut(&file.group) {
+                Some(counts) => *counts += file.counts.clone(),
+                None => {
+                    groups.insert(&file.group, file.counts.clone());
+                }
+            });
+        groups.into_iter().for_each(|(group, counts)| {
+            let value = counts.values()[*i];
+            if value > rule.max {
+                violations.push(Violation {
+                    rule,
+                    subject: group.clone(),
+                    definition: None,
+                    value,
+                });
+            }
+        });
+    }
+
+    let per_definition: Vec<(&Rule, &Column)> = indexed
+        .iter()
+        .filter(|(rule, _)| rule.per == Per::Definition)
+        .map(|(rule, i)| (*rule, &columns[*i]))
+        .collect();
+    if !per_definition.is_empty() {
+        let definitions = definitions.ok_or_else(|| {
+            Error::Check(String::from(
+                "rules per definition need a \"definition\" query which captures each definition as @definition, e.g. .tcount_queries/rust/definition.scm",
+            ))
+        })?;
+        // rules would pass without checking anything in languages which don't have the query
+        for (rule, _) in &per_definition {
+            let missing: BTreeSet<String> = by_file
+                .iter()
+                .filter(|(path, (lang, _))| {
+                    lang.get_treesitter_language().is_ok()
+                        && !definitions.langs.contains_key(*lang)
+                        && rule.applies_to(lang, relative(path))
+                })
+                .map(|(_, (lang, _))| lang.to_string())
+                .collect();
+            if !missing.is_empty() {
+                return Err(Error::Check(format!(
+                    "\"{}\" applies to {} files which have no \"definition\" query. Add one to .tcount_queries/{{language}}/definition.scm or limit the rule to other files with \"in\"",
+                    rule,
+                    missing.into_iter().collect::<Vec<_>>().join(", ")
+                )));
+            }
+        }
+        let found: Vec<(&Path, Vec<Definition>)> = by_file
+            .par_iter()
+            .filter(|(path, (lang, _))| {
+                per_definition
+                    .iter()
+                    .any(|(rule, _)| rule.applies_to(lang, relative(path)))
+                    && definitions.langs.contains_key(*lang)
+            })
+            .map(|(path, (lang, _))| {
+                let text = read(path)?;
+                let found = count_definitions(
+                    &text,
+                    lang,
+                    definitions,
+                    &per_definition,
+                    kind_patterns,
+                    queries,
+                )?;
+                Ok((*path, found))
+            })
+            .collect::<Result<_>>()?;
+        for (j, (rule, _)) in per_definition.iter().enumerate() {
+            found
+                .iter()
+                .filter(|(path, _)| rule.applies_to(by_file[path].0, relative(path)))
+                .for_each(|(path, definitions)| {
+                    definitions.iter().for_each(|definition| {
+                        if definition.values[j] > rule.max {
+                            violations.push(Violation {
+                                rule,
+                                subject: relative(path).display().to_string(),
+                                definition: Some((definition.line, definition.name.clone())),
+                                value: definition.values[j],
+                            });
+                        }
+                    });
+                });
+        }
+    }
+    Ok(violations)
+}
+
+/// Escape @s for a GitHub Actions workflow command. Properties also can't contain ":" or ",".
+fn escape_github(s: &str, property: bool) -> String {
+    let s = s
+        .replace('%', "%25")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A");
+    if property {
+        s.replace(':', "%3A").replace(',', "%2C")
+    } else {
+        s
+    }
+}
+
+/// Print @violations of @rules. Every rule is a test case in JUnit reports, whether or not it was
+/// broken.
+pub fn print(format: &Format, rules: &[Rule], violations: &[Violation]) {
+    match format {
+        Format::Human => {
+            violations.iter().for_each(|violation| {
+                println!(
+                    "{}: {} ({})",
+                    violation.location(),
+                    violation.message(),
+                    violation.rule
+                )
+            });
+            if violations.is_empty() {
+                println!("All rules passed.");
+            }
+        }
+        Format::Github => violations.iter().for_each(|violation| {
+            // annotations without a file are shown on the workflow run
+            let (properties, message) = match violation.path() {
+                Some(path) => {
+                    let mut properties = format!("file={}", escape_github(&path, true));
+                    if let Some((line, _)) = &violation.definition {
+                        properties.push_str(&format!(",line={}", line));
+                    }
+                    (properties, violation.message())
+                }
+                None => (
+                    String::new(),
+                    format!("{}: {}", violation.subject, violation.message()),
+                ),
+            };
+            println!(
+                "::error {}title={}::{}",
+                if properties.is_empty() {
+                    properties
+                } else {
+                    properties + ","
+                },
+                escape_github(&violation.rule.to_string(), true),
+                escape_github(&message, false)
+            );
+        }),
+        Format::Junit => {
+            let failures: Vec<Vec<&Violation>> = rules
+                .iter()
+                .map(|rule| {
+                    violations
+                        .iter()
+                        .filter(|violation| std::ptr::eq(violation.rule, rule))
+                        .collect()
+                })
+                .collect();
+            let nfailures = failures
+                .iter()
+                .filter(|failures| !failures.is_empty())
+                .count();
+            println!(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            println!(
+                r#"<testsuites name="tcount" tests="{}" failures="{}">"#,
+                rules.len(),
+                nfailures
+            );
+            println!(
+                r#"  <testsuite name="tcount --check" tests="{}" failures="{}">"#,
+                rules.len(),
+                nfailures
+            );
+            rules.iter().zip(failures).for_each(|(rule, failures)| {
+                let name = escape_xml(&rule.to_string());
+                if failures.is_empty() {
+                    println!(r#"    <testcase name="{}" classname="tcount"/>"#, name);
+                } else {
+                    println!(r#"    <testcase name="{}" classname="tcount">"#, name);
+                    let messages: Vec<String> = failures
+                        .iter()
+                        .map(|violation| {
+                            escape_xml(&format!(
+                                "{}: {}",
+                                violation.location(),
+                                violation.message()
+                            ))
+                        })
+                        .collect();
+                    println!(
+                        r#"      <failure message="broken {} times">{}</failure>"#,
+                        messages.len(),
+                        messages.join("\n")
+                    );
+                    println!("    </testcase>");
+                }
+            });
+            println!("  </testsuite>");
+            println!("</testsuites>");
+        }
+        Format::Checkstyle => {
+            // violations of rules per group are reported under the name of the group
+            let mut by_subject: BTreeMap<&String, Vec<&Violation>> = BTreeMap::new();
+            violations.iter().for_each(|violation| {
+                by_subject
+                    .entry(&violation.subject)
+                    .or_default()
+                    .push(violation)
+            });
+            println!(r#"<?xml version="1.0" encoding="UTF-8"?>"#);
+            println!(r#"<checkstyle version="4.3">"#);
+            by_subject.iter().for_each(|(subject, violations)| {
+                println!(r#"  <file name="{}">"#, escape_xml(subject));
+                violations.iter().for_each(|violation| {
+                    let line = match &violation.definition {
+                        Some((line, _)) => format!(r#" line="{}""#, line),
+                        None => String::new(),
+                    };
+                    println!(
+                        r#"    <error{} severity="error" message="{}" source="tcount.{}"/>"#,
+                        line,
+                        escape_xml(&violation.message()),
+                        escape_xml(&violation.rule.to_string())
+                    );
+                });
+                println!("  </file>");
+            });
+            println!("</checkstyle>");
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output;
+    use std::path::PathBuf;
+
+    fn file(group: &str, path: &str, ntokens: u64) -> FileCounts {
+        FileCounts {
+            group: group.to_string(),
+            lang: Language::from(Path::new(path)),
+            path: PathBuf::from(path),
+            counts: Counts {
+                nfiles: 1,
+                ntokens,
+                ..Counts::empty(0, 0, &[])
+            },
+        }
+    }
+
+    #[test]
+    fn parsing_rules() {
+        let rule = Rule::from_str("max tokens per file = 4000").unwrap();
+        assert_eq!(
+            (String::from("tokens"), Per::File, 4000, None),
+            (
+                rule.column.clone(),
+                rule.per,
+                rule.max,
+                rule.scope.map(|(scope, _)| scope)
+            )
+        );
+        let rule = Rule::from_str("  max query:unsafe  per group in src/**/*.rs=0").unwrap();
+        assert_eq!(
+            "max query:unsafe per group in src/**/*.rs = 0",
+            rule.to_string()
+        );
+        assert!(rule.applies_to(&Language::Go, Path::new("src/a/b.rs")));
+        assert!(!rule.applies_to(&Language::Rust, Path::new("tests/b.rs")));
+        let rule = Rule::from_str("max kind:if_expression per definition in Rust = 20").unwrap();
+        assert!(rule.applies_to(&Language::Rust, Path::new("tests/b.rs")));
+        let rule = Rule::from_str("max complexity per definition = 20").unwrap();
+        assert_eq!("query:complexity", rule.column);
+
+        assert!(Rule::from_str("max tokens per line = 1").is_err());
+        assert!(Rule::from_str("max tokens per file = lots").is_err());
+        assert!(Rule::from_str("max tokens per file in [ = 1").is_err());
+    }
+
+    #[test]
+    fn checking_files_and_groups() {
+        let rules = vec![
+            Rule::from_str("max tokens per file = 10").unwrap(),
+            Rule::from_str("max tokens per group in Go = 10").unwrap(),
+            Rule::from_str("max files per group = 2").unwrap(),
+        ];
+        let columns = output::columns(&[], &[], &[], false);
+        let files = vec![
+            file("Rust", "./a.rs", 8),
+            file("Rust", "./b.rs", 12),
+            file("Go", "./c.go", 9),
+            file("Go", "./d.go", 2),
+        ];
+        // only rules per definition read files
+        let read = |_: &Path| Ok(String::new());
+        let violations = check(&rules, &columns, &files, (&[], &[]), None, read).unwrap();
+        assert_eq!(
+            vec![
+                (
+                    String::from("b.rs"),
+                    12,
+                    String::from("12 tokens is more than the maximum of 10")
+                ),
+                (
+                    String::from("Go"),
+                    11,
+                    String::from("11 tokens is more than the maximum of 10")
+                ),
+            ],
+            violations
+                .iter()
+                .map(|violation| (
+                    violation.subject.clone(),
+                    violation.value,
+                    violation.message()
+                ))
+                .collect::<Vec<_>>()
+        );
+
+        let rules = vec![Rule::from_str("max query:nope per file = 1").unwrap()];
+        assert!(check(&rules, &columns, &files, (&[], &[]), None, read).is_err());
+        let rules = vec![Rule::from_str("max files per definition = 1").unwrap()];
+        assert!(check(&rules, &columns, &files, (&[], &[]), None, read).is_err());
+    }
+
+    #[test]
+    fn checking_definitions_without_a_query() {
+        let definitions = Query::from_str("definition@definition,name").unwrap();
+        let columns = output::columns(&[], &[], &[], false);
+        let files = vec![file("Rust", "./a.rs", 6), file("Go", "./c.go", 9)];
+        let read = |_: &Path| Ok(String::from("fn a() {}\n"));
+        let check_rule = |rule: &str| {
+            let rules = vec![Rule::from_str(rule).unwrap()];
+            check(
+                &rules,
+                &columns,
+                &files,
+                (&[], &[]),
+                Some(&definitions),
+                read,
+            )
+            .map(|violations| violations.len())
+        };
+        // Go has no definition query so nothing would be checked in c.go
+        assert!(check_rule("max tokens per definition = 1").is_err());
+        assert_eq!(
+            1,
+            check_rule("max tokens per definition in Rust = 1").unwrap()
+        );
+    }
+
+    #[test]
+    fn counting_definitions() {
+        let text = "fn a() {}\n\nfn b(x: u8) {\n    if x > 1 {}\n}\n";
+        let definitions = Query::from_str("definition@definition,name").unwrap();
+        let rule = Rule::from_str("max tokens per definition = 0").unwrap();
+        let columns = [Column::Tokens, Column::Kind(String::from("if_expression"))];
+        let found = count_definitions(
+            text,
+            &Language::Rust,
+            &definitions,
+            &[(&rule, &columns[0]), (&rule, &columns[1])],
+            &[],
+            &[],
+        )
+        .unwrap();
+        assert_eq!(
+            vec![
+                Definition {
+                    line: 1,
+                    name: String::from("a"),
+                    values: vec![6, 0],
+                },
+                Definition {
+                    line: 3,
+                    name: String::from("b"),
+                    values: vec![15, 1],
+                },
+            ],
+            found
+        );
+    }
+}
@@ -1,6 +1,12 @@
-use crate::output::Format;
+use crate::check;
+use crate::diff;
+use crate::history;
+use crate::hotspots;
+use crate::language::Mapping;
+use crate::output::{Format, Human, SortMetrics};
 use crate::query::Query;
 use regex::Regex;
+use std::fmt;
 use std::format;
 use std::path::PathBuf;
 use std::str::FromStr;
@@ -12,6 +18,19 @@ use structopt::StructOpt;
     about = "Count your code by tokens, node kinds, and patterns in the syntax tree."
 )]
 pub struct Cli {
+    #[structopt(
+        long,
+        help = "Use the options of a profile from the configuration files, e.g. [profile.ci] in .tcount.toml"
+    )]
+    pub profile: Option<String>,
+
+    #[structopt(
+        long,
+        conflicts_with = "profile",
+        help = "Don't read .tcount.toml or $XDG_CONFIG_HOME/tcount/config.toml. Without this the closest .tcount.toml in the current directory or its parents sets the default of any option"
+    )]
+    pub no_config: bool,
+
     #[structopt(
         long,
         help = "Logging level. 0 to not print errors. 1 to print IO and filesystem errors. 2 to print parsing errors. 3 to print everything else.",
@@ -49,13 +68,88 @@ pub struct Cli {
     #[structopt(
         long,
         default_value = "language",
-        help = "One of language|file|arg. \"arg\" will group by the `paths` arguments provided"
+        help = "One of language|file|arg|author. \"arg\" will group by the `paths` arguments provided. \"author\" counts each token towards the author of its line from git blame and adds the median age in days of the tokens and the percent more than a year old"
     )]
     pub groupby: GroupBy,
 
-    #[structopt(long, default_value = "table", help = "One of table|csv")]
+    #[structopt(
+        long,
+        help = "With --groupby=author, also print the bus factor of each directory, the fewest authors who wrote more than half of its tokens. Only tables show the bus factor"
+    )]
+    pub bus_factor: bool,
+
+    #[structopt(
+        long,
+        default_value = "table",
+        help = "One of table|csv|html|openmetrics|svg-treemap|tokei-json|cloc-yaml"
+    )]
     pub format: Format,
 
+    #[structopt(
+        long,
+        help = "Render the results with a template instead of --format. Templates can loop over the columns, rows, totals, and files, and format numbers with filters. See TEMPLATES.md for more information"
+    )]
+    pub template: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Append the counts for each file to a SQLite database, creating it if needed. Each invocation is recorded as a run with the time, git revision, and arguments"
+    )]
+    pub sqlite: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Save the counts for each group to a file which can be compared against later with --compare"
+    )]
+    pub save_baseline: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Compare the counts for each group with a file saved by --save-baseline. Each column shows the old count, the new count, the change, and the percent change. Only used with --format=table and --format=csv"
+    )]
+    pub compare: Option<PathBuf>,
+
+    #[structopt(
+        long,
+        help = "Count the files in a commit, tag, or branch of the git repository containing the current directory instead of the working directory. Files are read from the repository without checking them out and ignore files are used as of that revision"
+    )]
+    pub rev: Option<String>,
+
+    #[structopt(
+        long,
+        conflicts_with = "rev",
+        help = "Count the staged contents of files in the git index instead of the working directory. Files with unstaged changes are counted as they will be committed"
+    )]
+    pub staged: bool,
+
+    #[structopt(
+        long,
+        help = "Only count files which changed since the merge base of this revision (e.g. origin/main) and HEAD. Committed, staged, and unstaged changes are included along with new files which aren't ignored. With --rev or --staged only the files which changed in that revision or the index are counted"
+    )]
+    pub changed_since: Option<String>,
+
+    #[structopt(
+        long,
+        requires = "changed-since",
+        conflicts_with = "compare",
+        help = "Compare the counts of the files which changed with the same files in the merge base, like --compare"
+    )]
+    pub compare_base: bool,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["rev", "staged", "changed-since"],
+        help = "Count the files tracked in the git index instead of walking the working directory. Untracked files are never counted and ignore files aren't read. Submodules are left out unless --recurse-submodules is set"
+    )]
+    pub git_files: bool,
+
+    #[structopt(
+        long,
+        requires = "git-files",
+        help = "With --git-files, also count the files tracked in each submodule which is checked out"
+    )]
+    pub recurse_submodules: bool,
+
     #[structopt(long, help = "Don't respect gitignore and .git/info/exclude files")]
     pub no_git: bool,
 
@@ -68,6 +162,12 @@ pub struct Cli {
     #[structopt(long, help = "Count hidden files")]
     pub count_hidden: bool,
 
+    #[structopt(
+        long,
+        help = "Parse files as a language, overriding their extension, with ext:{ext}={language} or glob:{glob}={language} (e.g. ext:tpl=html or glob:include/**/*.h=cpp). Languages are named as in --list-languages and the last mapping which matches a file wins"
+    )]
+    pub map: Vec<Mapping>,
+
     #[structopt(
         long,
         help = "Whitelist of languages to parse. This overrides --blacklist and must be an exact match"
@@ -83,9 +183,78 @@ pub struct Cli {
     #[structopt(long, help = "Show a list of supported languages for parsing")]
     pub list_languages: bool,
 
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Columns to show, in order, separated by commas (e.g. group,tokens,query:comment). One of group|files|tokens|kind:{kind}|pattern:{pattern}|query:{query}|age:median|age:old"
+    )]
+    pub columns: Vec<String>,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Columns to hide, separated by commas (e.g. files)"
+    )]
+    pub hide: Vec<String>,
+
+    #[structopt(
+        long,
+        parse(try_from_str = parse_header),
+        help = "Rename a column with {column}={title} (e.g. query:comment=Comments)"
+    )]
+    pub header: Vec<(String, String)>,
+
+    #[structopt(
+        long,
+        require_equals = true,
+        help = "Make counts easier to read in tables and CSV. One of thousands|compact (e.g. 12,345 or 12.3k), defaults to thousands"
+    )]
+    pub human: Option<Option<Human>>,
+
+    #[structopt(
+        long,
+        conflicts_with_all = &["compare", "compare-base", "chart"],
+        help = "Show a row for each count and a column for each group, totals included. The rows are in the same order as --columns unless --sort-metrics is given. Every format is transposed: templates have the rows as metrics, openmetrics has a single gauge labelled by column, tokei-json and cloc-yaml are keyed by count, and svg-treemap is drawn in portrait. Subcommand tables and CSV are transposed too, but not JSON"
+    )]
+    pub transpose: bool,
+
+    #[structopt(
+        long,
+        require_equals = true,
+        requires = "transpose",
+        help = "Order of the rows of counts with --transpose. One of columns|name|total, where total puts the biggest first"
+    )]
+    pub sort_metrics: Option<SortMetrics>,
+
     #[structopt(long, help = "Show column totals. This is not affected by --top")]
     pub show_totals: bool,
 
+    #[structopt(
+        long,
+        help = "Draw a bar of the number of tokens next to each row and a bar of each language's share of the tokens above the table. Only used with --format=table"
+    )]
+    pub chart: bool,
+
+    #[structopt(
+        long,
+        default_value = "tokens",
+        help = "Column used as the area of each file in treemaps. One of files|tokens|kind:{kind}|pattern:{pattern}|query:{query}"
+    )]
+    pub treemap_column: String,
+
+    #[structopt(
+        long,
+        help = "Check the counts against a rule instead of printing them and exit with an error if any are broken, e.g. \"max tokens per file = 4000\". Rules are \"max {column} per {file|group|definition} = {n}\" and can be scoped to a language or glob with \"in\", e.g. \"max query:unsafe per group in Rust = 0\". Columns must be counted, e.g. with --query, and complexity is short for query:complexity. Definitions are the nodes captured as @definition by the \"definition\" query"
+    )]
+    pub check: Vec<check::Rule>,
+
+    #[structopt(
+        long,
+        default_value = "human",
+        help = "Format of the --check report. One of human|junit|checkstyle|github, where github prints GitHub Actions annotations"
+    )]
+    pub check_format: check::Format,
+
     #[structopt(long, help = "How many of the top results to show")]
     pub top: Option<usize>,
 
@@ -94,6 +263,162 @@ pub struct Cli {
         help = "Files and directories to parse and count."
     )]
     pub paths: Vec<PathBuf>,
+
+    #[structopt(subcommand)]
+    pub command: Option<Command>,
+
+    /// The arguments after the options from configuration files are added, without the program
+    #[structopt(skip)]
+    pub args: Vec<String>,
+}
+
+#[derive(StructOpt, Debug)]
+pub enum Command {
+    #[structopt(
+        about = "Count commits on the first-parent chain of HEAD, or --rev, and print the counts of each group over time. Options for counting go before the subcommand, e.g. tcount --groupby=file history --every=1w"
+    )]
+    History(History),
+
+    #[structopt(
+        about = "Count the tokens added and removed in each group between two revisions, e.g. tcount diff v1.0..HEAD. Files are compared by their tokens rather than their lines so changes to whitespace and formatting aren't counted"
+    )]
+    Diff(Diff),
+
+    #[structopt(
+        about = "Count the tokens added and removed in each group by a unified diff read from stdin, e.g. tcount patch < change.diff. Each file is rebuilt from the working tree before and after the diff is applied, without changing it, so patches from any tool can be sized before they're applied"
+    )]
+    Patch(Patch),
+
+    #[structopt(
+        about = "Rank files by their size and how many commits changed them, the biggest and most often changed first. Commits are read from the history of HEAD, or --rev. Sizes come from the working directory, or from --rev when it's given, and can't be counted from --staged, --changed-since, or --git-files"
+    )]
+    Hotspots(Hotspots),
+
+    #[structopt(
+        about = "Fail when a count rises above its budget in a budget file, e.g. tcount --groupby=file ratchet --columns=tokens,query:unsafe. The budget has the counts of each group, so legacy code can be held to what it is now while it's fixed. --update lowers the budgets of counts which dropped"
+    )]
+    Ratchet(Ratchet),
+}
+
+#[derive(StructOpt, Debug)]
+pub struct History {
+    #[structopt(
+        long,
+        parse(try_from_str = history::parse_date),
+        help = "Only count commits made on or after this date (e.g. 2024-01-31)"
+    )]
+    pub since: Option<i64>,
+
+    #[structopt(
+        long,
+        parse(try_from_str = history::parse_duration),
+        help = "Only count commits at least this long before the last one counted, starting from the newest. A number of days, weeks, months, or years (e.g. 1d, 2w, 1m, or 1y)"
+    )]
+    pub every: Option<i64>,
+
+    #[structopt(long, help = "Only count this many of the newest commits")]
+    pub commits: Option<usize>,
+
+    #[structopt(long, default_value = "csv", help = "One of csv|json")]
+    pub format: history::Format,
+
+    #[structopt(
+        default_value = ".",
+        help = "Files and directories to parse and count in each commit."
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Diff {
+    #[structopt(
+        help = "Revisions to compare as {from}..{to}, e.g. v1.0..HEAD. Either one defaults to HEAD"
+    )]
+    pub range: String,
+
+    #[structopt(long, default_value = "table", help = "One of table|csv|json")]
+    pub format: diff::Format,
+
+    #[structopt(
+        default_value = ".",
+        help = "Files and directories to parse and compare."
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Patch {
+    #[structopt(long, default_value = "table", help = "One of table|csv|json")]
+    pub format: diff::Format,
+
+    #[structopt(
+        default_value = ".",
+        help = "Files and directories to count changes in. Paths in the diff are relative to the current directory."
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Hotspots {
+    #[structopt(
+        long,
+        parse(try_from_str = history::parse_date),
+        help = "Only count commits made on or after this date, e.g. 2024-01-01. Every commit is counted by default"
+    )]
+    pub since: Option<i64>,
+
+    #[structopt(
+        long,
+        default_value = "tokens",
+        help = "Column used as the size of each file, e.g. tokens or query:{query} for a query which measures complexity"
+    )]
+    pub size: String,
+
+    #[structopt(long, default_value = "table", help = "One of table|csv|json")]
+    pub format: hotspots::Format,
+
+    #[structopt(default_value = ".", help = "Files and directories to rank.")]
+    pub paths: Vec<PathBuf>,
+}
+
+#[derive(StructOpt, Debug)]
+pub struct Ratchet {
+    #[structopt(
+        long,
+        default_value = ".tcount-budget.json",
+        help = "Budget file to check against, which is meant to be committed"
+    )]
+    pub budget: PathBuf,
+
+    #[structopt(
+        long,
+        use_delimiter = true,
+        help = "Columns to budget, separated by commas (e.g. tokens,query:unsafe). Defaults to the columns already in the budget, or tokens for a new budget"
+    )]
+    pub columns: Vec<String>,
+
+    #[structopt(
+        long,
+        help = "Write the budget file with the budgets of counts which dropped lowered to them. Groups and columns without a budget are added as they are and groups which no longer exist are removed. Budgets are never raised, edit the file to raise one"
+    )]
+    pub update: bool,
+
+    #[structopt(
+        default_value = ".",
+        help = "Files and directories to parse and count."
+    )]
+    pub paths: Vec<PathBuf>,
+}
+
+/// Parse the {column}={title} argument to --header
+fn parse_header(s: &str) -> Result<(String, String), String> {
+    match s.find('=') {
+        Some(i) => Ok((s[..i].to_string(), s[i + 1..].to_string())),
+        None => Err(format!(
+            "\"{}\" is not a supported argument to --header. Use {{column}}={{title}}",
+            s
+        )),
+    }
 }
 
 #[derive(Debug, PartialEq, Eq)]
@@ -124,6 +449,18 @@ pub enum GroupBy {
     Language,
     File,
     Arg,
+    Author,
+}
+
+impl fmt::Display for GroupBy {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            GroupBy::Language => write!(f, "language"),
+            GroupBy::File => write!(f, "file"),
+            GroupBy::Arg => write!(f, "arg"),
+            GroupBy::Author => write!(f, "author"),
+        }
+    }
 }
 
 impl FromStr for GroupBy {
@@ -134,8 +471,9 @@ impl FromStr for GroupBy {
             "language" => Ok(GroupBy::Language),
             "file" => Ok(GroupBy::File),
             "arg" => Ok(GroupBy::Arg),
+            "author" => Ok(GroupBy::Author),
             _ => Err(format!(
-                "\"{}\" is not a supported argument to --groupby. Use one of language|file",
+                "\"{}\" is not a supported argument to --groupby. Use one of language|file|arg|author",
                 s
             )),
         }
@@ -151,6 +489,16 @@ mod tests {
         assert_eq!(GroupBy::Language, GroupBy::from_str("language").unwrap());
         assert_eq!(GroupBy::File, GroupBy::from_str("file").unwrap());
         assert_eq!(GroupBy::Arg, GroupBy::from_str("arg").unwrap());
+        assert_eq!(GroupBy::Author, GroupBy::from_str("author").unwrap());
+    }
+
+    #[test]
+    fn header_from_str() {
+        assert_eq!(
+            Ok((String::from("query:comment"), String::from("Comments = 1"))),
+            parse_header("query:comment=Comments = 1")
+        );
+        assert!(parse_header("query:comment").is_err());
     }
 
     #[test]
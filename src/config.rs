@@ -0,0 +1,386 @@
+use crate::cli::Cli;
+use crate::error::{Error, Result};
+use std::env::var;
+use std::ffi::OsString;
+use std::fs;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use toml::value::{Table, Value};
+
+/// Name of the project configuration file, which is found in the current directory or any of its
+/// parents
+const PROJECT_FILE: &str = ".tcount.toml";
+
+/// Options which are paths, which are relative to the configuration file they're set in
+const PATH_OPTIONS: [&str; 4] = ["template", "sqlite", "save-baseline", "compare"];
+
+/// Options set by a configuration file, by the long name of their flag, along with named profiles
+/// of more options
+#[derive(Debug, Default, PartialEq)]
+pub struct Config {
+    pub options: Vec<(String, Value)>,
+    pub profiles: Vec<(String, Vec<(String, Value)>)>,
+}
+
+/// The options in @table, which must be strings, integers, booleans, or arrays of them
+fn options(table: Table) -> std::result::Result<Vec<(String, Value)>, String> {
+    table
+        .into_iter()
+        .map(|(key, value)| {
+            let scalar = |value: &Value| {
+                matches!(
+                    value,
+                    Value::String(_) | Value::Integer(_) | Value::Boolean(_)
+                )
+            };
+            let valid = match &value {
+                Value::Array(values) => values.iter().all(scalar),
+                value => scalar(value),
+            };
+            if valid {
+                Ok((key, value))
+            } else {
+                Err(format!(
+                    "{} must be a string, integer, boolean, or an array of them",
+                    key
+                ))
+            }
+        })
+        .collect()
+}
+
+impl Config {
+    /// Parse the text of a configuration file
+    pub fn parse(text: &str) -> std::result::Result<Config, String> {
+        let mut table = match text.parse::<Value>().map_err(|err| err.to_string())? {
+            Value::Table(table) => table,
+            _ => Table::new(),
+        };
+        let profiles = match table.remove("profile") {
+            Some(Value::Table(profiles)) => profiles
+                .into_iter()
+                .map(|(name, profile)| match profile {
+                    Value::Table(profile) => Ok((name, options(profile)?)),
+                    _ => Err(format!("profile.{} must be a table", name)),
+                })
+                .collect::<std::result::Result<_, _>>()?,
+            Some(_) => return Err(String::from("profile must be a table, e.g. [profile.ci]")),
+            None => Vec::new(),
+        };
+        if let Some((key, _)) = table.iter().find(|(_, value)| value.is_table()) {
+            return Err(format!(
+                "{} isn't an option, the only tables are profiles, e.g. [profile.ci]",
+                key
+            ));
+        }
+        Ok(Config {
+            options: options(table)?,
+            profiles,
+        })
+    }
+
+    /// Make the relative paths of path options, in the options and every profile, relative to
+    /// @dir instead
+    fn relative_to(mut self, dir: &Path) -> Config {
+        self.options
+            .iter_mut()
+            .chain(self.profiles.iter_mut().flat_map(|(_, options)| options))
+            .filter(|(key, _)| PATH_OPTIONS.contains(&key.replace('_', "-").as_str()))
+            .for_each(|(_, value)| {
+                if let Value::String(path) = value {
+                    if Path::new(path).is_relative() {
+                        *path = dir.join(&path).display().to_string();
+                    }
+                }
+            });
+        self
+    }
+
+    fn load(path: &Path) -> Result<Config> {
+        let text = fs::read_to_string(path)?;
+        Config::parse(&text)
+            .map(|config| config.relative_to(path.parent().unwrap_or_else(|| Path::new(""))))
+            .map_err(|err| Error::Config(format!("{}: {}", path.display(), err)))
+    }
+}
+
+/// The configuration files which are used, from the lowest priority to the highest. The user's
+/// file is $XDG_CONFIG_HOME/tcount/config.toml (defaults to $HOME/.config/tcount/config.toml) and
+/// the project's file is the closest .tcount.toml in the current directory or its parents.
+fn files() -> Vec<PathBuf> {
+    let user = match var("XDG_CONFIG_HOME") {
+        Ok(dir) if !dir.is_empty() => Some(PathBuf::from(dir)),
+        _ => var("HOME")
+            .ok()
+            .map(|home| PathBuf::from(home).join(".config")),
+    }
+    .map(|dir| dir.join("tcount").join("config.toml"));
+    let project = std::env::current_dir().ok().and_then(|cwd| {
+        cwd.ancestors()
+            .map(|dir| dir.join(PROJECT_FILE))
+            .find(|path| path.is_file())
+    });
+    user.into_iter()
+        .filter(|path| path.is_file())
+        .chain(project)
+        .collect()
+}
+
+/// Add @more to @options, replacing the options which are already set
+fn merge(options: &mut Vec<(String, Value)>, more: &[(String, Value)]) {
+    more.iter().for_each(|(key, value)| {
+        match options
+            .iter_mut()
+            .find(|(k, _)| k.replace('_', "-") == key.replace('_', "-"))
+        {
+            Some((_, v)) => *v = value.clone(),
+            None => options.push((key.clone(), value.clone())),
+        }
+    });
+}
+
+/// Flags which set each option in @options, except for the ones @is_set says were given on the
+/// command line. false turns off a flag which was set before it.
+fn flags(options: &[(String, Value)], is_set: impl Fn(&str) -> bool) -> Vec<OsString> {
+    let mut flags: Vec<(String, String)> = Vec::new();
+    options
+        .iter()
+        .map(|(key, value)| (key.replace('_', "-"), value))
+        .filter(|(name, _)| !is_set(name))
+        .for_each(|(name, value)| {
+            let values = match value {
+                Value::Array(values) => values.clone(),
+                value => vec![value.clone()],
+            };
+            values.into_iter().for_each(|value| match value {
+                Value::Boolean(false) => flags.retain(|(flag, _)| *flag != name),
+                Value::Boolean(true) => flags.push((name.clone(), format!("--{}", name))),
+                Value::String(s) => flags.push((name.clone(), format!("--{}={}", name, s))),
+                Value::Integer(n) => flags.push((name.clone(), format!("--{}={}", name, n))),
+                // anything else is rejected when parsing
+                _ => {}
+            });
+        });
+    flags
+        .into_iter()
+        .map(|(_, flag)| OsString::from(flag))
+        .collect()
+}
+
+/// Parse the command line @args, with the options which aren't given filled in from the
+/// configuration files and --profile. Options on the command line always win over the ones in
+/// configuration files, and profiles win over the options outside of them.
+pub fn parse_args(args: Vec<OsString>) -> Result<Cli> {
+    let app = Cli::clap();
+    // invalid arguments and --help exit the same way they do without a configuration file
+    let matches = app
+        .clone()
+        .get_matches_from_safe(&args)
+        .unwrap_or_else(|err| err.exit());
+    let mut cli = Cli::from_clap(&matches);
+    if cli.no_config {
+        cli.args = strings(&args);
+        return Ok(cli);
+    }
+    let paths = files();
+    let configs = paths
+        .iter()
+        .map(|path| Config::load(path))
+        .collect::<Result<Vec<_>>>()?;
+
+    let mut options = Vec::new();
+    configs
+        .iter()
+        .for_each(|config| merge(&mut options, &config.options));
+    if let Some(name) = &cli.profile {
+        let profiles: Vec<&Vec<(String, Value)>> = configs
+            .iter()
+            .flat_map(|config| config.profiles.iter())
+            .filter(|(profile, _)| profile == name)
+            .map(|(_, options)| options)
+            .collect();
+        if profiles.is_empty() {
+            return Err(Error::Config(format!(
+                "there's no profile named {} in {}",
+                name,
+                if paths.is_empty() {
+                    String::from("any configuration file")
+                } else {
+                    paths
+                        .iter()
+                        .map(|path| path.display().to_string())
+                        .collect::<Vec<_>>()
+                        .join(" or ")
+                }
+            )));
+        }
+        profiles
+            .into_iter()
+            .for_each(|profile| merge(&mut options, profile));
+    }
+
+    let mut args = args.into_iter();
+    let all: Vec<OsString> = args
+        .next()
+        .into_iter()
+        .chain(flags(&options, |name| matches.occurrences_of(name) > 0))
+        .chain(args)
+        .collect();
+    // the command line was valid on its own so any errors are from the configuration files
+    let matches = app.get_matches_from_safe(&all).map_err(|err| {
+        Error::Config(format!(
+            "{}: {}",
+            paths
+                .iter()
+                .map(|path| path.display().to_string())
+                .collect::<Vec<_>>()
+                .join(", "),
+            err.message
+                .lines()
+                .next()
+                .unwrap_or_default()
+                .trim_start_matches("error: ")
+        ))
+    })?;
+    let mut cli = Cli::from_clap(&matches);
+    cli.args = strings(&all);
+    Ok(cli)
+}
+
+/// @args as text without the program
+fn strings(args: &[OsString]) -> Vec<String> {
+    args.iter()
+        .skip(1)
+        .map(|arg| arg.to_string_lossy().into_owned())
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parsing_config() {
+        let text = r#"
+# defaults for everyone
+kind = ["line_comment", 'block_comment'] # trailing comment
+groupby = "file"
+top = 10
+no_git = true
+query = [
+    "comment",
+    "keyword@ifelse,repeat",
+]
+
+[profile.ci]
+format = "json"
+check = ["max tokens per file = 4000", "say \"hi\"!"]
+"#;
+        let strings = |values: &[&str]| {
+            Value::Array(values.iter().map(|&value| Value::from(value)).collect())
+        };
+        assert_eq!(
+            Ok(Config {
+                options: vec![
+                    (String::from("groupby"), Value::from("file")),
+                    (
+                        String::from("kind"),
+                        strings(&["line_comment", "block_comment"])
+                    ),
+                    (String::from("no_git"), Value::Boolean(true)),
+                    (
+                        String::from("query"),
+                        strings(&["comment", "keyword@ifelse,repeat"])
+                    ),
+                    (String::from("top"), Value::Integer(10)),
+                ],
+                profiles: vec![(
+                    String::from("ci"),
+                    vec![
+                        (
+                            String::from("check"),
+                            strings(&["max tokens per file = 4000", "say \"hi\"!"])
+                        ),
+                        (String::from("format"), Value::from("json")),
+                    ]
+                )],
+            }),
+            Config::parse(text)
+        );
+
+        assert_eq!(
+            Err(String::from(
+                "other isn't an option, the only tables are profiles, e.g. [profile.ci]"
+            )),
+            Config::parse("[other]\n")
+        );
+        assert_eq!(
+            Err(String::from(
+                "kind must be a string, integer, boolean, or an array of them"
+            )),
+            Config::parse("kind = [[\"comment\"]]\n")
+        );
+        assert!(Config::parse("top = 1\ntop = 2\n").is_err());
+        assert!(Config::parse("top = 1.5\n").is_err());
+        assert!(Config::parse("profile = 1\n").is_err());
+        assert!(Config::parse("kind = \"open\n").is_err());
+        assert!(Config::parse("top = lots\n").is_err());
+    }
+
+    #[test]
+    fn flags_from_options() {
+        let options = vec![
+            (
+                String::from("kind_pattern"),
+                Value::Array(vec![
+                    Value::String(String::from(".*comment")),
+                    Value::String(String::from("string")),
+                ]),
+            ),
+            (String::from("top"), Value::Integer(3)),
+            (String::from("no-git"), Value::Boolean(true)),
+            (String::from("chart"), Value::Boolean(false)),
+            (String::from("show_totals"), Value::Boolean(true)),
+            (String::from("show-totals"), Value::Boolean(false)),
+        ];
+        assert_eq!(
+            vec![
+                OsString::from("--kind-pattern=.*comment"),
+                OsString::from("--kind-pattern=string"),
+                OsString::from("--no-git"),
+            ],
+            flags(&options, |name| name == "top")
+        );
+    }
+
+    #[test]
+    fn paths_relative_to_the_config() {
+        let config = Config::parse(
+            r#"
+template = "table.tmpl"
+sqlite = "/tmp/counts.db"
+kind = "table.tmpl"
+
+[profile.ci]
+save_baseline = "ci/baseline.json"
+"#,
+        )
+        .unwrap()
+        .relative_to(Path::new("project"));
+        assert_eq!(
+            vec![
+                (String::from("kind"), Value::from("table.tmpl")),
+                (String::from("sqlite"), Value::from("/tmp/counts.db")),
+                (String::from("template"), Value::from("project/table.tmpl")),
+            ],
+            config.options
+        );
+        assert_eq!(
+            vec![(
+                String::from("save_baseline"),
+                Value::from("project/ci/baseline.json")
+            )],
+            config.profiles[0].1
+        );
+    }
+}
@@ -3,12 +3,112 @@ use crate::language::Language;
 use crate::query::{Query, QueryKind};
 use crate::tree::TreeIterator;
 use regex::Regex;
-use std::collections::HashMap;
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
-use std::ops::AddAssign;
-use std::path::Path;
+use std::ops::{AddAssign, Range};
+use std::path::{Path, PathBuf};
 use tree_sitter::{Node, Parser, QueryCursor};
 
+/// Lines contains the number of lines of code, comments, and blank lines. A line is only a comment
+/// if everything on it is inside of comment nodes in the syntax tree.
+#[derive(Debug, Default, Eq, PartialEq, Clone, Copy)]
+pub struct Lines {
+    pub code: u64,
+    pub comments: u64,
+    pub blanks: u64,
+}
+
+impl Lines {
+    /// Classify each line of @text using the byte ranges of the comments in @text which are ordered
+    /// by where they start. The lines of each of @nowners are counted separately, where @owner
+    /// gives the owner of each line by its index.
+    fn by_owner(
+        text: &str,
+        comments: &[Range<usize>],
+        owner: impl Fn(usize) -> usize,
+        nowners: usize,
+    ) -> Vec<Lines> {
+        let mut lines = vec![Lines::default(); nowners];
+        let mut comments = comments.iter().peekable();
+        let mut offset = 0;
+        text.split_inclusive('\n')
+            .enumerate()
+            .for_each(|(row, line)| {
+                let start = offset;
+                offset += line.len();
+                let lines = &mut lines[owner(row)];
+                if line.trim().is_empty() {
+                    lines.blanks += 1;
+                    return;
+                }
+                let is_comment =
+                    line.char_indices()
+                        .filter(|(_, c)| !c.is_whitespace())
+                        .all(|(i, _)| {
+                            let pos = start + i;
+                            while comments.next_if(|range| range.end <= pos).is_some() {}
+                            matches!(comments.peek(), Some(range) if range.start <= pos)
+                        });
+                if is_comment {
+                    lines.comments += 1;
+                } else {
+                    lines.code += 1;
+                }
+            });
+        lines
+    }
+}
+
+impl AddAssign for Lines {
+    fn add_assign(&mut self, other: Self) {
+        self.code += other.code;
+        self.comments += other.comments;
+        self.blanks += other.blanks;
+    }
+}
+
+/// Ages is how many tokens were written each number of days ago, for the age columns of
+/// --groupby=author
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Ages(BTreeMap<u64, u64>);
+
+impl Ages {
+    /// Add @ntokens written @days ago
+    pub fn add(&mut self, days: u64, ntokens: u64) {
+        *self.0.entry(days).or_insert(0) += ntokens;
+    }
+
+    /// The median age of the tokens in days, or 0 without any tokens
+    pub fn median(&self) -> u64 {
+        let total: u64 = self.0.values().sum();
+        let mut seen = 0;
+        self.0
+            .iter()
+            .find(|(_, ntokens)| {
+                seen += *ntokens;
+                seen * 2 >= total
+            })
+            .map_or(0, |(days, _)| *days)
+    }
+
+    /// The percent of tokens which are more than a year old
+    pub fn percent_old(&self) -> u64 {
+        let total: u64 = self.0.values().sum();
+        let old: u64 = self.0.range(366..).map(|(_, ntokens)| ntokens).sum();
+        (old * 100).checked_div(total).unwrap_or(0)
+    }
+}
+
+impl AddAssign<&Ages> for Ages {
+    fn add_assign(&mut self, other: &Ages) {
+        other
+            .0
+            .iter()
+            .for_each(|(days, ntokens)| self.add(*days, *ntokens));
+    }
+}
+
 /// Counts contains the cumulative totals for the how many files, number of tokens, number of nodes
 /// matching each kind specified by --kind, and number of matches for each query specified by
 /// --query. @nqueries is ordered first by the queries arguments and then by captures.
@@ -16,9 +116,12 @@ use tree_sitter::{Node, Parser, QueryCursor};
 pub struct Counts {
     pub nfiles: u64,
     pub ntokens: u64,
+    pub lines: Lines,
     pub nkinds: Vec<u64>,
     pub nkind_patterns: Vec<u64>,
     pub nqueries: Vec<u64>,
+    /// Ages of the tokens when they are counted by who wrote them
+    pub ages: Option<Ages>,
 }
 
 impl Counts {
@@ -27,12 +130,30 @@ impl Counts {
         Counts {
             nfiles: 0,
             ntokens: 0,
+            lines: Lines::default(),
             nkinds: vec![0; nkinds],
             nkind_patterns: vec![0; nkind_patterns],
             nqueries: Self::nqueries(queries, HashMap::new(), HashMap::new()),
+            ages: None,
         }
     }
 
+    /// Flatten the counts into a single row in the same order as the columns are displayed
+    pub fn values(&self) -> Vec<u64> {
+        [self.nfiles, self.ntokens]
+            .iter()
+            .chain(self.nkinds.iter())
+            .chain(self.nkind_patterns.iter())
+            .chain(self.nqueries.iter())
+            .copied()
+            .chain(
+                self.ages
+                    .iter()
+                    .flat_map(|ages| [ages.median(), ages.percent_old()]),
+            )
+            .collect()
+    }
+
     /// Convert @nmatches and @ncaptures into a deterministically ordered vector
     fn nqueries(
         queries: &[Query],
@@ -55,6 +176,16 @@ impl Counts {
     }
 }
 
+/// FileCounts are the Counts for a single file along with the group it is counted towards based on
+/// --groupby.
+#[derive(Debug, Clone)]
+pub struct FileCounts {
+    pub group: String,
+    pub lang: Language,
+    pub path: PathBuf,
+    pub counts: Counts,
+}
+
 impl AddAssign for Counts {
     fn add_assign(&mut self, other: Self) {
         #[inline(always)]
@@ -64,9 +195,15 @@ impl AddAssign for Counts {
         }
         self.nfiles += other.nfiles;
         self.ntokens += other.ntokens;
+        self.lines += other.lines;
         add(&mut self.nkinds, &other.nkinds);
         add(&mut self.nkind_patterns, &other.nkind_patterns);
         add(&mut self.nqueries, &other.nqueries);
+        match (&mut self.ages, &other.ages) {
+            (Some(ages), Some(other)) => *ages += other,
+            (None, Some(other)) => self.ages = Some(other.clone()),
+            _ => {}
+        }
     }
 }
 
@@ -79,26 +216,100 @@ impl Counts {
         kind_patterns: &Vec<Regex>,
         queries: &[Query],
     ) -> Result<Self> {
+        let text = match lang.get_treesitter_language() {
+            Ok(_) => fs::read_to_string(path.as_ref())?,
+            // unsupported languages aren't parsed so there's no need to read them
+            Err(_) => String::new(),
+        };
+        Self::from_text(path, &text, lang, kinds, kind_patterns, queries)
+    }
+
+    /// Try to count @text, the contents of @path, for the specified arguments
+    pub fn from_text(
+        path: impl AsRef<Path>,
+        text: &str,
+        lang: &Language,
+        kinds: &[String],
+        kind_patterns: &[Regex],
+        queries: &[Query],
+    ) -> Result<Self> {
+        Self::by_owner(path, text, lang, kinds, kind_patterns, queries, None)
+            .map(|mut counts| counts.remove(0))
+    }
+
+    /// Try to count @text, the contents of @path, for the specified arguments with the counts of
+    /// each owner of the lines in @text kept separately. @owners has the owner of each line, by
+    /// their index, and how many days ago they wrote it. Tokens, nodes, and query matches are
+    /// counted towards the owner of the line they start on and each owner gets the ages of their
+    /// tokens. The file itself is counted towards the owner of the most lines.
+    pub fn from_text_by_owner(
+        path: impl AsRef<Path>,
+        text: &str,
+        lang: &Language,
+        (kinds, kind_patterns, queries): (&[String], &[Regex], &[Query]),
+        owners: &[(usize, u64)],
+    ) -> Result<Vec<Self>> {
+        Self::by_owner(
+            path,
+            text,
+            lang,
+            kinds,
+            kind_patterns,
+            queries,
+            Some(owners),
+        )
+    }
+
+    /// The counts of each owner in @owners, or a single owner of every line without @owners. See
+    /// `Counts::from_text_by_owner`.
+    fn by_owner(
+        path: impl AsRef<Path>,
+        text: &str,
+        lang: &Language,
+        kinds: &[String],
+        kind_patterns: &[Regex],
+        queries: &[Query],
+        owners: Option<&[(usize, u64)]>,
+    ) -> Result<Vec<Self>> {
+        let nowners = owners
+            .and_then(|owners| owners.iter().map(|(owner, _)| owner + 1).max())
+            .unwrap_or(1);
+        // the file is only counted once, for the owner of the most lines, so the number of files
+        // is the same however they're grouped
+        let main_owner = owners.map_or(0, |owners| {
+            let mut nlines = vec![0; nowners];
+            owners.iter().for_each(|(owner, _)| nlines[*owner] += 1);
+            (0..nowners)
+                .max_by_key(|owner| (nlines[*owner], Reverse(*owner)))
+                .unwrap_or(0)
+        });
+        // rows past the last line, like the end of a file after its final newline, have no owner
+        // of their own so they belong to the file's
+        let owner = |row: usize| match owners {
+            Some(owners) => owners.get(row).map_or(main_owner, |(owner, _)| *owner),
+            None => 0,
+        };
+        let empty = Counts {
+            ages: owners.map(|_| Ages::default()),
+            ..Counts::empty(kinds.len(), kind_patterns.len(), queries)
+        };
+        let mut counts = vec![empty; nowners];
+
         let ts_lang = {
             match lang.get_treesitter_language() {
                 Ok(ts_lang) => ts_lang,
                 Err(_) => {
                     // Unsupported language gets an *empty* Counts struct
-                    return Ok(Counts {
-                        nfiles: 1,
-                        ..Counts::empty(kinds.len(), kind_patterns.len(), queries)
-                    });
+                    counts[main_owner].nfiles = 1;
+                    return Ok(counts);
                 }
             }
         };
 
-        let mut ntokens = 0;
-        let mut nkinds = vec![0; kinds.len()];
-        let mut nkind_patterns = vec![0; kind_patterns.len()];
-        let mut nmatch_queries = HashMap::new();
-        let mut ncapture_queries = HashMap::new();
+        let mut nmatch_queries = vec![HashMap::new(); nowners];
+        let mut ncapture_queries = vec![HashMap::new(); nowners];
+        let mut comments = Vec::new();
 
-        let text = fs::read_to_string(path.as_ref())?;
         let mut parser = Parser::new();
         parser
             .set_language(ts_lang)
@@ -106,18 +317,25 @@ impl Counts {
 
         let mut qcursor = QueryCursor::new();
         let text_callback = |n: Node| &text[n.byte_range()]; // weird but needed argument for queries
-        match parser.parse(&text, None) {
+        match parser.parse(text, None) {
             Some(tree) => {
                 queries.iter().for_each(|query| {
                     if let Some(ts_query) = query.langs.get(lang) {
                         match &query.kind {
                             QueryKind::Match => {
-                                nmatch_queries.insert(
-                                    &query.name,
-                                    qcursor
-                                        .matches(ts_query, tree.root_node(), text_callback)
-                                        .count() as u64,
-                                );
+                                qcursor
+                                    .matches(ts_query, tree.root_node(), text_callback)
+                                    .for_each(|qmatch| {
+                                        let row = qmatch
+                                            .captures
+                                            .iter()
+                                            .map(|capture| capture.node.start_position().row)
+                                            .min()
+                                            .unwrap_or(0);
+                                        *nmatch_queries[owner(row)]
+                                            .entry(&query.name)
+                                            .or_insert(0) += 1;
+                                    });
                             }
                             QueryKind::Captures(_) => {
                                 // We should only be finding capture names that were provided as
@@ -128,7 +346,8 @@ impl Counts {
                                     .captures(ts_query, tree.root_node(), text_callback)
                                     .for_each(|(qmatch, _)| {
                                         qmatch.captures.iter().for_each(|capture| {
-                                            *ncapture_queries
+                                            let row = capture.node.start_position().row;
+                                            *ncapture_queries[owner(row)]
                                                 .entry((
                                                     &query.name,
                                                     &capture_names[capture.index as usize],
@@ -143,37 +362,51 @@ impl Counts {
 
                 TreeIterator::new(&tree).for_each(|node| {
                     if !node.is_missing() {
+                        let row = node.start_position().row;
+                        let counts = &mut counts[owner(row)];
                         // count each terminal node which is the closest we can get to counting
                         // tokens. For some tokens this is a bit misleading since they can have
                         // children (e.g. string_literal in rust), but it's the closest we can
                         // achieve with tree-sitter.
                         if node.child_count() == 0 && !node.is_extra() && node.parent().is_some() {
-                            ntokens += 1;
+                            counts.ntokens += 1;
+                            if let (Some(ages), Some((_, age))) =
+                                (&mut counts.ages, owners.and_then(|owners| owners.get(row)))
+                            {
+                                ages.add(*age, 1);
+                            }
+                        }
+
+                        if node.kind().contains("comment") {
+                            comments.push(node.byte_range());
                         }
 
                         // count each --kinds that match the current nodes kind
                         kinds.iter().enumerate().for_each(|(i, kind)| {
                             if kind == node.kind() {
-                                nkinds[i] += 1;
+                                counts.nkinds[i] += 1;
                             }
                         });
 
                         // count each --kind_patterns that match the current nodes kind
                         kind_patterns.iter().enumerate().for_each(|(i, kind)| {
                             if kind.is_match(node.kind()) {
-                                nkind_patterns[i] += 1;
+                                counts.nkind_patterns[i] += 1;
                             }
                         });
                     }
                 });
-                let nqueries = Counts::nqueries(queries, nmatch_queries, ncapture_queries);
-                Ok(Counts {
-                    nfiles: 1,
-                    ntokens,
-                    nkinds,
-                    nkind_patterns,
-                    nqueries,
-                })
+                let lines = Lines::by_owner(text, &comments, owner, nowners);
+                counts
+                    .iter_mut()
+                    .zip(lines)
+                    .zip(nmatch_queries.into_iter().zip(ncapture_queries))
+                    .for_each(|((counts, lines), (nmatches, ncaptures))| {
+                        counts.lines = lines;
+                        counts.nqueries = Counts::nqueries(queries, nmatches, ncaptures);
+                    });
+                counts[main_owner].nfiles = 1;
+                Ok(counts)
             }
             None => Err(Error::Parser(path.as_ref().to_path_buf())),
         }
@@ -185,6 +418,12 @@ mod tests {
     use super::*;
     use std::str::FromStr;
 
+    const RUST1_LINES: Lines = Lines {
+        code: 5,
+        comments: 6,
+        blanks: 1,
+    };
+
     fn queries_with_captures() -> Vec<Query> {
         vec![
             Query::from_str("comment").unwrap(),
@@ -213,9 +452,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 0,
+            lines: Lines::default(),
             nkinds: Vec::new(),
             nkind_patterns: Vec::new(),
             nqueries: Vec::new(),
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -233,9 +474,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 0,
+            lines: Lines::default(),
             nkinds: Vec::new(),
             nkind_patterns: Vec::new(),
             nqueries: vec![0, 0],
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -253,9 +496,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 33,
+            lines: RUST1_LINES,
             nkinds: Vec::new(),
             nkind_patterns: Vec::new(),
             nqueries: Vec::new(),
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -273,9 +518,15 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 30,
+            lines: Lines {
+                code: 4,
+                comments: 6,
+                blanks: 2,
+            },
             nkinds: Vec::new(),
             nkind_patterns: Vec::new(),
             nqueries: Vec::new(),
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -293,9 +544,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 33,
+            lines: RUST1_LINES,
             nkinds: vec![8, 3],
             nkind_patterns: Vec::new(),
             nqueries: Vec::new(),
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -313,9 +566,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 33,
+            lines: RUST1_LINES,
             nkinds: vec![1, 3],
             nkind_patterns: vec![4],
             nqueries: Vec::new(),
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -333,9 +588,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 33,
+            lines: RUST1_LINES,
             nkinds: Vec::new(),
             nkind_patterns: Vec::new(),
             nqueries: vec![4, 2],
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -353,9 +610,11 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 33,
+            lines: RUST1_LINES,
             nkinds: vec![1, 3],
             nkind_patterns: vec![4],
             nqueries: vec![4, 2],
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
@@ -389,38 +648,151 @@ mod tests {
         let expected = Counts {
             nfiles: 1,
             ntokens: 73,
+            lines: Lines {
+                code: 15,
+                comments: 6,
+                blanks: 2,
+            },
             nkinds: vec![],
             nkind_patterns: vec![],
             nqueries: vec![4, 4, 3, 2],
+            ages: None,
         };
         assert_eq!(expected, got.unwrap());
     }
 
+    #[test]
+    fn lines_with_code_and_comments() {
+        let text = "a // b\n  // c\n\n/* d */ /* e */\n \t\nf";
+        let comments = [2..6, 9..13, 15..22, 23..30];
+        let expected = Lines {
+            code: 2,
+            comments: 2,
+            blanks: 2,
+        };
+        assert_eq!(vec![expected], Lines::by_owner(text, &comments, |_| 0, 1));
+        assert_eq!(vec![Lines::default()], Lines::by_owner("", &[], |_| 0, 1));
+    }
+
+    #[test]
+    fn counts_values() {
+        let counts = Counts {
+            nfiles: 3,
+            ntokens: 21,
+            lines: Lines::default(),
+            nkinds: vec![28, 29],
+            nkind_patterns: vec![5],
+            nqueries: vec![0, 44, 55],
+            ages: None,
+        };
+        assert_eq!(vec![3, 21, 28, 29, 5, 0, 44, 55], counts.values());
+    }
+
     #[test]
     fn add_assign_counts() {
         let mut c1 = Counts {
             nfiles: 30,
             ntokens: 21,
+            lines: Lines {
+                code: 1,
+                comments: 2,
+                blanks: 3,
+            },
             nkinds: vec![28, 28],
             nkind_patterns: vec![29, 20, 2],
             nqueries: vec![0, 44, 55],
+            ages: None,
         };
         let c2 = Counts {
             nfiles: 19,
             ntokens: 31,
+            lines: Lines {
+                code: 4,
+                comments: 5,
+                blanks: 6,
+            },
             nkinds: vec![5, 9],
             nkind_patterns: vec![6, 10, 14],
             nqueries: vec![33, 44],
+            ages: None,
         };
 
         c1 += c2;
         let expected = Counts {
             nfiles: 49,
             ntokens: 52,
+            lines: Lines {
+                code: 5,
+                comments: 7,
+                blanks: 9,
+            },
             nkinds: vec![33, 37],
             nkind_patterns: vec![35, 30, 16],
             nqueries: vec![33, 88, 55],
+            ages: None,
         };
         assert_eq!(expected, c1);
     }
+
+    #[test]
+    fn ages_of_tokens() {
+        let mut ages = Ages::default();
+        assert_eq!((0, 0), (ages.median(), ages.percent_old()));
+        ages.add(10, 3);
+        ages.add(400, 1);
+        assert_eq!((10, 25), (ages.median(), ages.percent_old()));
+        let mut more = Ages::default();
+        more.add(500, 4);
+        ages += &more;
+        assert_eq!((400, 62), (ages.median(), ages.percent_old()));
+    }
+
+    #[test]
+    fn counting_by_owner() {
+        let text = "fn a() {}\n// b\nfn b() {}\n";
+        let counts = Counts::from_text_by_owner(
+            "lib.rs",
+            text,
+            &Language::Rust,
+            (&[String::from("line_comment")], &[], &[]),
+            &[(1, 400), (0, 2), (0, 2)],
+        )
+        .unwrap();
+        let ages = |days, ntokens| {
+            let mut ages = Ages::default();
+            ages.add(days, ntokens);
+            Some(ages)
+        };
+        assert_eq!(
+            vec![
+                Counts {
+                    nfiles: 1,
+                    ntokens: 6,
+                    lines: Lines {
+                        code: 1,
+                        comments: 1,
+                        blanks: 0,
+                    },
+                    nkinds: vec![1],
+                    nkind_patterns: vec![],
+                    nqueries: vec![],
+                    ages: ages(2, 6),
+                },
+                Counts {
+                    nfiles: 0,
+                    ntokens: 6,
+                    lines: Lines {
+                        code: 1,
+                        comments: 0,
+                        blanks: 0,
+                    },
+                    nkinds: vec![0],
+                    nkind_patterns: vec![],
+                    nqueries: vec![],
+                    ages: ages(400, 6),
+                },
+            ],
+            counts
+        );
+    }
 }
@@ -0,0 +1,333 @@
+use crate::error::{Error, Result};
+use crate::language::Language;
+use crate::output::{self, Column, TableStyle};
+use crate::tree::TreeIterator;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::format;
+use std::ops::AddAssign;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use tree_sitter::Parser;
+
+/// Limit on the work done to find the smallest set of changes between two files, roughly the number
+/// of tokens compared. Beyond this the changes are approximated from the tokens in each file.
+const MAX_WORK: usize = 100_000_000;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "\"{}\" is not supported by diff or patch. Use one of table|csv|json",
+                s
+            )),
+        }
+    }
+}
+
+/// Split @range, e.g. v1.0..HEAD, into the revisions to compare from and to. A missing revision
+/// defaults to HEAD.
+pub fn parse_range(range: &str) -> (&str, &str) {
+    let (from, to) = range.split_once("..").unwrap_or((range, ""));
+    let or_head = |rev| match rev {
+        "" => "HEAD",
+        rev => rev,
+    };
+    (or_head(from), or_head(to))
+}
+
+/// Churn is the number of files which changed and the number of tokens added to and removed from
+/// them
+#[derive(Debug, Default, Eq, PartialEq, Clone)]
+pub struct Churn {
+    pub nfiles: u64,
+    pub added: u64,
+    pub removed: u64,
+    /// The change in each count after files and tokens, i.e. each --kind, --kind-pattern, and
+    /// --query column. Only `tcount patch` counts these.
+    pub deltas: Vec<i64>,
+}
+
+impl AddAssign<&Churn> for Churn {
+    fn add_assign(&mut self, other: &Churn) {
+        self.nfiles += other.nfiles;
+        self.added += other.added;
+        self.removed += other.removed;
+        if self.deltas.len() < other.deltas.len() {
+            self.deltas.resize(other.deltas.len(), 0);
+        }
+        self.deltas
+            .iter_mut()
+            .zip(&other.deltas)
+            .for_each(|(delta, other)| *delta += other);
+    }
+}
+
+/// FileChurn is the Churn of a single file along with the group it is counted towards based on
+/// --groupby.
+#[derive(Debug, Clone)]
+pub struct FileChurn {
+    pub group: String,
+    pub lang: Language,
+    pub path: PathBuf,
+    pub churn: Churn,
+}
+
+/// The leaf tokens of @text, the contents of @path, as the kind of node and its text. These are
+/// the same nodes which are counted as tokens so whitespace and comments aren't included.
+pub fn tokens<'a>(path: &Path, text: &'a str, lang: &Language) -> Result<Vec<(u16, &'a str)>> {
+    let ts_lang = match lang.get_treesitter_language() {
+        Ok(ts_lang) => ts_lang,
+        // files in unsupported languages have no tokens
+        Err(_) => return Ok(Vec::new()),
+    };
+    let mut parser = Parser::new();
+    parser
+        .set_language(ts_lang)
+        .expect("Unexpected internal error setting parser language");
+    let tree = parser
+        .parse(text, None)
+        .ok_or_else(|| Error::Parser(path.to_path_buf()))?;
+    Ok(TreeIterator::new(&tree)
+        .filter(|node| {
+            !node.is_missing()
+                && node.child_count() == 0
+                && !node.is_extra()
+                && node.parent().is_some()
+        })
+        .map(|node| (node.kind_id(), &text[node.byte_range()]))
+        .collect())
+}
+
+/// The Churn from the tokens in @old to the tokens in @new. The added and removed tokens are the
+/// fewest needed to turn @old into @new.
+pub fn churn<T: PartialEq + Eq + std::hash::Hash>(old: &[T], new: &[T]) -> Churn {
+    // skipping the tokens which are the same at the start and end is much faster than comparing
+    // them since most changes are small
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let (old, new) = (&old[prefix..], &new[prefix..]);
+    let suffix = old
+        .iter()
+        .rev()
+        .zip(new.iter().rev())
+        .take_while(|(a, b)| a == b)
+        .count();
+    let (old, new) = (&old[..old.len() - suffix], &new[..new.len() - suffix]);
+
+    let common = match distance(old, new) {
+        Some(distance) => (old.len() + new.len() - distance) / 2,
+        None => common_tokens(old, new),
+    };
+    Churn {
+        nfiles: if old.is_empty() && new.is_empty() {
+            0
+        } else {
+            1
+        },
+        added: (new.len() - common) as u64,
+        removed: (old.len() - common) as u64,
+        deltas: Vec::new(),
+    }
+}
+
+/// The fewest insertions and deletions to turn @a into @b, using Myers' algorithm. Gives up once
+/// the work done passes `MAX_WORK`.
+fn distance<T: PartialEq>(a: &[T], b: &[T]) -> Option<usize> {
+    let (n, m) = (a.len() as isize, b.len() as isize);
+    let max = a.len() + b.len();
+    let offset = max as isize + 1;
+    // furthest index into @a reached on each diagonal
+    let mut v = vec![0; 2 * max + 3];
+    for d in 0..=max as isize {
+        if (d as usize).saturating_mul(max) > MAX_WORK {
+            return None;
+        }
+        for k in (-d..=d).step_by(2) {
+            let i = (k + offset) as usize;
+            let mut x = if k == -d || (k != d && v[i - 1] < v[i + 1]) {
+                v[i + 1]
+            } else {
+                v[i - 1] + 1
+            };
+            let mut y = x - k;
+            while x < n && y < m && a[x as usize] == b[y as usize] {
+                x += 1;
+                y += 1;
+            }
+            v[i] = x;
+            if x >= n && y >= m {
+                return Some(d as usize);
+            }
+        }
+    }
+    Some(max)
+}
+
+/// The number of tokens @a and @b have in common regardless of their order
+fn common_tokens<T: Eq + std::hash::Hash>(a: &[T], b: &[T]) -> usize {
+    let mut counts: HashMap<&T, usize> = HashMap::new();
+    a.iter()
+        .for_each(|token| *counts.entry(token).or_insert(0) += 1);
+    b.iter()
+        .filter(|token| match counts.get_mut(token) {
+            Some(count) if *count > 0 => {
+                *count -= 1;
+                true
+            }
+            _ => false,
+        })
+        .count()
+}
+
+/// Print the churn of each group in @groups, and @totals, between the revisions in @range if
+/// there are any. @columns are the columns of `Churn::deltas`. JSON also has the churn of each
+/// file in @files.
+pub fn print(
+    format: &Format,
+    range: Option<(&str, &str)>,
+    columns: &[Column],
+    groups: &[(String, Churn)],
+    totals: Option<Churn>,
+    files: &[FileChurn],
+    style: TableStyle,
+) {
+    match format {
+        Format::Table => output::churn_table(columns, groups, totals.as_ref(), style).printstd(),
+        Format::Csv => {
+            if let Err(err) = output::churn_table(columns, groups, totals.as_ref(), style)
+                .to_csv(std::io::stdout())
+            {
+                eprintln!("{}", err);
+            }
+        }
+        Format::Json => {
+            let deltas = |churn: &Churn| {
+                columns
+                    .iter()
+                    .map(Column::key)
+                    .zip(churn.deltas.iter().map(|delta| Value::from(*delta)))
+                    .collect::<Map<String, Value>>()
+            };
+            let churn = |churn: &Churn| {
+                let mut json = json!({
+                    "files": churn.nfiles,
+                    "added": churn.added,
+                    "removed": churn.removed,
+                });
+                if !columns.is_empty() {
+                    json["deltas"] = Value::from(deltas(churn));
+                }
+                json
+            };
+            let group = |group: &String, c: &Churn| {
+                let mut json = churn(c);
+                json["group"] = Value::from(group.clone());
+                json
+            };
+            let mut json = json!({
+                "groups": groups
+                    .iter()
+                    .map(|(name, c)| group(name, c))
+                    .collect::<Vec<Value>>(),
+                "files": files
+                    .iter()
+                    .map(|file| {
+                        let mut json = json!({
+                            "path": file.path.display().to_string(),
+                            "language": file.lang.to_string(),
+                            "group": file.group,
+                            "added": file.churn.added,
+                            "removed": file.churn.removed,
+                        });
+                        if !columns.is_empty() {
+                            json["deltas"] = Value::from(deltas(&file.churn));
+                        }
+                        json
+                    })
+                    .collect::<Vec<Value>>(),
+            });
+            if let Some((from, to)) = range {
+                json["from"] = Value::from(from);
+                json["to"] = Value::from(to);
+            }
+            if let Some(totals) = totals {
+                json["totals"] = churn(&totals);
+            }
+            println!("{}", json);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranges() {
+        assert_eq!(("v1.0", "main"), parse_range("v1.0..main"));
+        assert_eq!(("v1.0", "HEAD"), parse_range("v1.0.."));
+        assert_eq!(("v1.0", "HEAD"), parse_range("v1.0"));
+        assert_eq!(("HEAD", "main"), parse_range("..main"));
+    }
+
+    #[test]
+    fn churn_of_tokens() {
+        let churn_of = |old: &str, new: &str| {
+            let old: Vec<char> = old.chars().collect();
+            let new: Vec<char> = new.chars().collect();
+            churn(&old, &new)
+        };
+        assert_eq!(Churn::default(), churn_of("abc", "abc"));
+        assert_eq!(
+            Churn {
+                nfiles: 1,
+                added: 1,
+                removed: 0,
+                deltas: Vec::new(),
+            },
+            churn_of("abc", "abxc")
+        );
+        assert_eq!(
+            Churn {
+                nfiles: 1,
+                added: 2,
+                removed: 3,
+                deltas: Vec::new(),
+            },
+            churn_of("abcdef", "axcyf")
+        );
+        assert_eq!(
+            Churn {
+                nfiles: 1,
+                added: 0,
+                removed: 3,
+                deltas: Vec::new(),
+            },
+            churn_of("abc", "")
+        );
+        assert_eq!(Some(2), distance(&[1, 2, 3], &[1, 3, 2]));
+        assert_eq!(2, common_tokens(&[1, 2, 3], &[3, 2, 4]));
+    }
+
+    #[test]
+    fn reformatting_has_no_churn() {
+        let old = "fn main() { let x = 1; }";
+        let new = "fn main() {\n    let x = 1;\n}\n";
+        let path = Path::new("main.rs");
+        let old = tokens(path, old, &Language::Rust).unwrap();
+        let new = tokens(path, new, &Language::Rust).unwrap();
+        assert_eq!(Churn::default(), churn(&old, &new));
+    }
+}
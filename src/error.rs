@@ -16,6 +16,17 @@ pub enum Error {
     Ignore(ignore::Error),
     LanguageIgnored(PathBuf, Language),
     Glob(GlobError),
+    Sqlite(rusqlite::Error),
+    UnknownColumn(String),
+    Template(String),
+    Baseline(String),
+    Git(git2::Error),
+    Patch(String),
+    Blame(String),
+    Check(String),
+    Config(String),
+    Format(String),
+    Usage(String),
 }
 
 impl Error {
@@ -28,6 +39,17 @@ impl Error {
             Error::Ignore(_) => verbose_lvl >= 1,
             Error::LanguageIgnored(_, _) => verbose_lvl >= 3,
             Error::Glob(_) => verbose_lvl >= 3,
+            Error::Sqlite(_) => true,
+            Error::UnknownColumn(_) => true,
+            Error::Template(_) => true,
+            Error::Baseline(_) => true,
+            Error::Git(_) => true,
+            Error::Patch(_) => true,
+            Error::Blame(_) => true,
+            Error::Check(_) => true,
+            Error::Config(_) => true,
+            Error::Format(_) => true,
+            Error::Usage(_) => true,
         }
     }
 }
@@ -49,6 +71,21 @@ impl Display for Error {
                 )
             }
             Error::Glob(err) => writeln!(f, "Error with globbing {}", err),
+            Error::Sqlite(err) => writeln!(f, "SQLite Error: {}", err),
+            Error::UnknownColumn(key) => writeln!(
+                f,
+                "Unknown column \"{}\". Columns are files, tokens, or kind:{{kind}}, pattern:{{pattern}}, and query:{{query}} for each --kind, --kind-pattern, and --query. --groupby=author adds age:median and age:old. Tables also have a group column",
+                key
+            ),
+            Error::Template(err) => writeln!(f, "Template Error: {}", err),
+            Error::Baseline(err) => writeln!(f, "Baseline Error: {}", err),
+            Error::Git(err) => writeln!(f, "Git Error: {}", err.message()),
+            Error::Patch(err) => writeln!(f, "Patch Error: {}", err),
+            Error::Blame(err) => writeln!(f, "Blame Error: {}", err),
+            Error::Check(err) => writeln!(f, "Check Error: {}", err),
+            Error::Config(err) => writeln!(f, "Config Error: {}", err),
+            Error::Format(err) => writeln!(f, "Format Error: {}", err),
+            Error::Usage(err) => writeln!(f, "Usage Error: {}", err),
         }
     }
 }
@@ -76,3 +113,15 @@ impl From<GlobError> for Error {
         Error::Glob(err)
     }
 }
+
+impl From<rusqlite::Error> for Error {
+    fn from(err: rusqlite::Error) -> Error {
+        Error::Sqlite(err)
+    }
+}
+
+impl From<git2::Error> for Error {
+    fn from(err: git2::Error) -> Error {
+        Error::Git(err)
+    }
+}
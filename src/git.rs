@@ -0,0 +1,677 @@
+use crate::count::Counts;
+use crate::error::{Error, Result};
+use crate::language::Language;
+use git2::{
+    BlameOptions, Delta, DiffOptions, ErrorCode, Index, Object, ObjectType, Oid, Repository,
+};
+use ignore::gitignore::{Gitignore, GitignoreBuilder};
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::io;
+use std::path::{Component, Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// File mode of symbolic links in git trees
+const LINK_MODE: i32 = 0o120000;
+
+/// File mode of submodules in git trees and the index
+const SUBMODULE_MODE: i32 = 0o160000;
+
+const DAY: i64 = 24 * 60 * 60;
+
+/// Author of lines which aren't committed, the same as `git blame`
+pub const UNCOMMITTED: &str = "Not Committed Yet";
+
+/// Counts of blobs which have already been counted, by the blob and the language it was counted
+/// as. Blobs are immutable so these can be reused for every revision in the same run.
+pub type Cache = Arc<Mutex<HashMap<(Oid, Language), Counts>>>;
+
+/// The tree of a commit given by --rev. Only the location of the repository is kept rather than
+/// the repository itself so this can be shared between threads.
+#[derive(Debug, Clone)]
+pub struct Rev {
+    pub name: String,
+    git_dir: PathBuf,
+    workdir: Option<PathBuf>,
+    /// The commit @name refers to, unless it's a tree or the index
+    pub commit: Option<Oid>,
+    snapshot: Snapshot,
+    pub cache: Cache,
+    /// Only these files, and the directories containing them, are counted when set. Paths are
+    /// relative to the root of the repository.
+    only: Option<Arc<HashSet<PathBuf>>>,
+}
+
+/// The files a `Rev` has
+#[derive(Debug, Clone, Copy)]
+enum Snapshot {
+    Tree(Oid),
+    /// The staged files of the index, which are read from it as they are rather than as a tree
+    Index,
+}
+
+/// A directory in a `Snapshot`
+#[derive(Debug, Clone)]
+enum Dir {
+    Tree(Oid),
+    /// The directory in the index at this path
+    Index(PathBuf),
+}
+
+/// An entry of a directory in a `Snapshot`
+#[derive(Debug, Clone)]
+struct Entry {
+    name: String,
+    /// The directory this is, unless it's a file
+    dir: Option<Dir>,
+    /// The blob of a file, or the commit of a submodule
+    id: Oid,
+    mode: i32,
+}
+
+/// Files which changed since the merge base of a revision given by --changed-since. Paths are
+/// relative to the root of the repository.
+#[derive(Debug)]
+pub struct Changes {
+    /// The merge base the changes are relative to
+    pub base: Oid,
+    /// Files which were changed or deleted, as they were in @base
+    pub old: Vec<PathBuf>,
+    /// Files which were changed or added
+    pub new: Vec<PathBuf>,
+    workdir: Option<PathBuf>,
+}
+
+/// Files tracked in the index, see --git-files. Paths are relative to the root of the repository.
+#[derive(Debug)]
+pub struct Tracked {
+    pub files: Vec<PathBuf>,
+    workdir: Option<PathBuf>,
+}
+
+/// Resolve "." and ".." in @path without touching the filesystem, since paths given with --rev
+/// don't need to exist in the working directory.
+pub fn normalize(path: &Path) -> PathBuf {
+    path.components()
+        .fold(PathBuf::new(), |mut acc, component| {
+            match component {
+                Component::CurDir => {}
+                Component::ParentDir => {
+                    acc.pop();
+                }
+                c => acc.push(c),
+            }
+            acc
+        })
+}
+
+/// The path of @path relative to @workdir, the root of the repository
+pub fn repo_path(workdir: Option<&Path>, path: &Path) -> Result<PathBuf> {
+    let workdir = match workdir {
+        Some(workdir) => workdir.canonicalize()?,
+        // bare repositories have no working directory so paths are always from the root
+        None => return Ok(normalize(path)),
+    };
+    let path = normalize(&env::current_dir()?.canonicalize()?.join(path));
+    path.strip_prefix(&workdir)
+        .map(Path::to_path_buf)
+        .map_err(|_| {
+            Error::IO(io::Error::new(
+                io::ErrorKind::NotFound,
+                format!("{} is outside of the repository", path.display()),
+            ))
+        })
+}
+
+/// Name @path, which is under @prefix, by replacing @prefix with @display
+fn display_path(path: &Path, prefix: &Path, display: &Path) -> PathBuf {
+    let rest = path.strip_prefix(prefix).unwrap_or(path);
+    if rest.as_os_str().is_empty() {
+        display.to_path_buf()
+    } else {
+        display.join(rest)
+    }
+}
+
+/// Whether @path is ignored by @ignores, where later matchers take precedence
+fn is_ignored(ignores: &[Gitignore], path: &Path, is_dir: bool) -> bool {
+    matches!(
+        ignores
+            .iter()
+            .rev()
+            .map(|ignore| ignore.matched(path, is_dir))
+            .find(|m| !m.is_none()),
+        Some(m) if m.is_ignore()
+    )
+}
+
+impl Rev {
+    /// Resolve @name (e.g. a commit, tag, or branch) in the repository containing the current
+    /// directory
+    pub fn resolve(name: &str) -> Result<Rev> {
+        let repo = Repository::discover(".")?;
+        let object = repo.revparse_single(name)?;
+        Rev::new(&repo, name, &object, Cache::default())
+    }
+
+    /// The staged contents of the index in the repository containing the current directory. Files
+    /// with unresolved conflicts have nothing staged so they aren't counted.
+    pub fn staged() -> Result<Rev> {
+        let repo = Repository::discover(".")?;
+        Ok(Rev {
+            name: String::from("the index"),
+            git_dir: repo.path().to_path_buf(),
+            workdir: repo.workdir().map(Path::to_path_buf),
+            commit: None,
+            snapshot: Snapshot::Index,
+            cache: Cache::default(),
+            only: None,
+        })
+    }
+
+    /// The tree of @object in @repo which shares counted blobs with other revisions through @cache
+    pub fn new(repo: &Repository, name: &str, object: &Object, cache: Cache) -> Result<Rev> {
+        Ok(Rev {
+            name: name.to_string(),
+            git_dir: repo.path().to_path_buf(),
+            workdir: repo.workdir().map(Path::to_path_buf),
+            commit: object.peel_to_commit().ok().map(|commit| commit.id()),
+            snapshot: Snapshot::Tree(object.peel_to_tree()?.id()),
+            cache,
+            only: None,
+        })
+    }
+
+    pub fn open(&self) -> Result<Repository> {
+        Ok(Repository::open(&self.git_dir)?)
+    }
+
+    /// Only count @files, which are relative to the root of the repository
+    pub fn restrict(self, files: &[PathBuf]) -> Rev {
+        let only = files
+            .iter()
+            .flat_map(|file| file.ancestors())
+            .map(Path::to_path_buf)
+            .collect();
+        Rev {
+            only: Some(Arc::new(only)),
+            ..self
+        }
+    }
+
+    /// Find every file under @paths in the tree along with the id of its blob, the same as
+    /// `fs::iter_paths` does for the working directory. Ignore files are read from the tree.
+    pub fn files(
+        &self,
+        repo: &Repository,
+        paths: &[impl AsRef<Path>],
+        no_git: bool,
+        count_hidden: bool,
+        no_dot_ignore: bool,
+        no_parent_ignore: bool,
+    ) -> (Vec<(PathBuf, Oid)>, Vec<Error>) {
+        let mut files = Vec::new();
+        let mut errors = Vec::new();
+        let (root, index) = match self.snapshot {
+            Snapshot::Tree(tree) => (Dir::Tree(tree), HashMap::new()),
+            Snapshot::Index => match repo.index().map(|index| index_dirs(&index)) {
+                Ok(dirs) => (Dir::Index(PathBuf::new()), dirs),
+                Err(err) => return (files, vec![err.into()]),
+            },
+        };
+        let walker = Walker {
+            repo,
+            index,
+            no_git,
+            count_hidden,
+            no_dot_ignore,
+            no_parent_ignore,
+            only: self.only.as_deref(),
+        };
+        for path in paths {
+            let path = path.as_ref();
+            let res = repo_path(self.workdir.as_deref(), path).and_then(|prefix| {
+                let found = walker.walk(
+                    &root,
+                    Path::new(""),
+                    &prefix,
+                    path,
+                    &mut Vec::new(),
+                    &mut files,
+                )?;
+                if found {
+                    Ok(())
+                } else {
+                    Err(Error::IO(io::Error::new(
+                        io::ErrorKind::NotFound,
+                        format!("{} doesn't exist in {}", path.display(), self.name),
+                    )))
+                }
+            });
+            if let Err(err) = res {
+                errors.push(err);
+            }
+        }
+        (files, errors)
+    }
+}
+
+/// Who last changed each line of a file
+#[derive(Debug)]
+pub struct Blame {
+    pub authors: Vec<String>,
+    /// The owner of each line as the index of its author and how many days ago they wrote it
+    pub owners: Vec<(usize, u64)>,
+}
+
+/// Finds who last changed each line of the files in the working directory for --groupby=author.
+/// Only the location of the repository is kept, like `Rev`, so it can be shared between threads.
+#[derive(Debug)]
+pub struct Blamer {
+    git_dir: PathBuf,
+    workdir: PathBuf,
+    /// Seconds since the Unix epoch that ages are measured from
+    now: i64,
+}
+
+impl Blamer {
+    /// Blame files in the repository containing the current directory
+    pub fn discover() -> Result<Blamer> {
+        let repo = Repository::discover(".")?;
+        let workdir = repo
+            .workdir()
+            .ok_or_else(|| git2::Error::from_str("can't blame files in a bare repository"))?;
+        Ok(Blamer {
+            git_dir: repo.path().to_path_buf(),
+            workdir: workdir.to_path_buf(),
+            now: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_secs() as i64),
+        })
+    }
+
+    /// Open the repository for a thread. The git2 error is returned as it is so every file which
+    /// can't be blamed reports it.
+    pub fn open(&self) -> std::result::Result<Repository, git2::Error> {
+        Repository::open(&self.git_dir)
+    }
+
+    /// Blame each line of @text, the contents of @path in the working directory. Lines which were
+    /// changed since HEAD, and every line of files which aren't in HEAD, belong to `UNCOMMITTED`.
+    pub fn blame(&self, repo: &Repository, path: &Path, text: &[u8]) -> Result<Blame> {
+        let mut authors = vec![UNCOMMITTED.to_string()];
+        let mut owners = vec![(0, 0); text.split_inclusive(|b| *b == b'\n').count()];
+        let path = repo_path(Some(&self.workdir), path)?;
+        let committed = match repo.blame_file(&path, Some(BlameOptions::new().use_mailmap(true))) {
+            Ok(blame) => blame,
+            Err(err) if matches!(err.code(), ErrorCode::NotFound | ErrorCode::UnbornBranch) => {
+                return Ok(Blame { authors, owners })
+            }
+            Err(err) => return Err(err.into()),
+        };
+        let blame = committed.blame_buffer(text)?;
+        for hunk in blame.iter() {
+            // changed lines have no commit or signature
+            if hunk.final_commit_id().is_zero() {
+                continue;
+            }
+            let signature = hunk.final_signature();
+            let author = String::from_utf8_lossy(signature.name_bytes());
+            let owner = match authors.iter().position(|name| *name == author) {
+                Some(owner) => owner,
+                None => {
+                    authors.push(author.into_owned());
+                    authors.len() - 1
+                }
+            };
+            let age = ((self.now - signature.when().seconds()).max(0) / DAY) as u64;
+            owners
+                .iter_mut()
+                .skip(hunk.final_start_line().saturating_sub(1))
+                .take(hunk.lines_in_hunk())
+                .for_each(|line| *line = (owner, age));
+        }
+        Ok(Blame { authors, owners })
+    }
+}
+
+impl Changes {
+    /// Find the files which changed since the merge base of @base and @rev, or HEAD and the working
+    /// directory without @rev. Uncommitted changes and files which aren't ignored or committed yet
+    /// are included for the working directory.
+    pub fn find(base: &str, rev: Option<&Rev>) -> Result<Changes> {
+        let repo = Repository::discover(".")?;
+        // the index and trees aren't commits so they're compared with the merge base of HEAD
+        let head = match rev.and_then(|rev| rev.commit) {
+            Some(commit) => commit,
+            None => repo.head()?.peel_to_commit()?.id(),
+        };
+        let base = repo.merge_base(repo.revparse_single(base)?.peel_to_commit()?.id(), head)?;
+        let base_tree = repo.find_commit(base)?.tree()?;
+        let mut options = DiffOptions::new();
+        let diff = match rev.map(|rev| rev.snapshot) {
+            Some(Snapshot::Tree(tree)) => repo.diff_tree_to_tree(
+                Some(&base_tree),
+                Some(&repo.find_tree(tree)?),
+                Some(&mut options),
+            )?,
+            Some(Snapshot::Index) => {
+                repo.diff_tree_to_index(Some(&base_tree), None, Some(&mut options))?
+            }
+            None => {
+                options.include_untracked(true).recurse_untracked_dirs(true);
+                repo.diff_tree_to_workdir_with_index(Some(&base_tree), Some(&mut options))?
+            }
+        };
+
+        let mut changes = Changes {
+            base,
+            old: Vec::new(),
+            new: Vec::new(),
+            workdir: repo.workdir().map(Path::to_path_buf),
+        };
+        for delta in diff.deltas() {
+            if !matches!(delta.status(), Delta::Added | Delta::Untracked) {
+                changes
+                    .old
+                    .extend(delta.old_file().path().map(Path::to_path_buf));
+            }
+            if delta.status() != Delta::Deleted {
+                changes
+                    .new
+                    .extend(delta.new_file().path().map(Path::to_path_buf));
+            }
+        }
+        Ok(changes)
+    }
+
+    /// The files in the working directory under @paths which changed. Files are named the same as
+    /// they are when walking @paths.
+    pub fn select(
+        &self,
+        paths: &[impl AsRef<Path>],
+        count_hidden: bool,
+    ) -> (Vec<PathBuf>, Vec<Error>) {
+        select(self.workdir.as_deref(), &self.new, paths, count_hidden)
+    }
+}
+
+impl Tracked {
+    /// Every file in the index of the repository containing the current directory. Files in
+    /// submodules which are checked out are included when @submodules is set, otherwise
+    /// submodules are left out.
+    pub fn find(submodules: bool) -> Result<Tracked> {
+        let repo = Repository::discover(".")?;
+        let mut files = Vec::new();
+        index_files(&repo, Path::new(""), submodules, &mut files)?;
+        Ok(Tracked {
+            files,
+            workdir: repo.workdir().map(Path::to_path_buf),
+        })
+    }
+
+    /// The tracked files under @paths, named the same as they are when walking @paths
+    pub fn select(
+        &self,
+        paths: &[impl AsRef<Path>],
+        count_hidden: bool,
+    ) -> (Vec<PathBuf>, Vec<Error>) {
+        select(self.workdir.as_deref(), &self.files, paths, count_hidden)
+    }
+}
+
+/// Add the files in the index of @repo to @files, where @prefix is the path of @repo from the root
+/// of the outermost repository. See `Tracked::find`.
+fn index_files(
+    repo: &Repository,
+    prefix: &Path,
+    submodules: bool,
+    files: &mut Vec<PathBuf>,
+) -> Result<()> {
+    let index = repo.index()?;
+    for entry in index.iter() {
+        let path = PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned());
+        match entry.mode as i32 {
+            // symlinks aren't followed when walking the working directory either
+            LINK_MODE => {}
+            SUBMODULE_MODE => {
+                if !submodules {
+                    continue;
+                }
+                let workdir = match repo.workdir() {
+                    Some(workdir) => workdir.join(&path),
+                    None => continue,
+                };
+                // submodules which aren't checked out have nothing to count
+                if let Ok(submodule) = Repository::open(workdir) {
+                    index_files(&submodule, &prefix.join(&path), submodules, files)?;
+                }
+            }
+            _ => files.push(prefix.join(path)),
+        }
+    }
+    // conflicted files have an entry for each side
+    files.dedup();
+    Ok(())
+}
+
+/// The files in @files, which are relative to @workdir, under each of @paths. Files are named the
+/// same as they are when walking @paths and hidden files are left out unless @count_hidden is set.
+fn select(
+    workdir: Option<&Path>,
+    files: &[PathBuf],
+    paths: &[impl AsRef<Path>],
+    count_hidden: bool,
+) -> (Vec<PathBuf>, Vec<Error>) {
+    let mut selected = Vec::new();
+    let mut errors = Vec::new();
+    for path in paths {
+        let path = path.as_ref();
+        match repo_path(workdir, path) {
+            Ok(prefix) => selected.extend(
+                files
+                    .iter()
+                    .filter(|file| file.starts_with(&prefix))
+                    .filter(|file| {
+                        count_hidden
+                            || !file
+                                .strip_prefix(&prefix)
+                                .unwrap_or(file)
+                                .iter()
+                                .any(|name| name.to_string_lossy().starts_with('.'))
+                    })
+                    .map(|file| display_path(file, &prefix, path)),
+            ),
+            Err(err) => errors.push(err),
+        }
+    }
+    (selected, errors)
+}
+
+/// Read the contents of the blob @id as text
+pub fn read(repo: &Repository, id: Oid) -> Result<String> {
+    let blob = repo.find_blob(id)?;
+    String::from_utf8(blob.content().to_vec())
+        .map_err(|err| Error::IO(io::Error::new(io::ErrorKind::InvalidData, err)))
+}
+
+/// The commit checked out in the repository containing the current directory, if there's one
+pub fn head() -> Option<Oid> {
+    let repo = Repository::discover(".").ok()?;
+    let commit = repo.head().ok()?.peel_to_commit().ok()?;
+    Some(commit.id())
+}
+
+/// Up to the first `language::HEAD_LEN` bytes of the blob @id
+pub fn read_head(repo: &Repository, id: Oid) -> Option<Vec<u8>> {
+    let blob = repo.find_blob(id).ok()?;
+    let content = blob.content();
+    Some(content[..content.len().min(crate::language::HEAD_LEN)].to_vec())
+}
+
+/// The entries of each directory of the staged files in @index, by the path of the directory
+fn index_dirs(index: &Index) -> HashMap<PathBuf, Vec<Entry>> {
+    let mut dirs: HashMap<PathBuf, Vec<Entry>> = HashMap::new();
+    for entry in index.iter() {
+        // entries of conflicts are in stages 1 to 3 and nothing is staged for them
+        if (entry.flags >> 12) & 0b11 != 0 {
+            continue;
+        }
+        let path = PathBuf::from(String::from_utf8_lossy(&entry.path).into_owned());
+        let mut child = Entry {
+            name: String::new(),
+            dir: None,
+            id: entry.id,
+            mode: entry.mode as i32,
+        };
+        // add the file to its directory then each directory to its parent until one already is
+        for path in path
+            .ancestors()
+            .take_while(|path| !path.as_os_str().is_empty())
+        {
+            child.name = path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let parent = path.parent().map(Path::to_path_buf).unwrap_or_default();
+            let existed = dirs.contains_key(&parent);
+            dirs.entry(parent.clone()).or_default().push(child);
+            if existed {
+                break;
+            }
+            child = Entry {
+                name: String::new(),
+                dir: Some(Dir::Index(parent)),
+                id: Oid::zero(),
+                mode: 0,
+            };
+        }
+    }
+    dirs
+}
+
+struct Walker<'a> {
+    repo: &'a Repository,
+    /// Directories of the index when the index is walked rather than a tree
+    index: HashMap<PathBuf, Vec<Entry>>,
+    no_git: bool,
+    count_hidden: bool,
+    no_dot_ignore: bool,
+    no_parent_ignore: bool,
+    only: Option<&'a HashSet<PathBuf>>,
+}
+
+impl<'a> Walker<'a> {
+    /// The entries of @dir
+    fn entries(&self, dir: &Dir) -> Result<Vec<Entry>> {
+        match dir {
+            Dir::Tree(tree) => Ok(self
+                .repo
+                .find_tree(*tree)?
+                .iter()
+                .filter_map(|entry| {
+                    Some(Entry {
+                        name: entry.name()?.to_string(),
+                        dir: (entry.kind() == Some(ObjectType::Tree))
+                            .then(|| Dir::Tree(entry.id())),
+                        id: entry.id(),
+                        mode: entry.filemode(),
+                    })
+                })
+                .collect()),
+            Dir::Index(path) => Ok(self.index.get(path).cloned().unwrap_or_default()),
+        }
+    }
+
+    /// Build a matcher for the ignore file @name in @entries, the directory at @dir, if there is
+    /// one
+    fn ignore_file(&self, entries: &[Entry], dir: &Path, name: &str) -> Result<Option<Gitignore>> {
+        let entry = match entries.iter().find(|entry| entry.name == name) {
+            Some(entry) if entry.dir.is_none() && entry.mode != SUBMODULE_MODE => entry,
+            _ => return Ok(None),
+        };
+        let text = read(self.repo, entry.id)?;
+        let mut builder = GitignoreBuilder::new(dir);
+        for line in text.lines() {
+            // invalid globs are skipped, the same as git does
+            let _ = builder.add_line(None, line);
+        }
+        Ok(Some(builder.build()?))
+    }
+
+    /// Walk @tree, the directory at @dir, collecting the files under @prefix into @files. Files are
+    /// named by replacing @prefix in their path with @display. Returns whether @prefix was found.
+    fn walk(
+        &self,
+        tree: &Dir,
+        dir: &Path,
+        prefix: &Path,
+        display: &Path,
+        ignores: &mut Vec<Gitignore>,
+        files: &mut Vec<(PathBuf, Oid)>,
+    ) -> Result<bool> {
+        let inside = dir.starts_with(prefix);
+        let nignores = ignores.len();
+        let entries = self.entries(tree)?;
+        if inside || !self.no_parent_ignore {
+            // .ignore files take precedence over .gitignore files in the same directory
+            if !self.no_git {
+                ignores.extend(self.ignore_file(&entries, dir, ".gitignore")?);
+            }
+            if !self.no_dot_ignore {
+                ignores.extend(self.ignore_file(&entries, dir, ".ignore")?);
+            }
+        }
+
+        let mut found = inside;
+        for entry in &entries {
+            let name = entry.name.as_str();
+            let path = dir.join(name);
+            if !path.starts_with(prefix) && !prefix.starts_with(&path) {
+                continue;
+            }
+            let is_dir = entry.dir.is_some();
+            // the path given as an argument is always counted, like when walking the filesystem
+            if path != prefix
+                && path.starts_with(prefix)
+                && ((!self.count_hidden && name.starts_with('.'))
+                    || is_ignored(ignores, &path, is_dir))
+            {
+                continue;
+            }
+            if matches!(self.only, Some(only) if !only.contains(&path)) {
+                continue;
+            }
+
+            match &entry.dir {
+                Some(subtree) => {
+                    found |= self.walk(subtree, &path, prefix, display, ignores, files)?;
+                }
+                None if entry.mode != LINK_MODE
+                    && entry.mode != SUBMODULE_MODE
+                    && path.starts_with(prefix) =>
+                {
+                    found = true;
+                    files.push((display_path(&path, prefix, display), entry.id));
+                }
+                // submodules and links aren't followed, and files outside of @prefix aren't counted
+                None => {}
+            }
+        }
+        ignores.truncate(nignores);
+        Ok(found)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn normalize_paths() {
+        assert_eq!(PathBuf::from("a/c"), normalize(Path::new("./a/b/../c/.")));
+        assert_eq!(PathBuf::from("/a"), normalize(Path::new("/a/b/..")));
+        assert_eq!(PathBuf::from(""), normalize(Path::new(".")));
+    }
+}
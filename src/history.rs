@@ -0,0 +1,257 @@
+use crate::count::Counts;
+use crate::error::Result;
+use crate::output::{self, Column, Layout};
+use git2::{Commit, Oid, Repository};
+use prettytable::Cell;
+use serde_json::{json, Map, Value};
+use std::format;
+use std::str::FromStr;
+
+const DAY: i64 = 24 * 60 * 60;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Format {
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "\"{}\" is not supported by history. Use one of csv|json",
+                s
+            )),
+        }
+    }
+}
+
+/// The counts for each group in a single commit of the history
+pub struct Point {
+    pub commit: Oid,
+    /// Seconds since the Unix epoch that the commit was made
+    pub time: i64,
+    pub counts: Vec<(String, Counts)>,
+}
+
+/// Days since 1970-01-01 of the date @year-@month-@day
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    // http://howardhinnant.github.io/date_algorithms.html#days_from_civil
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year.rem_euclid(400);
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+/// The (year, month, day) of the date @days since 1970-01-01
+fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    // http://howardhinnant.github.io/date_algorithms.html#civil_from_days
+    let days = days + 719468;
+    let era = days.div_euclid(146097);
+    let doe = days.rem_euclid(146097);
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400;
+    (if month <= 2 { year + 1 } else { year }, month, day)
+}
+
+/// Parse the YYYY-MM-DD argument to --since into seconds since the Unix epoch at midnight UTC
+pub fn parse_date(s: &str) -> std::result::Result<i64, String> {
+    let err = || {
+        format!(
+            "\"{}\" is not a supported argument to --since. Use a date like 2024-01-31",
+            s
+        )
+    };
+    let parts = s
+        .split('-')
+        .map(|part| part.parse::<i64>().map_err(|_| err()))
+        .collect::<std::result::Result<Vec<i64>, String>>()?;
+    match parts[..] {
+        [year, month, day] => {
+            let days = days_from_civil(year, month, day);
+            // dates like 2024-02-31 don't survive the round trip
+            if (1..=12).contains(&month) && civil_from_days(days) == (year, month, day) {
+                Ok(days * DAY)
+            } else {
+                Err(err())
+            }
+        }
+        _ => Err(err()),
+    }
+}
+
+/// Parse the argument to --every, e.g. 2w, into seconds
+pub fn parse_duration(s: &str) -> std::result::Result<i64, String> {
+    let err = || {
+        format!(
+            "\"{}\" is not a supported argument to --every. Use a number of days, weeks, months, or years like 1d, 2w, 1m, or 1y",
+            s
+        )
+    };
+    let unit = match s.chars().last() {
+        Some('d') => DAY,
+        Some('w') => 7 * DAY,
+        Some('m') => 30 * DAY,
+        Some('y') => 365 * DAY,
+        _ => return Err(err()),
+    };
+    match s[..s.len() - 1].parse::<i64>() {
+        Ok(n) if n > 0 => Ok(n * unit),
+        _ => Err(err()),
+    }
+}
+
+/// Format @time, in seconds since the Unix epoch, as an ISO 8601 date and time in UTC
+fn format_time(time: i64) -> String {
+    let (year, month, day) = civil_from_days(time.div_euclid(DAY));
+    let secs = time.rem_euclid(DAY);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}Z",
+        year,
+        month,
+        day,
+        secs / 3600,
+        secs % 3600 / 60,
+        secs % 60
+    )
+}
+
+/// Sample commits on the first-parent chain from @tip, oldest first. Commits made before @since
+/// are left out, samples are at least @every seconds apart, and only the newest @limit samples are
+/// kept.
+pub fn sample<'repo>(
+    repo: &'repo Repository,
+    tip: Oid,
+    since: Option<i64>,
+    every: Option<i64>,
+    limit: Option<usize>,
+) -> Result<Vec<Commit<'repo>>> {
+    let mut commits: Vec<Commit> = Vec::new();
+    let mut commit = repo.find_commit(tip)?;
+    loop {
+        if matches!(limit, Some(limit) if commits.len() >= limit) {
+            break;
+        }
+        let time = commit.time().seconds();
+        if matches!(since, Some(since) if time < since) {
+            break;
+        }
+        let due = match (commits.last(), every) {
+            (Some(last), Some(every)) => time <= last.time().seconds() - every,
+            _ => true,
+        };
+        let parent = commit.parents().next();
+        if due {
+            commits.push(commit);
+        }
+        match parent {
+            Some(parent) => commit = parent,
+            None => break,
+        }
+    }
+    commits.reverse();
+    Ok(commits)
+}
+
+/// Print @points with a row for each group in each commit, or a column with @transpose. CSV uses
+/// the columns and titles from @layout while JSON has every count by the key of its column.
+pub fn print(
+    format: &Format,
+    columns: &[Column],
+    layout: &Layout,
+    points: &[Point],
+    transpose: bool,
+) {
+    match format {
+        Format::Csv => {
+            let titles = ["Commit", "Date", &layout.group_title]
+                .iter()
+                .map(|title| title.to_string())
+                .chain(layout.counts().map(|i| layout.titles[i].clone()))
+                .collect();
+            let mut rows = Vec::new();
+            for point in points {
+                for (group, counts) in &point.counts {
+                    let values = counts.values();
+                    rows.push(
+                        [
+                            point.commit.to_string(),
+                            format_time(point.time),
+                            group.clone(),
+                        ]
+                        .into_iter()
+                        .chain(layout.counts().map(|i| values[i].to_string()))
+                        .map(|value| Cell::new(&value))
+                        .collect(),
+                    );
+                }
+            }
+            let table = output::rows_table(titles, rows, transpose);
+            if let Err(err) = table.to_csv(std::io::stdout()) {
+                eprintln!("{}", err);
+            }
+        }
+        Format::Json => {
+            let json: Vec<Value> = points
+                .iter()
+                .map(|point| {
+                    json!({
+                        "commit": point.commit.to_string(),
+                        "date": format_time(point.time),
+                        "groups": point
+                            .counts
+                            .iter()
+                            .map(|(group, counts)| {
+                                json!({
+                                    "group": group,
+                                    "counts": columns
+                                        .iter()
+                                        .map(Column::key)
+                                        .zip(counts.values().into_iter().map(Value::from))
+                                        .collect::<Map<String, Value>>(),
+                                })
+                            })
+                            .collect::<Vec<Value>>(),
+                    })
+                })
+                .collect();
+            println!("{}", Value::from(json));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dates() {
+        assert_eq!(Ok(0), parse_date("1970-01-01"));
+        assert_eq!(Ok(1709164800), parse_date("2024-02-29"));
+        assert!(parse_date("2023-02-29").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("2024-01").is_err());
+        assert_eq!("2024-02-29T12:30:05Z", format_time(1709164800 + 45005));
+        assert_eq!("1969-12-31T23:59:59Z", format_time(-1));
+    }
+
+    #[test]
+    fn durations() {
+        assert_eq!(Ok(DAY), parse_duration("1d"));
+        assert_eq!(Ok(14 * DAY), parse_duration("2w"));
+        assert_eq!(Ok(365 * DAY), parse_duration("1y"));
+        assert!(parse_duration("0d").is_err());
+        assert!(parse_duration("1").is_err());
+        assert!(parse_duration("w").is_err());
+    }
+}
@@ -0,0 +1,171 @@
+use crate::error::Result;
+use crate::language::Language;
+use crate::output::{self, Column, TableStyle};
+use git2::{Oid, Repository, Sort};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::format;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Format {
+    Table,
+    Csv,
+    Json,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        match s {
+            "table" => Ok(Format::Table),
+            "csv" => Ok(Format::Csv),
+            "json" => Ok(Format::Json),
+            _ => Err(format!(
+                "\"{}\" is not supported by hotspots. Use one of table|csv|json",
+                s
+            )),
+        }
+    }
+}
+
+/// A file along with its size, the number of commits which changed it, and how much of a hotspot
+/// it is
+#[derive(Debug, PartialEq)]
+pub struct Hotspot {
+    pub path: PathBuf,
+    pub lang: Language,
+    pub size: u64,
+    pub commits: u64,
+    /// The size and commits of the file relative to the biggest and most changed files, multiplied
+    /// together, from 0 to 100
+    pub score: f64,
+}
+
+/// The number of commits reachable from @tip which changed each file, by its path from the root
+/// of the repository. Commits made before @since and merges aren't counted.
+pub fn commits(repo: &Repository, tip: Oid, since: Option<i64>) -> Result<HashMap<PathBuf, u64>> {
+    let mut counts = HashMap::new();
+    let mut walk = repo.revwalk()?;
+    walk.set_sorting(Sort::TIME)?;
+    walk.push(tip)?;
+    for id in walk {
+        let commit = repo.find_commit(id?)?;
+        if commit.parent_count() > 1
+            || matches!(since, Some(since) if commit.time().seconds() < since)
+        {
+            continue;
+        }
+        let parent = commit
+            .parents()
+            .next()
+            .map(|parent| parent.tree())
+            .transpose()?;
+        let diff = repo.diff_tree_to_tree(parent.as_ref(), Some(&commit.tree()?), None)?;
+        for delta in diff.deltas() {
+            if let Some(path) = delta.new_file().path() {
+                *counts.entry(path.to_path_buf()).or_insert(0) += 1;
+            }
+        }
+    }
+    Ok(counts)
+}
+
+/// Score each of @files, as its path, language, size, and number of commits, and order them from
+/// the biggest hotspot to the smallest. Files which weren't changed aren't hotspots so they don't
+/// affect the scores of the others.
+pub fn rank(files: Vec<(PathBuf, Language, u64, u64)>) -> Vec<Hotspot> {
+    let files: Vec<_> = files
+        .into_iter()
+        .filter(|(_, _, _, commits)| *commits > 0)
+        .collect();
+    let max_size = files.iter().map(|(_, _, size, _)| *size).max().unwrap_or(0);
+    let max_commits = files
+        .iter()
+        .map(|(_, _, _, commits)| *commits)
+        .max()
+        .unwrap_or(0);
+    let mut hotspots: Vec<Hotspot> = files
+        .into_iter()
+        .map(|(path, lang, size, commits)| Hotspot {
+            score: 100.0
+                * (size as f64 / max_size.max(1) as f64)
+                * (commits as f64 / max_commits as f64),
+            path,
+            lang,
+            size,
+            commits,
+        })
+        .collect();
+    hotspots.sort_by(|h1, h2| {
+        h2.score
+            .total_cmp(&h1.score)
+            .then(h2.commits.cmp(&h1.commits))
+            .then(h1.path.cmp(&h2.path))
+    });
+    hotspots
+}
+
+/// Print @hotspots where @column is the column used for their size
+pub fn print(format: &Format, column: &Column, hotspots: &[Hotspot], style: TableStyle) {
+    match format {
+        Format::Table => output::hotspots_table(column, hotspots, style).printstd(),
+        Format::Csv => {
+            if let Err(err) =
+                output::hotspots_table(column, hotspots, style).to_csv(std::io::stdout())
+            {
+                eprintln!("{}", err);
+            }
+        }
+        Format::Json => {
+            let json: Vec<Value> = hotspots
+                .iter()
+                .map(|hotspot| {
+                    json!({
+                        "path": hotspot.path.display().to_string(),
+                        "language": hotspot.lang.to_string(),
+                        "size": {column.key(): hotspot.size},
+                        "commits": hotspot.commits,
+                        "score": hotspot.score,
+                    })
+                })
+                .collect();
+            println!("{}", Value::from(json));
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ranking_hotspots() {
+        let hotspots = rank(vec![
+            (PathBuf::from("big.rs"), Language::Rust, 100, 1),
+            (PathBuf::from("hot.rs"), Language::Rust, 50, 4),
+            (PathBuf::from("cold.rs"), Language::Rust, 1000, 0),
+        ]);
+        assert_eq!(
+            vec![
+                Hotspot {
+                    path: PathBuf::from("hot.rs"),
+                    lang: Language::Rust,
+                    size: 50,
+                    commits: 4,
+                    score: 50.0,
+                },
+                Hotspot {
+                    path: PathBuf::from("big.rs"),
+                    lang: Language::Rust,
+                    size: 100,
+                    commits: 1,
+                    score: 25.0,
+                },
+            ],
+            hotspots
+        );
+    }
+}
@@ -1,10 +1,17 @@
 use crate::error::{Error, Result};
 use crate::output::print_languages;
+use crate::tree::TreeIterator;
+use once_cell::sync::Lazy;
+use regex::Regex;
 use std::cmp::{Eq, Ord, PartialEq, PartialOrd};
 use std::collections::HashMap;
 use std::ffi::OsString;
 use std::fmt;
+use std::fs;
+use std::io::Read;
 use std::path::Path;
+use std::str::FromStr;
+use tree_sitter::Parser;
 
 /// There are many commented out languages, these languages do not have up to date Tree-sitter
 /// parsers. To add support for them, the parser needs to be update to use tree-sitter v0.19.3
@@ -159,6 +166,62 @@ impl fmt::Display for Language {
 }
 
 impl Language {
+    /// Hex colour used when drawing charts, these are the same colours GitHub's linguist uses
+    pub fn color(&self) -> &'static str {
+        match self {
+            Language::Bash => "#89e051",
+            Language::BibTeX => "#778899",
+            Language::C => "#555555",
+            Language::CSharp => "#178600",
+            Language::Clojure => "#db5855",
+            Language::Cpp => "#f34b7d",
+            Language::Css => "#563d7c",
+            Language::Dart => "#00b4ab",
+            Language::Elm => "#60b5cc",
+            Language::Erlang => "#b83998",
+            Language::EmbeddedTemplate => "#701516",
+            Language::Fennel => "#fff3d7",
+            Language::GDScript => "#355570",
+            Language::Go => "#00add8",
+            Language::GraphQL => "#e10098",
+            Language::Haskell => "#5e5086",
+            Language::Html => "#e34c26",
+            Language::Java => "#b07219",
+            Language::Javascript => "#f1e05a",
+            Language::Json => "#292929",
+            Language::Julia => "#a270ba",
+            Language::Kotlin => "#a97bff",
+            Language::LaTeX => "#3d6117",
+            Language::Lua => "#000080",
+            Language::Markdown => "#083fa1",
+            Language::Nix => "#7e7eff",
+            Language::OCaml => "#3be133",
+            Language::OCamlInterface => "#3be133",
+            Language::OCamlLex => "#3be133",
+            Language::Php => "#4f5d95",
+            Language::Python => "#3572a5",
+            Language::Query => "#8ea64c",
+            Language::R => "#198ce7",
+            Language::Rst => "#141414",
+            Language::Ruby => "#701516",
+            Language::Rust => "#dea584",
+            Language::Scala => "#c22d40",
+            Language::Scss => "#c6538c",
+            Language::Supercollider => "#46390b",
+            Language::Svelte => "#ff3e00",
+            Language::Swift => "#f05138",
+            Language::Teal => "#00b1bc",
+            Language::Toml => "#9c4221",
+            Language::Typescript => "#3178c6",
+            Language::Tsx => "#3178c6",
+            Language::Verilog => "#b2b7f8",
+            Language::Vue => "#41b883",
+            Language::Yaml => "#cb171e",
+            Language::Zig => "#ec915c",
+            Language::Unsupported => "#cccccc",
+        }
+    }
+
     pub fn get_treesitter_language(&self) -> Result<tree_sitter::Language> {
         match self {
             Language::Bash => Ok(tree_sitter_bash::language()),
@@ -194,16 +257,262 @@ impl Language {
     }
 }
 
+/// A mapping from files to the language they're parsed as, given with --map. These override the
+/// extensions tcount knows about.
+#[derive(Debug)]
+pub enum Mapping {
+    /// Files whose names end with ".{ext}"
+    Ext(String, Language),
+    /// Files whose path, relative to the current directory, matches a glob
+    Glob(glob::Pattern, Language),
+}
+
+impl FromStr for Mapping {
+    type Err = String;
+
+    /// Parse a mapping with the syntax "ext:{ext}={language}" or "glob:{glob}={language}" where
+    /// the language is its name or its query directory name from --list-languages
+    fn from_str(s: &str) -> std::result::Result<Self, Self::Err> {
+        let invalid = || {
+            format!(
+                "Invalid mapping \"{}\". Mappings look like ext:{{ext}}={{language}} or glob:{{glob}}={{language}}, e.g. ext:tpl=html",
+                s
+            )
+        };
+        let (kind, rest) = s.split_once(':').ok_or_else(invalid)?;
+        let (pattern, name) = rest.rsplit_once('=').ok_or_else(invalid)?;
+        let lang = Language::named(name).ok_or_else(|| {
+            format!(
+                "\"{}\" isn't a supported language, see --list-languages",
+                name
+            )
+        })?;
+        match kind {
+            "ext" => Ok(Mapping::Ext(
+                pattern.trim_start_matches('.').to_string(),
+                lang,
+            )),
+            "glob" => glob::Pattern::new(pattern)
+                .map(|glob| Mapping::Glob(glob, lang))
+                .map_err(|err| format!("Invalid glob \"{}\": {}", pattern, err)),
+            _ => Err(invalid()),
+        }
+    }
+}
+
+/// Classes, templates, namespaces, access specifiers, and headers without .h, which C doesn't have
+static CPP_HINT: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?m)^\s*(?:template\s*<|namespace(?:\s+\w+)?\s*\{|using\s+namespace\s|class\s+\w+\s*[:{]|class\s+\w+\s*$|(?:public|private|protected)\s*:|#\s*include\s*<\w+>)|\bstd::")
+        .expect("Unexpected internal error compiling the C++ hint")
+});
+
+/// An Emacs modeline, e.g. "-*- mode: ruby -*-" or "-*- ruby -*-"
+static EMACS_MODELINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"-\*-\s*(?:.*?\bmode:\s*)?([\w+#-]+?)\s*(?:;.*)?-\*-")
+        .expect("Unexpected internal error compiling the Emacs modeline pattern")
+});
+
+/// A Vim modeline, e.g. "vim: set ft=sh:"
+static VIM_MODELINE: Lazy<Regex> = Lazy::new(|| {
+    Regex::new(r"(?:^|\s)(?:vi|vim|ex)(?:[<=>]?\d+)?:.*?\b(?:ft|filetype|syntax|syn)=([\w+#-]+)")
+        .expect("Unexpected internal error compiling the Vim modeline pattern")
+});
+
+/// How much of the start of a file is read to find its language when its name doesn't say
+pub const HEAD_LEN: usize = 8192;
+
+/// Up to the first `HEAD_LEN` bytes of the file at @path
+pub fn read_head(path: &Path) -> Option<Vec<u8>> {
+    let mut head = Vec::new();
+    fs::File::open(path)
+        .and_then(|file| file.take(HEAD_LEN as u64).read_to_end(&mut head))
+        .ok()?;
+    Some(head)
+}
+
+impl Language {
+    /// The supported language called @name, which is its name, its query directory name, or a
+    /// name editors and interpreters use for it (e.g. "sh" or "c++"). Case is ignored.
+    pub fn named(name: &str) -> Option<Language> {
+        let name = name.to_lowercase();
+        ALIAS_TO_LANGUAGE
+            .get(name.as_str())
+            .or_else(|| DIR_TO_LANGUAGE.get(name.as_str()))
+            .cloned()
+            .or_else(|| {
+                DIR_TO_LANGUAGE
+                    .values()
+                    .find(|lang| lang.to_string().to_lowercase() == name)
+                    .cloned()
+            })
+            .filter(|lang| lang.get_treesitter_language().is_ok())
+    }
+
+    /// The language of @path, from the last of @mappings which matches it or else its name. Files
+    /// without an extension which aren't well known, like scripts, are recognized by the shebang or
+    /// modeline in @head, the start of their text, which is only read when it's needed.
+    pub fn detect(
+        path: &Path,
+        mappings: &[Mapping],
+        head: impl FnOnce() -> Option<Vec<u8>>,
+    ) -> Language {
+        let relative = path.strip_prefix(".").unwrap_or(path);
+        let name = path
+            .file_name()
+            .map(|name| name.to_string_lossy())
+            .unwrap_or_default();
+        let mapped = mappings.iter().rev().find_map(|mapping| match mapping {
+            Mapping::Ext(ext, lang) => {
+                let matches = name.len() > ext.len()
+                    && name.ends_with(ext.as_str())
+                    && name[..name.len() - ext.len()].ends_with('.');
+                matches.then(|| lang.clone())
+            }
+            Mapping::Glob(glob, lang) => glob.matches_path(relative).then(|| lang.clone()),
+        });
+        if let Some(lang) = mapped {
+            return lang;
+        }
+        let ext = path.extension().map(|ext| ext.to_string_lossy());
+        match (Language::from(path), ext) {
+            (Language::Unsupported, None) => head()
+                .and_then(|head| Language::from_head(&head))
+                .unwrap_or(Language::Unsupported),
+            (lang, Some(ext)) => match AMBIGUOUS_EXTS.get(ext.as_ref()) {
+                Some(candidates) => head()
+                    .and_then(|head| Language::disambiguate(candidates, &head))
+                    .unwrap_or(lang),
+                None => lang,
+            },
+            (lang, None) => lang,
+        }
+    }
+
+    /// The one of @candidates which @head, the start of a file, is written in. A candidate is
+    /// chosen when it's the only one whose `hint` is in the text, otherwise it's the one whose
+    /// grammar has the fewest errors parsing the text, and the first of them when that's a tie.
+    fn disambiguate(candidates: &[Language], head: &[u8]) -> Option<Language> {
+        let mut text = String::from_utf8_lossy(head).into_owned();
+        if head.len() >= HEAD_LEN {
+            // drop the last line, which is likely to be cut off
+            text.truncate(text.rfind('\n').unwrap_or(0));
+        }
+        let candidates: Vec<&Language> = candidates
+            .iter()
+            .filter(|lang| lang.get_treesitter_language().is_ok())
+            .collect();
+        let hinted: Vec<&Language> = candidates
+            .iter()
+            .copied()
+            .filter(|lang| matches!(lang.hint(), Some(hint) if hint.is_match(&text)))
+            .collect();
+        match hinted[..] {
+            [lang] => Some(lang.clone()),
+            // several hints means at least one of them is wrong, so let the grammars decide
+            _ => {
+                let pool = if hinted.is_empty() {
+                    candidates
+                } else {
+                    hinted
+                };
+                pool.into_iter()
+                    .min_by_key(|lang| lang.count_errors(&text))
+                    .cloned()
+            }
+        }
+    }
+
+    /// A pattern which only matches code in this language among the others that share its
+    /// extensions, see `AMBIGUOUS_EXTS`
+    fn hint(&self) -> Option<&'static Regex> {
+        match self {
+            Language::Cpp => Some(&CPP_HINT),
+            _ => None,
+        }
+    }
+
+    /// How many syntax errors there are in @text parsed as this language. Languages without a
+    /// grammar have no errors.
+    fn count_errors(&self, text: &str) -> usize {
+        let mut parser = Parser::new();
+        let tree = self
+            .get_treesitter_language()
+            .ok()
+            .and_then(|lang| parser.set_language(lang).ok())
+            .and_then(|_| parser.parse(text, None));
+        tree.map(|tree| {
+            TreeIterator::new(&tree)
+                .filter(|node| node.is_error() || node.is_missing())
+                .count()
+        })
+        .unwrap_or(0)
+    }
+
+    /// The language named by a shebang, e.g. "#!/usr/bin/env python3", or an Emacs or Vim
+    /// modeline, e.g. "-*- mode: ruby -*-" or "vim: set ft=sh:", at the start of a file. Vim
+    /// modelines can also be in the last lines of files which are shorter than `HEAD_LEN`.
+    fn from_head(head: &[u8]) -> Option<Language> {
+        let text = String::from_utf8_lossy(&head[..head.len().min(HEAD_LEN)]);
+        let lines: Vec<&str> = text.lines().collect();
+        let first = lines.first()?;
+        if let Some(command) = first.strip_prefix("#!") {
+            let mut words = command
+                .split_whitespace()
+                .map(|word| word.rsplit('/').next().unwrap_or(word));
+            let mut interpreter = words.next()?;
+            if interpreter == "env" {
+                // skip options and variables, e.g. #!/usr/bin/env -S VAR=1 node
+                interpreter = words.find(|word| !word.starts_with('-') && !word.contains('='))?;
+            }
+            // python3.11 is python
+            let interpreter =
+                interpreter.trim_end_matches(|c: char| c.is_ascii_digit() || c == '.');
+            if let Some(lang) = Language::named(interpreter) {
+                return Some(lang);
+            }
+        }
+
+        // the Emacs modeline goes after the shebang when there is one
+        let emacs_mode = lines.iter().take(2).find_map(|line| {
+            EMACS_MODELINE
+                .captures(line)
+                .and_then(|caps| Language::named(caps[1].trim_end_matches("-mode")))
+        });
+        if emacs_mode.is_some() {
+            return emacs_mode;
+        }
+
+        let last = if head.len() < HEAD_LEN {
+            lines.len().saturating_sub(5)
+        } else {
+            lines.len()
+        };
+        lines
+            .iter()
+            .take(5)
+            .chain(lines.iter().skip(last.max(5)))
+            .find_map(|line| {
+                VIM_MODELINE
+                    .captures(line)
+                    .and_then(|caps| Language::named(&caps[1]))
+            })
+    }
+}
+
 impl From<&Path> for Language {
     fn from(path: &Path) -> Language {
         let (tag, map) = if path.is_dir() {
             // we assign a `Language` to query directories which take the form {query dir}/{language}/{query name}.scm
             // the {query name}.scm has already been stripped since this is a directory
             (path.file_name(), &DIR_TO_LANGUAGE)
+        } else if let Some(lang) = path
+            .file_name()
+            .and_then(|name| FILENAME_TO_LANGUAGE.get(name.to_string_lossy().as_ref()))
+        {
+            // well known files like Rakefile don't have an extension, or have a misleading one
+            return lang.clone();
         } else {
-            // we only check the extension to determine language
-            // TODO this could be improved on by looking for she-bangs, Vim modelines, or Emacs modelines,
-            // among a few other more complicated heuristics
+            // the contents of files without an extension are checked by `Language::detect`
             (path.extension(), &EXT_TO_LANGUAGE)
         };
         let tag = tag.map(OsString::from).unwrap_or(OsString::new());
@@ -280,6 +589,81 @@ static EXT_TO_LANGUAGE: phf::Map<&'static str, Language> = phf::phf_map! {
     // "zig"     => Language::Zig,
 };
 
+/// Extensions which several languages use, with the languages a file might be written in, see
+/// `Language::disambiguate`. The language in `EXT_TO_LANGUAGE` is used when the content doesn't tell.
+static AMBIGUOUS_EXTS: phf::Map<&'static str, &'static [Language]> = phf::phf_map! {
+    // "h"  => &[Language::C, Language::Cpp, Language::ObjectiveC],
+    "h"     => &[Language::C, Language::Cpp],
+    // "m"  => &[Language::ObjectiveC, Language::Matlab, Language::Mathematica],
+    // "pl" => &[Language::Perl, Language::Prolog],
+    // "v"  => &[Language::Verilog, Language::Coq, Language::V],
+};
+
+/// Files which are always written in a language, whatever their extension is
+static FILENAME_TO_LANGUAGE: phf::Map<&'static str, Language> = phf::phf_map! {
+    ".bash_logout"   => Language::Bash,
+    ".bash_profile"  => Language::Bash,
+    ".bashrc"        => Language::Bash,
+    ".profile"       => Language::Bash,
+    ".zlogin"        => Language::Bash,
+    ".zprofile"      => Language::Bash,
+    ".zshenv"        => Language::Bash,
+    ".zshrc"         => Language::Bash,
+    "APKBUILD"       => Language::Bash,
+    "PKGBUILD"       => Language::Bash,
+    "Jakefile"       => Language::Javascript,
+    "BUCK"           => Language::Python,
+    "BUILD"          => Language::Python,
+    "BUILD.bazel"    => Language::Python,
+    "SConscript"     => Language::Python,
+    "SConstruct"     => Language::Python,
+    "Snakefile"      => Language::Python,
+    "WORKSPACE"      => Language::Python,
+    "WORKSPACE.bazel"=> Language::Python,
+    ".irbrc"         => Language::Ruby,
+    ".pryrc"         => Language::Ruby,
+    "Berksfile"      => Language::Ruby,
+    "Brewfile"       => Language::Ruby,
+    "Capfile"        => Language::Ruby,
+    "Dangerfile"     => Language::Ruby,
+    "Fastfile"       => Language::Ruby,
+    "Gemfile"        => Language::Ruby,
+    "Guardfile"      => Language::Ruby,
+    "Podfile"        => Language::Ruby,
+    "Rakefile"       => Language::Ruby,
+    "Thorfile"       => Language::Ruby,
+    "Vagrantfile"    => Language::Ruby,
+};
+
+/// Other names of languages used by interpreters in shebangs and by editors in modelines
+static ALIAS_TO_LANGUAGE: phf::Map<&'static str, Language> = phf::phf_map! {
+    "ash"          => Language::Bash,
+    "dash"         => Language::Bash,
+    "ksh"          => Language::Bash,
+    "sh"           => Language::Bash,
+    "shell-script" => Language::Bash,
+    "zsh"          => Language::Bash,
+    "c++"          => Language::Cpp,
+    "cs"           => Language::CSharp,
+    "csharp"       => Language::CSharp,
+    "escript"      => Language::Erlang,
+    "golang"       => Language::Go,
+    "js"           => Language::Javascript,
+    "js2"          => Language::Javascript,
+    "node"         => Language::Javascript,
+    "nodejs"       => Language::Javascript,
+    "tex"          => Language::LaTeX,
+    "md"           => Language::Markdown,
+    "tuareg"       => Language::OCaml,
+    "py"           => Language::Python,
+    "pypy"         => Language::Python,
+    "jruby"        => Language::Ruby,
+    "rb"           => Language::Ruby,
+    "rs"           => Language::Rust,
+    "ts"           => Language::Typescript,
+    "ts-node"      => Language::Typescript,
+};
+
 /// These dir names are used when reading query files to know what language they refer to.
 /// See tcount --help for more information on queries.
 static DIR_TO_LANGUAGE: phf::Map<&'static str, Language> = phf::phf_map! {
@@ -333,3 +717,126 @@ static DIR_TO_LANGUAGE: phf::Map<&'static str, Language> = phf::phf_map! {
     "yaml"             => Language::Yaml,
     "zig"              => Language::Zig,
 };
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detecting_mapped_languages() {
+        let mappings: Vec<Mapping> = [
+            "ext:tpl=html",
+            "glob:include/**/*.h=C++",
+            "ext:.jsm=javascript",
+        ]
+        .iter()
+        .map(|s| Mapping::from_str(s).unwrap())
+        .collect();
+        let detect = |path: &str| Language::detect(Path::new(path), &mappings, || None);
+        assert_eq!(Language::Html, detect("./views/index.tpl"));
+        assert_eq!(Language::Cpp, detect("./include/a/b.h"));
+        assert_eq!(Language::C, detect("./src/b.h"));
+        assert_eq!(Language::Unsupported, detect("./tpl"));
+        assert_eq!(Language::Javascript, detect("./lib/a.jsm"));
+        assert_eq!(Language::Rust, detect("./src/main.rs"));
+
+        assert!(Mapping::from_str("ext:jsm").is_err());
+        assert!(Mapping::from_str("path:a=rust").is_err());
+        assert!(Mapping::from_str("ext:kt=kotlin").is_err());
+    }
+
+    #[test]
+    fn detecting_well_known_files() {
+        assert_eq!(Language::Ruby, Language::from(Path::new("./Rakefile")));
+        assert_eq!(Language::Python, Language::from(Path::new("a/BUILD.bazel")));
+        assert_eq!(Language::Bash, Language::from(Path::new("/home/a/.bashrc")));
+        assert_eq!(
+            Language::Unsupported,
+            Language::from(Path::new("./rakefile"))
+        );
+    }
+
+    #[test]
+    fn detecting_languages_from_contents() {
+        let detect = |path: &str, text: &str| {
+            Language::detect(Path::new(path), &[], || Some(text.as_bytes().to_vec()))
+        };
+        assert_eq!(
+            Language::Python,
+            detect("bin/a", "#!/usr/bin/python3.11\nprint(1)\n")
+        );
+        assert_eq!(Language::Bash, detect("bin/a", "#!/bin/sh\necho 1\n"));
+        assert_eq!(
+            Language::Javascript,
+            detect("bin/a", "#!/usr/bin/env -S NODE_ENV=1 node --harmony\n")
+        );
+        assert_eq!(Language::Ruby, detect("bin/a", "#!/usr/bin/env ruby -w\n"));
+        assert_eq!(Language::Unsupported, detect("bin/a", "#!/usr/bin/perl\n"));
+        assert_eq!(
+            Language::Ruby,
+            detect("a", "# -*- mode: ruby; coding: utf-8 -*-\n")
+        );
+        assert_eq!(
+            Language::Python,
+            detect("a", "#!/bin/tool\n# -*- python -*-\n")
+        );
+        assert_eq!(
+            Language::Bash,
+            detect("a", "# vim: set ft=zsh ts=4:\necho\n")
+        );
+        assert_eq!(
+            Language::Rust,
+            detect("a", "fn main() {}\n\n// vim: syntax=rust\n")
+        );
+        assert_eq!(Language::Unsupported, detect("a", "nothing to see here\n"));
+        // the contents only matter when the name doesn't tell
+        assert_eq!(Language::Go, detect("a.go", "#!/bin/sh\n"));
+        assert_eq!(Language::Unsupported, detect("a.txt", "#!/bin/sh\n"));
+    }
+
+    #[test]
+    fn disambiguating_extensions() {
+        let detect = |path: &str, text: &str| {
+            Language::detect(Path::new(path), &[], || Some(text.as_bytes().to_vec()))
+        };
+        assert_eq!(
+            Language::C,
+            detect("a.h", "int f(void);\nstruct s { int x; };\n")
+        );
+        assert_eq!(
+            Language::Cpp,
+            detect("a.h", "#pragma once\nclass A {\n  int x;\n};\n")
+        );
+        assert_eq!(
+            Language::Cpp,
+            detect("a.h", "#include <vector>\nint f();\n")
+        );
+        // C++ parses all of this, so the tie goes to C
+        assert_eq!(Language::C, detect("a.h", "#define N 1\n"));
+        assert_eq!(
+            Language::C,
+            Language::detect(Path::new("a.h"), &[], || None)
+        );
+        // without hints it's the grammar with the fewest errors
+        let candidates = [Language::Ruby, Language::Rust];
+        assert_eq!(
+            Some(Language::Rust),
+            Language::disambiguate(
+                &candidates,
+                b"fn f() -> u8 {\n    let x: u8 = 1;\n    x\n}\n"
+            )
+        );
+        assert_eq!(
+            Some(Language::Ruby),
+            Language::disambiguate(&candidates, b"def f\n  puts 1\nend\n")
+        );
+        // mappings aren't second-guessed
+        let mappings = [Mapping::from_str("ext:h=c").unwrap()];
+        assert_eq!(
+            Language::C,
+            Language::detect(Path::new("a.h"), &mappings, || Some(
+                b"class A {};".to_vec()
+            ))
+        );
+    }
+}
@@ -1,112 +1,352 @@
+use git2::Oid;
+use rayon::iter::Either;
 use rayon::prelude::*;
-use std::collections::{HashMap, HashSet};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::io::{self, Read};
 use std::iter::FromIterator;
 use std::path::{Path, PathBuf};
 use std::process;
-use structopt::StructOpt;
+use std::time::Instant;
 
+mod baseline;
+mod check;
 mod cli;
+mod config;
 mod count;
+mod diff;
 mod error;
 mod fs;
+mod git;
+mod history;
+mod hotspots;
 mod language;
 mod output;
+mod patch;
 mod query;
+mod ratchet;
 mod tree;
 
+use baseline::Baseline;
 use cli::{GroupBy, SortBy};
-use count::Counts;
+use count::{Counts, FileCounts};
 use error::{Error, Result};
+use git::{Changes, Rev, Tracked};
 use language::Language;
 use output::print;
 
+/// Whether files of @lang should be counted based on --whitelist and --blacklist
+fn is_counted(lang: &Language, whitelist: &HashSet<String>, blacklist: &HashSet<String>) -> bool {
+    if whitelist.is_empty() {
+        blacklist.is_empty() || !blacklist.contains(&lang.to_string())
+    } else {
+        whitelist.contains(&lang.to_string())
+    }
+}
+
+/// Where the files under each path are found
+enum Source<'a> {
+    /// Walk the working directory
+    Worktree,
+    /// Only the files in the working directory which changed, see --changed-since
+    Changed(&'a Changes),
+    /// The tree of a revision, see --rev
+    Rev(&'a Rev),
+    /// The files in the working directory which are tracked in the index, see --git-files
+    Tracked(&'a Tracked),
+}
+
+/// Every file under @paths in @source, which is the working directory or a selection of its files
+fn list_paths<'a>(
+    paths: &'a [impl AsRef<Path>],
+    cli: &cli::Cli,
+    source: &'a Source,
+) -> impl ParallelIterator<Item = Result<PathBuf>> + 'a {
+    let selected = match source {
+        Source::Changed(changes) => Some(changes.select(paths, cli.count_hidden)),
+        Source::Tracked(tracked) => Some(tracked.select(paths, cli.count_hidden)),
+        _ => None,
+    };
+    match selected {
+        Some((files, errors)) => Either::Right(
+            files
+                .into_par_iter()
+                .map(Ok)
+                .chain(errors.into_par_iter().map(Err)),
+        ),
+        None => Either::Left(fs::iter_paths(
+            paths,
+            cli.no_git,
+            cli.count_hidden,
+            cli.no_dot_ignore,
+            cli.no_parent_ignore,
+        )),
+    }
+}
+
 fn get_counts_for_paths(
     paths: &[impl AsRef<Path>],
     cli: &cli::Cli,
+    source: &Source,
     whitelist: &HashSet<String>,
     blacklist: &HashSet<String>,
 ) -> (Vec<(Language, PathBuf, Counts)>, Vec<Error>) {
-    let (file_counts, errors): (Vec<_>, Vec<_>) = fs::iter_paths(
+    let files = match source {
+        Source::Rev(rev) => return get_counts_for_rev(paths, cli, rev, whitelist, blacklist),
+        _ => list_paths(paths, cli, source),
+    };
+    let (file_counts, errors): (Vec<_>, Vec<_>) = files
+        .map(|res| {
+            let path = res?;
+            let lang = Language::detect(path.as_ref(), &cli.map, || {
+                language::read_head(path.as_ref())
+            });
+            if is_counted(&lang, whitelist, blacklist) {
+                let counts =
+                    Counts::from_path(&path, &lang, &cli.kind, &cli.kind_pattern, &cli.query)?;
+                Ok((lang, path, counts))
+            } else {
+                Err(Error::LanguageIgnored(path, lang))
+            }
+        })
+        .partition(Result::is_ok);
+    (
+        file_counts.into_iter().map(Result::unwrap).collect(),
+        errors.into_iter().map(Result::unwrap_err).collect(),
+    )
+}
+
+/// Count the files under @paths in the tree of @rev. Blobs are read from the repository one at a
+/// time since it can't be shared between threads, then parsed in parallel. Blobs which are in
+/// `rev.cache` aren't read or parsed again.
+fn get_counts_for_rev(
+    paths: &[impl AsRef<Path>],
+    cli: &cli::Cli,
+    rev: &Rev,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> (Vec<(Language, PathBuf, Counts)>, Vec<Error>) {
+    let repo = match rev.open() {
+        Ok(repo) => repo,
+        Err(err) => return (Vec::new(), vec![err]),
+    };
+    let (files, mut errors) = rev.files(
+        &repo,
         paths,
         cli.no_git,
         cli.count_hidden,
         cli.no_dot_ignore,
         cli.no_parent_ignore,
-    )
-    .map(|res| {
-        let path = res?;
-        let lang = Language::from(path.as_ref());
-        let ignore_path = if whitelist.is_empty() {
-            blacklist.is_empty() || !blacklist.contains(&lang.to_string())
-        } else {
-            whitelist.contains(&lang.to_string())
-        };
-
-        if ignore_path {
-            let counts = Counts::from_path(&path, &lang, &cli.kind, &cli.kind_pattern, &cli.query)?;
-            Ok((lang, path, counts))
-        } else {
-            Err(Error::LanguageIgnored(path, lang))
+    );
+    let mut file_counts = Vec::new();
+    let mut texts = Vec::new();
+    {
+        let cache = rev.cache.lock().unwrap();
+        for (path, id) in files {
+            let lang = Language::detect(path.as_ref(), &cli.map, || git::read_head(&repo, id));
+            if !is_counted(&lang, whitelist, blacklist) {
+                errors.push(Error::LanguageIgnored(path, lang));
+                continue;
+            }
+            if let Some(counts) = cache.get(&(id, lang.clone())) {
+                file_counts.push((lang, path, counts.clone()));
+                continue;
+            }
+            let text = match lang.get_treesitter_language() {
+                Ok(_) => git::read(&repo, id),
+                // unsupported languages aren't parsed so there's no need to read them
+                Err(_) => Ok(String::new()),
+            };
+            match text {
+                Ok(text) => texts.push((lang, path, id, text)),
+                Err(err) => errors.push(err),
+            }
         }
-    })
-    .partition(Result::is_ok);
+    }
+
+    let (counted, count_errors): (Vec<_>, Vec<_>) = texts
+        .into_par_iter()
+        .map(|(lang, path, id, text)| {
+            let counts = Counts::from_text(
+                &path,
+                &text,
+                &lang,
+                &cli.kind,
+                &cli.kind_pattern,
+                &cli.query,
+            )?;
+            Ok((lang, path, id, counts))
+        })
+        .partition(Result::is_ok);
+    errors.extend(count_errors.into_iter().map(Result::unwrap_err));
+    let mut cache = rev.cache.lock().unwrap();
+    for (lang, path, id, counts) in counted.into_iter().map(Result::unwrap) {
+        cache.insert((id, lang.clone()), counts.clone());
+        file_counts.push((lang, path, counts));
+    }
+    (file_counts, errors)
+}
+
+/// Count every file under @paths in the working directory with the counts of each file split
+/// between the authors of its lines, from git blame. Each author of a file gets its own FileCounts.
+fn get_author_counts(
+    paths: &[PathBuf],
+    cli: &cli::Cli,
+    source: &Source,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> (Vec<FileCounts>, Vec<Error>) {
+    let blamer = match git::Blamer::discover() {
+        Ok(blamer) => blamer,
+        Err(err) => return (Vec::new(), vec![err]),
+    };
+    let (files, errors): (Vec<_>, Vec<_>) = list_paths(paths, cli, source)
+        .map_init(
+            || blamer.open(),
+            |repo, res| {
+                let path = res?;
+                let lang = Language::detect(path.as_ref(), &cli.map, || {
+                    language::read_head(path.as_ref())
+                });
+                if !is_counted(&lang, whitelist, blacklist) {
+                    return Err(Error::LanguageIgnored(path, lang));
+                }
+                let repo = repo.as_ref().map_err(|err| {
+                    Error::Git(git2::Error::new(err.code(), err.class(), err.message()))
+                })?;
+                let (text, blame) = match lang.get_treesitter_language() {
+                    Ok(_) => {
+                        let text = std::fs::read_to_string(&path)?;
+                        let blame = blamer.blame(repo, &path, text.as_bytes())?;
+                        (text, blame)
+                    }
+                    // unsupported languages aren't parsed, and may not be text, but their lines are
+                    // still blamed so the file belongs to whoever wrote most of it
+                    Err(_) => (
+                        String::new(),
+                        blamer.blame(repo, &path, &std::fs::read(&path)?)?,
+                    ),
+                };
+                let counts = Counts::from_text_by_owner(
+                    &path,
+                    &text,
+                    &lang,
+                    (&cli.kind, &cli.kind_pattern, &cli.query),
+                    &blame.owners,
+                )?;
+                // only the authors who own a line, or the file when it has no lines, are kept
+                let owned: HashSet<usize> = blame.owners.iter().map(|(owner, _)| *owner).collect();
+                Ok(blame
+                    .authors
+                    .into_iter()
+                    .zip(counts)
+                    .enumerate()
+                    .filter(|(owner, (_, counts))| owned.contains(owner) || counts.nfiles > 0)
+                    .map(|(_, (author, counts))| FileCounts {
+                        group: author,
+                        lang: lang.clone(),
+                        path: path.clone(),
+                        counts,
+                    })
+                    .collect::<Vec<FileCounts>>())
+            },
+        )
+        .partition(Result::is_ok);
     (
-        file_counts.into_iter().map(Result::unwrap).collect(),
+        files.into_iter().flat_map(Result::unwrap).collect(),
         errors.into_iter().map(Result::unwrap_err).collect(),
     )
 }
 
-fn run(cli: cli::Cli) -> Result<()> {
-    let whitelist: HashSet<String> = HashSet::from_iter(cli.whitelist.iter().cloned());
-    let blacklist: HashSet<String> = HashSet::from_iter(cli.blacklist.iter().cloned());
-
-    let (mut counts, errors): (Vec<(String, Counts)>, Vec<Error>) = match cli.groupby {
-        GroupBy::Language => {
-            let (counts, errors) = get_counts_for_paths(&cli.paths, &cli, &whitelist, &blacklist);
-            let counts = counts
+/// Count every file under @paths and label each one with the group it belongs to based on
+/// --groupby
+fn get_file_counts(
+    cli: &cli::Cli,
+    paths: &[PathBuf],
+    source: &Source,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> (Vec<FileCounts>, Vec<Error>) {
+    match cli.groupby {
+        GroupBy::Author => get_author_counts(paths, cli, source, whitelist, blacklist),
+        GroupBy::Language | GroupBy::File => {
+            let (counts, errors) = get_counts_for_paths(paths, cli, source, whitelist, blacklist);
+            let files = counts
                 .into_iter()
-                .fold(HashMap::new(), |mut acc, (lang, _path, counts)| {
-                    if let Some(cur) = acc.get_mut(&lang.to_string()) {
-                        *cur += counts;
-                    } else {
-                        acc.insert(lang.to_string(), counts);
-                    }
-                    acc
+                .map(|(lang, path, counts)| FileCounts {
+                    group: match cli.groupby {
+                        GroupBy::Language => lang.to_string(),
+                        _ => path.display().to_string(),
+                    },
+                    lang,
+                    path,
+                    counts,
                 })
-                .into_iter()
                 .collect();
-            (counts, errors)
-        }
-        GroupBy::File => {
-            let (counts, errors) = get_counts_for_paths(&cli.paths, &cli, &whitelist, &blacklist);
-            let counts = counts
-                .into_iter()
-                .map(|(_lang, path, count)| (path.display().to_string(), count))
-                .collect();
-            (counts, errors)
+            (files, errors)
         }
         GroupBy::Arg => {
-            let (counts, errors): (Vec<_>, Vec<_>) = cli
-                .paths
+            let (files, errors): (Vec<_>, Vec<_>) = paths
                 .par_iter()
-                .map(|path| {
+                .map(|arg| {
                     let (counts, errors) =
-                        get_counts_for_paths(&[path], &cli, &whitelist, &blacklist);
-                    let counts = counts.into_iter().fold(
-                        Counts::empty(cli.kind.len(), cli.kind_pattern.len(), &cli.query),
-                        |mut acc, (_lang, _path, counts)| {
-                            acc += counts;
-                            acc
-                        },
-                    );
-                    ((path.display().to_string(), counts), errors)
+                        get_counts_for_paths(&[arg], cli, source, whitelist, blacklist);
+                    let files: Vec<FileCounts> = counts
+                        .into_iter()
+                        .map(|(lang, path, counts)| FileCounts {
+                            group: arg.display().to_string(),
+                            lang,
+                            path,
+                            counts,
+                        })
+                        .collect();
+                    (files, errors)
                 })
                 .unzip();
-            (counts, errors.into_iter().flatten().collect())
+            (
+                files.into_iter().flatten().collect(),
+                errors.into_iter().flatten().collect(),
+            )
         }
-    };
+    }
+}
 
-    match cli.sort_by {
+/// Groups which always get a row, even if they have no files. When grouping by argument this is
+/// each of @paths.
+fn seed_groups(cli: &cli::Cli, paths: &[PathBuf]) -> Vec<String> {
+    match cli.groupby {
+        GroupBy::Arg => paths
+            .iter()
+            .map(|path| path.display().to_string())
+            .collect(),
+        _ => Vec::new(),
+    }
+}
+
+/// Sum the counts of @files for each group. Groups in @seed are always present, even if none of
+/// @files belong to them.
+fn group_counts(files: &[FileCounts], seed: &[String], cli: &cli::Cli) -> Vec<(String, Counts)> {
+    let empty = Counts::empty(cli.kind.len(), cli.kind_pattern.len(), &cli.query);
+    let init: HashMap<String, Counts> = seed
+        .iter()
+        .map(|group| (group.clone(), empty.clone()))
+        .collect();
+    files
+        .iter()
+        .fold(init, |mut acc, file| {
+            if let Some(cur) = acc.get_mut(&file.group) {
+                *cur += file.counts.clone();
+            } else {
+                acc.insert(file.group.clone(), file.counts.clone());
+            }
+            acc
+        })
+        .into_iter()
+        .collect()
+}
+
+fn sort_counts(counts: &mut [(String, Counts)], sort_by: &SortBy) {
+    match sort_by {
         // sort asc lexographical order on either language or file
         SortBy::Group => counts.sort_by(|(l1, _c1), (l2, _c2)| l1.cmp(l2)),
         // sort desc numerical order
@@ -114,8 +354,217 @@ fn run(cli: cli::Cli) -> Result<()> {
         // sort desc numerical order
         SortBy::Tokens => counts.sort_by(|(_l1, c1), (_l2, c2)| c2.ntokens.cmp(&c1.ntokens)),
     }
+}
+
+fn run(cli: cli::Cli) -> Result<()> {
+    let started = Instant::now();
+    let whitelist: HashSet<String> = HashSet::from_iter(cli.whitelist.iter().cloned());
+    let blacklist: HashSet<String> = HashSet::from_iter(cli.blacklist.iter().cloned());
+
+    let blame = cli.groupby == GroupBy::Author;
+    if blame && (cli.command.is_some() || cli.rev.is_some() || cli.staged || cli.compare_base) {
+        return Err(Error::Blame(String::from(
+            "--groupby=author only counts the working directory so it can't be used with --rev, --staged, --compare-base, or a subcommand",
+        )));
+    }
+    if !cli.check.is_empty() {
+        if cli.command.is_some() {
+            return Err(Error::Check(String::from(
+                "--check only checks the counts of the files so it can't be used with a subcommand",
+            )));
+        }
+        if (cli.rev.is_some() || cli.staged)
+            && cli
+                .check
+                .iter()
+                .any(|rule| rule.per == check::Per::Definition)
+        {
+            return Err(Error::Check(String::from(
+                "rules per definition only check the working directory so they can't be used with --rev or --staged",
+            )));
+        }
+    }
+    if cli.transpose {
+        let json = match &cli.command {
+            Some(cli::Command::History(history)) => {
+                matches!(history.format, history::Format::Json)
+            }
+            Some(cli::Command::Diff(cli::Diff { format, .. }))
+            | Some(cli::Command::Patch(cli::Patch { format, .. })) => {
+                matches!(format, diff::Format::Json)
+            }
+            Some(cli::Command::Hotspots(hotspots)) => {
+                matches!(hotspots.format, hotspots::Format::Json)
+            }
+            Some(cli::Command::Ratchet(_)) => {
+                return Err(Error::Format(String::from(
+                    "ratchet doesn't print a table so it can't be used with --transpose",
+                )));
+            }
+            None => false,
+        };
+        if json {
+            return Err(Error::Format(String::from(
+                "JSON has no rows or columns so --format=json can't be used with --transpose",
+            )));
+        }
+    }
+    if cli.sort_metrics.is_some() && cli.command.is_some() {
+        return Err(Error::Format(String::from(
+            "--sort-metrics only sorts the counts of groups so it can't be used with a subcommand",
+        )));
+    }
+    let columns = output::columns(&cli.kind, &cli.kind_pattern, &cli.query, blame);
+    let treemap_column = columns
+        .iter()
+        .position(|column| column.key() == cli.treemap_column)
+        .ok_or_else(|| Error::UnknownColumn(cli.treemap_column.clone()))?;
+    if let Some(cli::Command::History(history)) = &cli.command {
+        let layout = output::Layout::new(&columns, &cli.columns, &cli.hide, &cli.header)?;
+        return run_history(&cli, history, &columns, &layout, &whitelist, &blacklist);
+    }
+    if let Some(cli::Command::Diff(diff)) = &cli.command {
+        return run_diff(&cli, diff, &whitelist, &blacklist);
+    }
+    if let Some(cli::Command::Patch(patch)) = &cli.command {
+        return run_patch(&cli, patch, &columns, &whitelist, &blacklist);
+    }
+    if let Some(cli::Command::Hotspots(hotspots)) = &cli.command {
+        return run_hotspots(&cli, hotspots, &columns, &whitelist, &blacklist);
+    }
+    if let Some(cli::Command::Ratchet(ratchet)) = &cli.command {
+        return run_ratchet(&cli, ratchet, &columns, &whitelist, &blacklist);
+    }
+    let rev = if cli.staged {
+        Some(Rev::staged()?)
+    } else {
+        cli.rev.as_deref().map(Rev::resolve).transpose()?
+    };
+    let changes = cli
+        .changed_since
+        .as_deref()
+        .map(|base| Changes::find(base, rev.as_ref()))
+        .transpose()?;
+    let rev = match (rev, &changes) {
+        (Some(rev), Some(changes)) => Some(rev.restrict(&changes.new)),
+        (rev, _) => rev,
+    };
+    let tracked = cli
+        .git_files
+        .then(|| Tracked::find(cli.recurse_submodules))
+        .transpose()?;
+    let source = match (&rev, &changes, &tracked) {
+        (Some(rev), _, _) => Source::Rev(rev),
+        (None, Some(changes), _) => Source::Changed(changes),
+        (None, None, Some(tracked)) => Source::Tracked(tracked),
+        (None, None, None) => Source::Worktree,
+    };
 
-    let totals: Option<Counts> = if cli.show_totals {
+    let baseline = match &changes {
+        Some(changes) if cli.compare_base => {
+            // the same files as they were in the merge base. Files which were added don't exist
+            // there so the errors aren't useful
+            let repo = git2::Repository::discover(".")?;
+            let base = Rev::new(
+                &repo,
+                &changes.base.to_string(),
+                &repo.find_object(changes.base, None)?,
+                git::Cache::default(),
+            )?
+            .restrict(&changes.old);
+            let (files, _) = get_file_counts(
+                &cli,
+                &cli.paths,
+                &Source::Rev(&base),
+                &whitelist,
+                &blacklist,
+            );
+            let counts = group_counts(&files, &seed_groups(&cli, &cli.paths), &cli);
+            Some(Baseline::new(&cli.groupby.to_string(), &columns, &counts))
+        }
+        _ => cli
+            .compare
+            .as_ref()
+            .map(|path| Baseline::load(path, &cli.groupby.to_string()))
+            .transpose()?,
+    };
+    let mut options = output::Options {
+        format: &cli.format,
+        columns: &columns,
+        layout: output::Layout::new(&columns, &cli.columns, &cli.hide, &cli.header)?,
+        human: table_style(&cli).human,
+        chart: cli.chart,
+        transpose: cli.transpose,
+        sort_metrics: cli.sort_metrics.unwrap_or(output::SortMetrics::Columns),
+        treemap_column,
+        comparison: None,
+        started,
+    };
+
+    let template = cli
+        .template
+        .as_ref()
+        .map(output::template::Template::from_path)
+        .transpose()?;
+
+    let (files, errors) = get_file_counts(&cli, &cli.paths, &source, &whitelist, &blacklist);
+
+    if !cli.check.is_empty() {
+        // a query which is missing is reported when a rule needs it
+        let definitions = cli
+            .check
+            .iter()
+            .any(|rule| rule.per == check::Per::Definition)
+            .then(|| "definition@definition,name".parse::<query::Query>().ok())
+            .flatten();
+        let violations = check::check(
+            &cli.check,
+            &columns,
+            &files,
+            (&cli.kind_pattern, &cli.query),
+            definitions.as_ref(),
+            |path| Ok(std::fs::read_to_string(path)?),
+        )?;
+        check::print(&cli.check_format, &cli.check, &violations);
+        print_errors(errors, cli.verbose);
+        return if violations.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Check(format!(
+                "the rules were broken {} times",
+                violations.len()
+            )))
+        };
+    }
+
+    let mut counts = group_counts(&files, &seed_groups(&cli, &cli.paths), &cli);
+
+    if let Some(path) = &cli.sqlite {
+        // with --rev the commit that was counted is recorded rather than the one checked out, and
+        // the index is staged on top of the one checked out
+        let revision = match &source {
+            Source::Rev(rev) if !cli.staged => rev.commit,
+            _ => git::head(),
+        };
+        output::sqlite::write(
+            path,
+            revision.map(|commit| commit.to_string()),
+            &cli.args.join(" "),
+            &columns,
+            &files,
+        )?;
+    }
+
+    sort_counts(&mut counts, &cli.sort_by);
+
+    if let Some(path) = &cli.save_baseline {
+        Baseline::new(&cli.groupby.to_string(), &columns, &counts).save(path)?;
+    }
+    // groups removed since the baseline are found before --top leaves any out
+    options.comparison = baseline.map(|baseline| baseline.compare(&counts));
+
+    // templates always have the totals available
+    let totals: Option<Counts> = if cli.show_totals || template.is_some() {
         Some(counts.iter().fold(
             Counts::empty(cli.kind.len(), cli.kind_pattern.len(), &cli.query),
             |mut cur, (_, counts)| {
@@ -133,30 +582,530 @@ fn run(cli: cli::Cli) -> Result<()> {
         counts
     };
 
-    if !counts.is_empty() {
-        print(
-            &cli.format,
-            counts,
-            totals,
-            &cli.kind,
-            &cli.kind_pattern,
-            &cli.query,
+    if let Some(template) = template {
+        let totals = totals
+            .unwrap_or_else(|| Counts::empty(cli.kind.len(), cli.kind_pattern.len(), &cli.query));
+        print!(
+            "{}",
+            template.render(&output::context(&options, &counts, &totals, &files))?
         );
+    } else if !counts.is_empty() {
+        print(&options, counts, totals, &files);
+        if cli.bus_factor && blame && matches!(cli.format, output::Format::Table) {
+            output::bus_factor_table(&files, table_style(&cli)).printstd();
+        }
     } else {
         println!("No files found.");
     }
 
+    print_errors(errors, cli.verbose);
+    Ok(())
+}
+
+/// Count each commit sampled by `tcount history` and print the counts of each group over time.
+/// Blobs which don't change between commits are only counted once.
+fn run_history(
+    cli: &cli::Cli,
+    history: &cli::History,
+    columns: &[output::Column],
+    layout: &output::Layout,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> Result<()> {
+    let repo = git2::Repository::discover(".")?;
+    let tip = repo
+        .revparse_single(cli.rev.as_deref().unwrap_or("HEAD"))?
+        .peel_to_commit()?
+        .id();
+    let seed = seed_groups(cli, &history.paths);
+    let cache = git::Cache::default();
+    let mut points = Vec::new();
+    let mut errors = Vec::new();
+    for commit in history::sample(&repo, tip, history.since, history.every, history.commits)? {
+        let name = commit.as_object().short_id()?;
+        let rev = Rev::new(
+            &repo,
+            name.as_str().unwrap_or_default(),
+            commit.as_object(),
+            cache.clone(),
+        )?;
+        let (files, commit_errors) = get_file_counts(
+            cli,
+            &history.paths,
+            &Source::Rev(&rev),
+            whitelist,
+            blacklist,
+        );
+        let mut counts = group_counts(&files, &seed, cli);
+        sort_counts(&mut counts, &cli.sort_by);
+        points.push(history::Point {
+            commit: commit.id(),
+            time: commit.time().seconds(),
+            counts,
+        });
+        errors.extend(commit_errors);
+    }
+    history::print(&history.format, columns, layout, &points, cli.transpose);
+
+    print_errors(errors, cli.verbose);
+    Ok(())
+}
+
+/// Find the churn of each file under @paths which changed between the trees of @from and @to by
+/// comparing their tokens
+fn get_churn_for_paths(
+    paths: &[impl AsRef<Path>],
+    cli: &cli::Cli,
+    (from, to): (&Rev, &Rev),
+    repo: &git2::Repository,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> (Vec<(Language, PathBuf, diff::Churn)>, Vec<Error>) {
+    let list = |rev: &Rev| {
+        rev.files(
+            repo,
+            paths,
+            cli.no_git,
+            cli.count_hidden,
+            cli.no_dot_ignore,
+            cli.no_parent_ignore,
+        )
+    };
+    let (old_files, mut errors) = list(from);
+    let (new_files, new_errors) = list(to);
+    // paths which were added since @from don't exist in it
+    errors.retain(|err| !matches!(err, Error::IO(err) if err.kind() == io::ErrorKind::NotFound));
+    errors.extend(new_errors);
+
+    // each file which changed, with its blob in each revision if it exists
+    let mut changed: BTreeMap<PathBuf, (Option<Oid>, Option<Oid>)> = BTreeMap::new();
+    for (path, id) in old_files {
+        changed.entry(path).or_default().0 = Some(id);
+    }
+    for (path, id) in new_files {
+        changed.entry(path).or_default().1 = Some(id);
+    }
+    let mut texts = Vec::new();
+    for (path, (old, new)) in changed {
+        let lang = Language::detect(path.as_ref(), &cli.map, || {
+            new.or(old).and_then(|id| git::read_head(repo, id))
+        });
+        if old == new || lang.get_treesitter_language().is_err() {
+            continue;
+        }
+        if !is_counted(&lang, whitelist, blacklist) {
+            errors.push(Error::LanguageIgnored(path, lang));
+            continue;
+        }
+        let read = |id: Option<Oid>| id.map_or(Ok(String::new()), |id| git::read(repo, id));
+        match (read(old), read(new)) {
+            (Ok(old), Ok(new)) => texts.push((lang, path, old, new)),
+            (Err(err), _) | (_, Err(err)) => errors.push(err),
+        }
+    }
+
+    let (churn, churn_errors): (Vec<_>, Vec<_>) = texts
+        .into_par_iter()
+        .map(|(lang, path, old, new)| {
+            let churn = diff::churn(
+                &diff::tokens(&path, &old, &lang)?,
+                &diff::tokens(&path, &new, &lang)?,
+            );
+            Ok((lang, path, churn))
+        })
+        .partition(Result::is_ok);
+    errors.extend(churn_errors.into_iter().map(Result::unwrap_err));
+    (
+        churn
+            .into_iter()
+            .map(Result::unwrap)
+            .filter(|(_, _, churn)| churn.nfiles > 0)
+            .collect(),
+        errors,
+    )
+}
+
+/// Compare the tokens of each file between the revisions given to `tcount diff` and print the
+/// churn of each group
+fn run_diff(
+    cli: &cli::Cli,
+    diff: &cli::Diff,
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> Result<()> {
+    let (from_name, to_name) = diff::parse_range(&diff.range);
+    let from = Rev::resolve(from_name)?;
+    let to = Rev::resolve(to_name)?;
+    let repo = from.open()?;
+
+    // the paths are compared together unless each one is its own group
+    let args: Vec<Vec<&PathBuf>> = match cli.groupby {
+        GroupBy::Arg => diff.paths.iter().map(|path| vec![path]).collect(),
+        _ => vec![diff.paths.iter().collect()],
+    };
+    let mut files = Vec::new();
+    let mut errors = Vec::new();
+    for paths in args {
+        let (churn, path_errors) =
+            get_churn_for_paths(&paths, cli, (&from, &to), &repo, whitelist, blacklist);
+        files.extend(
+            churn
+                .into_iter()
+                .map(|(lang, path, churn)| diff::FileChurn {
+                    group: match cli.groupby {
+                        GroupBy::Language => lang.to_string(),
+                        GroupBy::File => path.display().to_string(),
+                        GroupBy::Arg => paths[0].display().to_string(),
+                        GroupBy::Author => unreachable!("--groupby=author is rejected by run"),
+                    },
+                    lang,
+                    path,
+                    churn,
+                }),
+        );
+        errors.extend(path_errors);
+    }
+
+    let (groups, totals) = group_churn(&files, seed_groups(cli, &diff.paths), cli);
+
+    diff::print(
+        &diff.format,
+        Some((from_name, to_name)),
+        &[],
+        &groups,
+        totals,
+        &files,
+        table_style(cli),
+    );
+
+    print_errors(errors, cli.verbose);
+    Ok(())
+}
+
+/// Sum the churn of @files for each group, sorted by --sort-by and cut to --top, along with the
+/// totals of every group if --show-totals is set. Groups in @seed are always present.
+fn group_churn(
+    files: &[diff::FileChurn],
+    seed: Vec<String>,
+    cli: &cli::Cli,
+) -> (Vec<(String, diff::Churn)>, Option<diff::Churn>) {
+    let mut groups: Vec<(String, diff::Churn)> = seed
+        .into_iter()
+        .map(|group| (group, diff::Churn::default()))
+        .collect();
+    for file in files {
+        match groups.iter_mut().find(|(group, _)| *group == file.group) {
+            Some((_, churn)) => *churn += &file.churn,
+            None => groups.push((file.group.clone(), file.churn.clone())),
+        }
+    }
+    match cli.sort_by {
+        SortBy::Group => groups.sort_by(|(g1, _), (g2, _)| g1.cmp(g2)),
+        SortBy::NumFiles => groups.sort_by_key(|(_, churn)| Reverse(churn.nfiles)),
+        // the most churn first
+        SortBy::Tokens => groups.sort_by_key(|(_, churn)| Reverse(churn.added + churn.removed)),
+    }
+    let totals = cli.show_totals.then(|| {
+        groups
+            .iter()
+            .fold(diff::Churn::default(), |mut acc, (_, churn)| {
+                acc += churn;
+                acc
+            })
+    });
+    if let Some(n) = cli.top {
+        groups.truncate(n);
+    }
+    (groups, totals)
+}
+
+/// Rebuild each file changed by the unified diff on stdin from the working directory, before and
+/// after the diff, and print the churn of each group for `tcount patch`. The working directory
+/// isn't changed.
+fn run_patch(
+    cli: &cli::Cli,
+    patch: &cli::Patch,
+    columns: &[output::Column],
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> Result<()> {
+    let mut text = String::new();
+    io::stdin().read_to_string(&mut text)?;
+    let file_patches = patch::parse(&text).map_err(Error::Patch)?;
+
+    let mut errors = Vec::new();
+    let mut texts = Vec::new();
+    for file in file_patches {
+        let path = match file.path() {
+            Some(path) => path.clone(),
+            None => continue,
+        };
+        // the argument the file is under, which is its group with --groupby=arg
+        let arg = match patch
+            .paths
+            .iter()
+            .find(|arg| git::normalize(&path).starts_with(git::normalize(arg)))
+        {
+            Some(arg) => arg,
+            None => continue,
+        };
+        let lang = Language::detect(path.as_ref(), &cli.map, || {
+            file.old.as_ref().and_then(|old| language::read_head(old))
+        });
+        if file.binary || lang.get_treesitter_language().is_err() {
+            continue;
+        }
+        if !is_counted(&lang, whitelist, blacklist) {
+            errors.push(Error::LanguageIgnored(path, lang));
+            continue;
+        }
+        let pre = match &file.old {
+            Some(old) => match std::fs::read_to_string(old) {
+                Ok(pre) => pre,
+                Err(err) => {
+                    errors.push(err.into());
+                    continue;
+                }
+            },
+            // added files start out empty
+            None => String::new(),
+        };
+        match patch::apply(&pre, &file.hunks) {
+            Ok(post) => texts.push((arg, lang, path, pre, post)),
+            Err(err) => errors.push(Error::Patch(format!("{}: {}", path.display(), err))),
+        }
+    }
+
+    let (files, churn_errors): (Vec<_>, Vec<_>) = texts
+        .into_par_iter()
+        .map(|(arg, lang, path, pre, post)| {
+            let count = |text: &str| {
+                Counts::from_text(&path, text, &lang, &cli.kind, &cli.kind_pattern, &cli.query)
+            };
+            let (old, new) = (count(&pre)?.values(), count(&post)?.values());
+            let mut churn = diff::churn(
+                &diff::tokens(&path, &pre, &lang)?,
+                &diff::tokens(&path, &post, &lang)?,
+            );
+            // every count after files and tokens
+            churn.deltas = old
+                .iter()
+                .zip(&new)
+                .skip(2)
+                .map(|(old, new)| *new as i64 - *old as i64)
+                .collect();
+            Ok(diff::FileChurn {
+                group: match cli.groupby {
+                    GroupBy::Language => lang.to_string(),
+                    GroupBy::File => path.display().to_string(),
+                    GroupBy::Arg => arg.display().to_string(),
+                    GroupBy::Author => unreachable!("--groupby=author is rejected by run"),
+                },
+                lang,
+                path,
+                churn,
+            })
+        })
+        .partition(Result::is_ok);
+    errors.extend(churn_errors.into_iter().map(Result::unwrap_err));
+    let files: Vec<diff::FileChurn> = files
+        .into_iter()
+        .map(Result::unwrap)
+        .filter(|file| file.churn.nfiles > 0 || file.churn.deltas.iter().any(|delta| *delta != 0))
+        .collect();
+
+    let (groups, totals) = group_churn(&files, seed_groups(cli, &patch.paths), cli);
+    diff::print(
+        &patch.format,
+        None,
+        &columns[2..],
+        &groups,
+        totals,
+        &files,
+        table_style(cli),
+    );
+
+    print_errors(errors, cli.verbose);
+    Ok(())
+}
+
+/// Count the files given to `tcount hotspots` along with the number of commits which changed each
+/// one and print them from the biggest hotspot to the smallest
+fn run_hotspots(
+    cli: &cli::Cli,
+    hotspots: &cli::Hotspots,
+    columns: &[output::Column],
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> Result<()> {
+    if cli.staged || cli.changed_since.is_some() || cli.git_files {
+        return Err(Error::Usage(String::from(
+            "hotspots count the working directory, or --rev, so they can't be used with --staged, --changed-since, or --git-files",
+        )));
+    }
+    let column = columns
+        .iter()
+        .position(|column| column.key() == hotspots.size)
+        .ok_or_else(|| Error::UnknownColumn(hotspots.size.clone()))?;
+    let rev = match &cli.rev {
+        Some(rev) => Rev::resolve(rev)?,
+        None => Rev::resolve("HEAD")?,
+    };
+    let repo = rev.open()?;
+    let tip = rev
+        .commit
+        .ok_or_else(|| git2::Error::from_str("hotspots need the history of a commit"))?;
+    let commits = hotspots::commits(&repo, tip, hotspots.since)?;
+
+    // sizes come from the working directory unless a revision was given
+    let source = match &cli.rev {
+        Some(_) => Source::Rev(&rev),
+        None => Source::Worktree,
+    };
+    let (counts, mut errors) =
+        get_counts_for_paths(&hotspots.paths, cli, &source, whitelist, blacklist);
+    let mut files = Vec::with_capacity(counts.len());
+    for (lang, path, counts) in counts {
+        match git::repo_path(repo.workdir(), &path) {
+            Ok(repo_path) => {
+                let ncommits = commits.get(&repo_path).copied().unwrap_or(0);
+                files.push((path, lang, counts.values()[column], ncommits));
+            }
+            Err(err) => errors.push(err),
+        }
+    }
+    let mut ranked = hotspots::rank(files);
+    if let Some(n) = cli.top {
+        ranked.truncate(n);
+    }
+
+    if ranked.is_empty() {
+        println!("No files found.");
+    } else {
+        hotspots::print(
+            &hotspots.format,
+            &columns[column],
+            &ranked,
+            table_style(cli),
+        );
+    }
+
+    print_errors(errors, cli.verbose);
+    Ok(())
+}
+
+/// Count the files under the paths of `tcount ratchet` and compare each group with its budget.
+/// With --update the budget file is written with the budgets which dropped lowered.
+fn run_ratchet(
+    cli: &cli::Cli,
+    ratchet: &cli::Ratchet,
+    columns: &[output::Column],
+    whitelist: &HashSet<String>,
+    blacklist: &HashSet<String>,
+) -> Result<()> {
+    let groupby = cli.groupby.to_string();
+    let budget = if ratchet.budget.exists() {
+        Baseline::load(&ratchet.budget, &groupby)?
+    } else if ratchet.update {
+        Baseline {
+            groupby: groupby.clone(),
+            groups: Vec::new(),
+        }
+    } else {
+        return Err(Error::Baseline(format!(
+            "{}: there's no budget yet, create it with tcount ratchet --update",
+            ratchet.budget.display()
+        )));
+    };
+    let keys = match (ratchet.columns.is_empty(), ratchet::keys(&budget)) {
+        (false, _) => ratchet.columns.clone(),
+        (true, keys) if !keys.is_empty() => keys,
+        _ => vec![String::from("tokens")],
+    };
+    if let Some(key) = keys
+        .iter()
+        .find(|key| !columns.iter().any(|column| column.key() == **key))
+    {
+        return Err(Error::UnknownColumn(key.clone()));
+    }
+
+    let source = match &cli.rev {
+        Some(rev) => Some(Rev::resolve(rev)?),
+        None => None,
+    };
+    let source = match &source {
+        Some(rev) => Source::Rev(rev),
+        None => Source::Worktree,
+    };
+    let (files, errors) = get_file_counts(cli, &ratchet.paths, &source, whitelist, blacklist);
+    let mut counts = group_counts(&files, &seed_groups(cli, &ratchet.paths), cli);
+    sort_counts(&mut counts, &SortBy::Group);
+    let current = Baseline::new(&groupby, columns, &counts);
+
+    // counts which weren't budgeted yet are recorded by --update rather than exceeding it
+    let budget = if ratchet.update {
+        let (budget, lowered) = ratchet::update(&budget, &current, &keys);
+        budget.save(&ratchet.budget)?;
+        println!(
+            "Lowered {} budgets in {}",
+            lowered,
+            ratchet.budget.display()
+        );
+        budget
+    } else {
+        budget
+    };
+    let exceeded = ratchet::exceeded(&budget, &current, &keys);
+    exceeded.iter().for_each(|exceeded| {
+        println!(
+            "{}: {} {} is more than its budget of {}",
+            exceeded.group, exceeded.value, exceeded.key, exceeded.budget
+        )
+    });
+    if exceeded.is_empty() && !ratchet.update {
+        println!("Every count is within its budget.");
+    }
+
+    print_errors(errors, cli.verbose);
+    if exceeded.is_empty() {
+        Ok(())
+    } else {
+        Err(Error::Check(format!(
+            "{} counts are over their budget in {}",
+            exceeded.len(),
+            ratchet.budget.display()
+        )))
+    }
+}
+
+/// The style of tables from --human and --transpose, where --human without a value uses thousands
+/// separators
+fn table_style(cli: &cli::Cli) -> output::TableStyle {
+    output::TableStyle {
+        human: cli
+            .human
+            .map(|human| human.unwrap_or(output::Human::Thousands)),
+        transpose: cli.transpose,
+    }
+}
+
+fn print_errors(errors: Vec<Error>, verbose: u8) {
     errors
         .into_iter()
-        .filter(|err| err.should_show(cli.verbose))
+        .filter(|err| err.should_show(verbose))
         .for_each(|err| {
             eprintln!("{}", err);
         });
-    Ok(())
 }
 
 fn main() {
-    let cli = cli::Cli::from_args();
+    let cli = match config::parse_args(std::env::args_os().collect()) {
+        Ok(cli) => cli,
+        Err(err) => {
+            eprintln!("{}", err);
+            process::exit(1);
+        }
+    };
 
     if cli.list_languages {
         Language::print_all();
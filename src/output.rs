@@ -1,16 +1,39 @@
-use crate::count::Counts;
+use crate::baseline::Comparison;
+use crate::count::{Counts, FileCounts};
+use crate::diff::Churn;
+use crate::error::Error;
+use crate::hotspots::Hotspot;
 use crate::language::Language;
 use crate::query::{Query, QueryKind};
 use prettytable::{format, Cell, Row, Table};
 use regex::Regex;
+use serde_json::{json, Map, Value};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
 use std::format;
+use std::path::Path;
 use std::str::FromStr;
+use std::time::Instant;
+
+mod chart;
+mod cloc;
+mod html;
+mod openmetrics;
+pub mod sqlite;
+pub mod template;
+mod tokei;
+mod treemap;
 
 #[derive(Debug)]
 pub enum Format {
     Table,
     Csv,
+    Html,
+    OpenMetrics,
+    SvgTreemap,
+    TokeiJson,
+    ClocYaml,
 }
 
 impl FromStr for Format {
@@ -20,7 +43,29 @@ impl FromStr for Format {
         match s {
             "table" => Ok(Format::Table),
             "csv" => Ok(Format::Csv),
-            _ => Err(format!("\"{}\" is not supported. Use one of table|csv", s)),
+            "html" => Ok(Format::Html),
+            "openmetrics" => Ok(Format::OpenMetrics),
+            "svg-treemap" => Ok(Format::SvgTreemap),
+            "tokei-json" => Ok(Format::TokeiJson),
+            "cloc-yaml" => Ok(Format::ClocYaml),
+            _ => Err(format!(
+                "\"{}\" is not supported. Use one of table|csv|html|openmetrics|svg-treemap|tokei-json|cloc-yaml",
+                s
+            )),
+        }
+    }
+}
+
+impl std::fmt::Display for Format {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Format::Table => write!(f, "table"),
+            Format::Csv => write!(f, "csv"),
+            Format::Html => write!(f, "html"),
+            Format::OpenMetrics => write!(f, "openmetrics"),
+            Format::SvgTreemap => write!(f, "svg-treemap"),
+            Format::TokeiJson => write!(f, "tokei-json"),
+            Format::ClocYaml => write!(f, "cloc-yaml"),
         }
     }
 }
@@ -51,8 +96,8 @@ fn label_cell(label: &str) -> Cell {
     Cell::new(label).style_spec("li")
 }
 #[inline]
-fn count_cell(count: u64) -> Cell {
-    Cell::new(&count.to_string()).style_spec("r")
+fn count_cell(count: &str) -> Cell {
+    Cell::new(count).style_spec("r")
 }
 
 #[inline]
@@ -60,83 +105,885 @@ fn generic_cell(s: impl Display) -> Cell {
     Cell::new(&s.to_string()).style_spec("l")
 }
 
-pub fn print(
-    format: &Format,
-    counts: Vec<(String, Counts)>,
-    totals: Option<Counts>,
-    kinds: &Vec<String>,
-    kind_patterns: &Vec<Regex>,
-    queries: &Vec<Query>,
-) {
-    let mut table = Table::new();
-    table.set_format(format_builder().build());
+/// A column of counts, see `Counts::values`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Column {
+    Files,
+    Tokens,
+    Kind(String),
+    Pattern(String),
+    Query(String),
+    Capture(String, String),
+    /// Median age in days of the tokens counted with --groupby=author
+    MedianAge,
+    /// Percent of the tokens counted with --groupby=author which are more than a year old
+    PercentOld,
+}
 
-    let mut titles = Vec::with_capacity(3 + kinds.len() + kind_patterns.len() + queries.len());
-    titles.push(title_cell("Group"));
-    titles.push(title_cell("Files"));
-    titles.push(title_cell("Tokens"));
+impl Column {
+    /// Identifies the column in machine readable output (e.g. "query:comment")
+    pub fn key(&self) -> String {
+        match self {
+            Column::Files => String::from("files"),
+            Column::Tokens => String::from("tokens"),
+            Column::Kind(kind) => format!("kind:{}", kind),
+            Column::Pattern(pattern) => format!("pattern:{}", pattern),
+            Column::Query(name) => format!("query:{}", name),
+            Column::Capture(name, capture) => format!("query:{}@{}", name, capture),
+            Column::MedianAge => String::from("age:median"),
+            Column::PercentOld => String::from("age:old"),
+        }
+    }
+
+    /// Displayed at the top of the column in tables (e.g. "Query(comment)")
+    pub fn title(&self) -> String {
+        match self {
+            Column::Files => String::from("Files"),
+            Column::Tokens => String::from("Tokens"),
+            Column::Kind(kind) => format!("Kind({})", kind),
+            Column::Pattern(pattern) => format!("Pattern({})", pattern),
+            Column::Query(name) => format!("Query({})", name),
+            Column::Capture(name, capture) => format!("Query({}@{})", name, capture),
+            Column::MedianAge => String::from("Median Age (days)"),
+            Column::PercentOld => String::from("Older Than 1y (%)"),
+        }
+    }
+}
+
+/// Columns for each count, in the same order as `Counts::values`. Counts with @ages have the age
+/// columns last.
+pub fn columns(
+    kinds: &[String],
+    kind_patterns: &[Regex],
+    queries: &[Query],
+    ages: bool,
+) -> Vec<Column> {
+    let mut columns = Vec::with_capacity(2 + kinds.len() + kind_patterns.len() + queries.len());
+    columns.push(Column::Files);
+    columns.push(Column::Tokens);
     kinds
         .iter()
-        .for_each(|kind| titles.push(title_cell(&format!("Kind({})", kind))));
+        .for_each(|kind| columns.push(Column::Kind(kind.clone())));
     kind_patterns
         .iter()
-        .for_each(|kind_pat| titles.push(title_cell(&format!("Pattern({})", kind_pat))));
+        .for_each(|kind_pat| columns.push(Column::Pattern(kind_pat.to_string())));
     queries.iter().for_each(|query| match &query.kind {
-        QueryKind::Match => titles.push(title_cell(&format!("Query({})", query.name))),
-        QueryKind::Captures(names) => names.iter().for_each(|name| {
-            titles.push(title_cell(&format!("Query({}@{})", query.name, name)));
-        }),
+        QueryKind::Match => columns.push(Column::Query(query.name.clone())),
+        QueryKind::Captures(names) => names
+            .iter()
+            .for_each(|name| columns.push(Column::Capture(query.name.clone(), name.clone()))),
     });
-    table.set_titles(Row::new(titles));
+    if ages {
+        columns.push(Column::MedianAge);
+        columns.push(Column::PercentOld);
+    }
+    columns
+}
 
-    counts
+/// Total number of tokens for each language in @files, in descending order. Languages without any
+/// tokens are left out.
+fn language_tokens(files: &[FileCounts]) -> Vec<(Language, u64)> {
+    let mut langs: Vec<(Language, u64)> = files
         .iter()
-        .chain(
-            {
-                if let Some(totals) = totals {
-                    vec![(String::from("TOTALS"), totals)]
-                } else {
-                    vec![]
-                }
+        .fold(HashMap::new(), |mut acc, file| {
+            *acc.entry(file.lang.clone()).or_insert(0) += file.counts.ntokens;
+            acc
+        })
+        .into_iter()
+        .filter(|(_, ntokens)| *ntokens > 0)
+        .collect();
+    langs.sort_by(|(l1, n1), (l2, n2)| n2.cmp(n1).then(l1.cmp(l2)));
+    langs
+}
+
+/// The Counts of the files in @files for each supported language along with the files themselves,
+/// ordered by language.
+fn by_language(files: &[FileCounts]) -> Vec<(Language, Counts, Vec<&FileCounts>)> {
+    let mut langs: BTreeMap<&Language, (Counts, Vec<&FileCounts>)> = BTreeMap::new();
+    files
+        .iter()
+        .filter(|file| file.lang != Language::Unsupported)
+        .for_each(|file| match langs.get_mut(&file.lang) {
+            Some((counts, files)) => {
+                *counts += file.counts.clone();
+                files.push(file);
             }
-            .iter(),
-        )
-        .map(|(label, count)| {
-            let mut cols =
-                Vec::with_capacity(3 + kinds.len() + kind_patterns.len() + queries.len());
-
-            // Language
-            cols.push(label_cell(&label.to_string()));
-            // number of files
-            cols.push(count_cell(count.nfiles));
-            // number of tokens
-            cols.push(count_cell(count.ntokens));
-            // number of nodes for a specific kind
-            count.nkinds.iter().for_each(|n| cols.push(count_cell(*n)));
-            // number of nodes for a specific pattern
-            count
-                .nkind_patterns
+            None => {
+                langs.insert(&file.lang, (file.counts.clone(), vec![file]));
+            }
+        });
+    langs
+        .into_iter()
+        .map(|(lang, (counts, mut files))| {
+            files.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+            (lang.clone(), counts, files)
+        })
+        .collect()
+}
+
+/// The sum of @counts, or empty Counts if there aren't any
+fn sum<'a>(counts: impl Iterator<Item = &'a Counts>) -> Counts {
+    counts
+        .cloned()
+        .reduce(|mut total, counts| {
+            total += counts;
+            total
+        })
+        .unwrap_or_else(|| Counts::empty(0, 0, &[]))
+}
+
+/// @n with commas between each group of thousands (e.g. 12,345)
+pub fn thousands(n: u64) -> String {
+    let digits: Vec<char> = n.to_string().chars().collect();
+    digits
+        .rchunks(3)
+        .rev()
+        .map(|group| group.iter().collect::<String>())
+        .collect::<Vec<String>>()
+        .join(",")
+}
+
+/// @n shortened with a suffix for thousands, millions, etc. (e.g. 12.3k)
+pub fn compact(n: u64) -> String {
+    const SUFFIXES: [&str; 6] = ["", "k", "M", "G", "T", "P"];
+    let mut value = n as f64;
+    let mut suffix = 0;
+    while value >= 999.95 && suffix + 1 < SUFFIXES.len() {
+        value /= 1000.0;
+        suffix += 1;
+    }
+    if suffix == 0 {
+        n.to_string()
+    } else {
+        format!("{:.1}{}", value, SUFFIXES[suffix])
+    }
+}
+
+/// The counts for a group or file as structured data. See `context`.
+fn counts_context(columns: &[Column], counts: &Counts) -> Map<String, Value> {
+    let values = counts.values();
+    let mut row = Map::new();
+    row.insert(
+        "counts".into(),
+        columns
+            .iter()
+            .zip(values.iter())
+            .map(|(column, value)| (column.key(), Value::from(*value)))
+            .collect::<Map<String, Value>>()
+            .into(),
+    );
+    row.insert("values".into(), values.into());
+    row.insert(
+        "lines".into(),
+        json!({
+            "code": counts.lines.code,
+            "comments": counts.lines.comments,
+            "blanks": counts.lines.blanks,
+        }),
+    );
+    row
+}
+
+/// The results of counting as structured data, which templates are rendered with. @counts are the
+/// rows for each group, @totals is the sum of every group, and @files is every file counted. The
+/// titles of the columns are from @options.layout:
+///
+///   columns: [{key, title}]
+///   rows: [{group, counts: {key: value}, values: [value], lines: {code, comments, blanks}}]
+///   totals: {group, counts, values, lines}
+///   files: [{path, language, group, counts, values, lines}]
+///   metrics: [{key, title, values: [value for each row], total}]
+///   transpose: whether --transpose was given
+///
+/// metrics is the transposed rows, with the columns shown in tables in the order from
+/// --sort-metrics. Templates which show a table should show metrics instead of rows when transpose
+/// is set.
+pub fn context(
+    options: &Options,
+    counts: &[(String, Counts)],
+    totals: &Counts,
+    files: &[FileCounts],
+) -> Value {
+    let columns = options.columns;
+    let group = |group: &str, counts: &Counts| {
+        let mut row = counts_context(columns, counts);
+        row.insert("group".into(), group.into());
+        Value::from(row)
+    };
+    json!({
+        "columns": columns
+            .iter()
+            .zip(options.layout.titles.iter())
+            .map(|(column, title)| json!({"key": column.key(), "title": title}))
+            .collect::<Vec<Value>>(),
+        "rows": counts
+            .iter()
+            .map(|(name, counts)| group(name, counts))
+            .collect::<Vec<Value>>(),
+        "totals": group("TOTALS", totals),
+        "transpose": options.transpose,
+        "metrics": sorted_metrics(options, counts, options.layout.counts())
+            .into_iter()
+            .map(|i| {
+                json!({
+                    "key": columns[i].key(),
+                    "title": options.layout.titles[i],
+                    "values": counts
+                        .iter()
+                        .map(|(_, counts)| counts.values()[i])
+                        .collect::<Vec<u64>>(),
+                    "total": totals.values()[i],
+                })
+            })
+            .collect::<Vec<Value>>(),
+        "files": files
+            .iter()
+            .map(|file| {
+                let mut row = counts_context(columns, &file.counts);
+                row.insert("path".into(), file.path.display().to_string().into());
+                row.insert("language".into(), file.lang.to_string().into());
+                row.insert("group".into(), file.group.clone().into());
+                Value::from(row)
+            })
+            .collect::<Vec<Value>>(),
+    })
+}
+
+/// Escape @s so it can be embedded in HTML, SVG, or XML documents
+pub(crate) fn escape_xml(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut acc, c| {
+            match c {
+                '&' => acc.push_str("&amp;"),
+                '<' => acc.push_str("&lt;"),
+                '>' => acc.push_str("&gt;"),
+                '"' => acc.push_str("&quot;"),
+                '\'' => acc.push_str("&#39;"),
+                _ => acc.push(c),
+            }
+            acc
+        })
+}
+
+/// How counts are written in tables, see --human
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Human {
+    Thousands,
+    Compact,
+}
+
+impl FromStr for Human {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "thousands" => Ok(Human::Thousands),
+            "compact" => Ok(Human::Compact),
+            _ => Err(format!(
+                "\"{}\" is not supported. Use one of thousands|compact",
+                s
+            )),
+        }
+    }
+}
+
+/// The order of the rows of counts with --transpose, see --sort-metrics
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SortMetrics {
+    Columns,
+    Name,
+    Total,
+}
+
+impl FromStr for SortMetrics {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "columns" => Ok(SortMetrics::Columns),
+            "name" => Ok(SortMetrics::Name),
+            "total" => Ok(SortMetrics::Total),
+            _ => Err(format!(
+                "\"{}\" is not a supported argument to --sort-metrics. Use one of columns|name|total",
+                s
+            )),
+        }
+    }
+}
+
+impl SortMetrics {
+    /// Sort @metrics, which are in the order of their columns, by the name and total that @key
+    /// gives each of them. Names are in ascending order and totals are in descending order.
+    pub fn sort<T>(&self, metrics: &mut [T], key: impl Fn(&T) -> (String, u64)) {
+        match self {
+            SortMetrics::Columns => {}
+            SortMetrics::Name => metrics.sort_by_key(|metric| key(metric).0),
+            SortMetrics::Total => metrics.sort_by_key(|metric| Reverse(key(metric).1)),
+        }
+    }
+}
+
+/// How the tables of subcommands and --bus-factor are written, see --human and --transpose
+#[derive(Debug, Clone, Copy, Default)]
+pub struct TableStyle {
+    pub human: Option<Human>,
+    pub transpose: bool,
+}
+
+/// Format @n for a table
+fn format_count(n: u64, human: Option<Human>) -> String {
+    match human {
+        None => n.to_string(),
+        Some(Human::Thousands) => thousands(n),
+        Some(Human::Compact) => compact(n),
+    }
+}
+
+/// A column of a table, either the group or one of the counts by its index in `Counts::values`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Field {
+    Group,
+    Count(usize),
+}
+
+/// The columns shown in tables and their titles, see --columns, --hide, and --header
+#[derive(Debug, PartialEq)]
+pub struct Layout {
+    pub fields: Vec<Field>,
+    pub group_title: String,
+    /// Title of each column, in the same order as `Counts::values`
+    pub titles: Vec<String>,
+}
+
+impl Layout {
+    /// Show the fields with the keys in @select in that order, or every field if it's empty, except
+    /// for the ones in @hide. @headers renames fields by their key.
+    pub fn new(
+        columns: &[Column],
+        select: &[String],
+        hide: &[String],
+        headers: &[(String, String)],
+    ) -> crate::error::Result<Layout> {
+        let field = |key: &String| {
+            if key == "group" {
+                Ok(Field::Group)
+            } else {
+                columns
+                    .iter()
+                    .position(|column| column.key() == *key)
+                    .map(Field::Count)
+                    .ok_or_else(|| Error::UnknownColumn(key.clone()))
+            }
+        };
+
+        let mut fields = if select.is_empty() {
+            std::iter::once(Field::Group)
+                .chain((0..columns.len()).map(Field::Count))
+                .collect()
+        } else {
+            select
                 .iter()
-                .for_each(|n| cols.push(count_cell(*n)));
-            // number of nodes for a specific query
-            count
-                .nqueries
+                .map(field)
+                .collect::<crate::error::Result<Vec<Field>>>()?
+        };
+        let hidden = hide
+            .iter()
+            .map(field)
+            .collect::<crate::error::Result<Vec<Field>>>()?;
+        fields.retain(|field| !hidden.contains(field));
+
+        let mut layout = Layout {
+            fields,
+            group_title: String::from("Group"),
+            titles: columns.iter().map(Column::title).collect(),
+        };
+        for (key, title) in headers {
+            match field(key)? {
+                Field::Group => layout.group_title = title.clone(),
+                Field::Count(i) => layout.titles[i] = title.clone(),
+            }
+        }
+        Ok(layout)
+    }
+
+    /// Indexes into `Counts::values` of the counts which are shown, in order
+    pub fn counts(&self) -> impl Iterator<Item = usize> + '_ {
+        self.fields.iter().filter_map(|field| match field {
+            Field::Group => None,
+            Field::Count(i) => Some(*i),
+        })
+    }
+
+    fn title(&self, field: Field) -> &str {
+        match field {
+            Field::Group => &self.group_title,
+            Field::Count(i) => &self.titles[i],
+        }
+    }
+}
+
+/// Options for printing the results which don't change what is counted
+pub struct Options<'a> {
+    pub format: &'a Format,
+    pub columns: &'a [Column],
+    pub layout: Layout,
+    pub human: Option<Human>,
+    pub chart: bool,
+    pub transpose: bool,
+    pub sort_metrics: SortMetrics,
+    pub treemap_column: usize,
+    pub comparison: Option<Comparison>,
+    /// When counting started, for formats which report how long it took
+    pub started: Instant,
+}
+
+/// Build a table with a row for each group in @counts, followed by @totals. The columns are from
+/// @options.layout. With @chart, each group also gets a bar of its number of tokens.
+fn counts_table(
+    options: &Options,
+    counts: &[(String, Counts)],
+    totals: Option<&Counts>,
+    chart: bool,
+) -> Table {
+    let layout = &options.layout;
+    let mut table = Table::new();
+    table.set_format(format_builder().build());
+
+    table.set_titles(Row::new(
+        layout
+            .fields
+            .iter()
+            .map(|field| layout.title(*field))
+            .chain(if chart { Some("") } else { None })
+            .map(title_cell)
+            .collect(),
+    ));
+
+    let max_tokens = counts.iter().map(|(_, c)| c.ntokens).max().unwrap_or(0);
+    let bar_style = if chart::use_color() { "lFb" } else { "l" };
+    let totals = totals.map(|totals| (String::from("TOTALS"), totals.clone()));
+    counts
+        .iter()
+        .map(|(label, count)| {
+            (
+                label,
+                count,
+                chart.then(|| chart::bar(count.ntokens, max_tokens, chart::BAR_WIDTH)),
+            )
+        })
+        // totals are never charted since they would dwarf every other bar
+        .chain(totals.iter().map(|(label, count)| (label, count, None)))
+        .map(|(label, count, bar)| {
+            let values = count.values();
+            layout
+                .fields
                 .iter()
-                .for_each(|n| cols.push(count_cell(*n)));
-            cols
+                .map(|field| match field {
+                    Field::Group => label_cell(label),
+                    Field::Count(i) => count_cell(&format_count(values[*i], options.human)),
+                })
+                .chain(bar.map(|bar| Cell::new(&bar).style_spec(bar_style)))
+                .collect()
         })
         .for_each(|row| {
             table.add_row(Row::new(row));
         });
+    table
+}
+
+/// @indexes into `Counts::values` in the order of the rows with --transpose. They're sorted by
+/// @options.sort_metrics using the titles from @options.layout and the sum of every group in
+/// @counts.
+fn sorted_metrics(
+    options: &Options,
+    counts: &[(String, Counts)],
+    indexes: impl Iterator<Item = usize>,
+) -> Vec<usize> {
+    let totals = sum(counts.iter().map(|(_, counts)| counts)).values();
+    let mut indexes: Vec<usize> = indexes.collect();
+    options.sort_metrics.sort(&mut indexes, |&i| {
+        (
+            options.layout.titles[i].clone(),
+            totals.get(i).copied().unwrap_or(0),
+        )
+    });
+    indexes
+}
 
-    match format {
+/// Build a table with @titles and a row for each of @rows. With @transpose each title is a row
+/// instead and each of @rows is a column, with the first cell of each row as its title.
+pub(crate) fn rows_table(titles: Vec<String>, rows: Vec<Vec<Cell>>, transpose: bool) -> Table {
+    let mut table = Table::new();
+    table.set_format(format_builder().build());
+    if !transpose {
+        table.set_titles(Row::new(
+            titles.iter().map(|title| title_cell(title)).collect(),
+        ));
+        rows.into_iter().for_each(|row| {
+            table.add_row(Row::new(row));
+        });
+        return table;
+    }
+    table.set_titles(Row::new(
+        titles
+            .iter()
+            .take(1)
+            .cloned()
+            .chain(rows.iter().map(|row| row[0].get_content()))
+            .map(|title| title_cell(&title))
+            .collect(),
+    ));
+    titles.iter().enumerate().skip(1).for_each(|(i, title)| {
+        table.add_row(Row::new(
+            std::iter::once(label_cell(title))
+                .chain(rows.iter().map(|row| row[i].clone()))
+                .collect(),
+        ));
+    });
+    table
+}
+
+/// Build a table with a row for each column shown in @options.layout and a column for each group
+/// in @counts, followed by @totals. See --transpose and --sort-metrics.
+fn transposed_table(
+    options: &Options,
+    counts: &[(String, Counts)],
+    totals: Option<&Counts>,
+) -> Table {
+    let layout = &options.layout;
+    let mut table = Table::new();
+    table.set_format(format_builder().build());
+
+    table.set_titles(Row::new(
+        std::iter::once(layout.group_title.as_str())
+            .chain(counts.iter().map(|(group, _)| group.as_str()))
+            .chain(totals.map(|_| "TOTALS"))
+            .map(title_cell)
+            .collect(),
+    ));
+
+    let values: Vec<Vec<u64>> = counts
+        .iter()
+        .map(|(_, counts)| counts)
+        .chain(totals)
+        .map(Counts::values)
+        .collect();
+    sorted_metrics(options, counts, layout.counts())
+        .into_iter()
+        .for_each(|i| {
+            table.add_row(Row::new(
+                std::iter::once(label_cell(&layout.titles[i]))
+                    .chain(
+                        values
+                            .iter()
+                            .map(|values| count_cell(&format_count(values[i], options.human))),
+                    )
+                    .collect(),
+            ));
+        });
+    table
+}
+
+/// The change from @old to @new, e.g. +12
+fn format_delta(old: u64, new: u64, human: Option<Human>) -> String {
+    if new == old {
+        String::from("0")
+    } else if new > old {
+        format!("+{}", format_count(new - old, human))
+    } else {
+        format!("-{}", format_count(old - new, human))
+    }
+}
+
+/// The change from @old to @new as a percent of @old, e.g. +12.5%. Nothing is shown when there was
+/// nothing before.
+fn format_change(old: Option<u64>, new: u64) -> String {
+    match old {
+        Some(old) if old > 0 => format!("{:+.1}%", (new as f64 - old as f64) * 100.0 / old as f64),
+        _ => String::new(),
+    }
+}
+
+/// Build a table of the number of changed files and tokens added and removed in each group in
+/// @groups, followed by @totals, with the change in each of @columns. See `tcount diff`.
+pub fn churn_table(
+    columns: &[Column],
+    groups: &[(String, Churn)],
+    totals: Option<&Churn>,
+    style: TableStyle,
+) -> Table {
+    let human = style.human;
+    let titles = ["Group", "Files", "Added", "Removed", "Net"]
+        .iter()
+        .map(|title| title.to_string())
+        .chain(columns.iter().map(|column| format!("{} Δ", column.title())))
+        .collect();
+    let rows = groups
+        .iter()
+        .map(|(group, churn)| (group.as_str(), churn))
+        .chain(totals.map(|totals| ("TOTALS", totals)))
+        .map(|(label, churn)| {
+            let mut cells = vec![
+                label_cell(label),
+                count_cell(&format_count(churn.nfiles, human)),
+                count_cell(&format_count(churn.added, human)),
+                count_cell(&format_count(churn.removed, human)),
+                count_cell(&format_delta(churn.removed, churn.added, human)),
+            ];
+            cells.extend((0..columns.len()).map(|i| {
+                let delta = churn.deltas.get(i).copied().unwrap_or(0);
+                count_cell(&format_delta(
+                    (-delta.min(0)) as u64,
+                    delta.max(0) as u64,
+                    human,
+                ))
+            }));
+            cells
+        })
+        .collect();
+    rows_table(titles, rows, style.transpose)
+}
+
+/// Build a table of @hotspots with their size in @column. See `tcount hotspots`.
+pub fn hotspots_table(column: &Column, hotspots: &[Hotspot], style: TableStyle) -> Table {
+    let human = style.human;
+    let titles = vec![
+        String::from("File"),
+        String::from("Language"),
+        column.title(),
+        String::from("Commits"),
+        String::from("Score"),
+    ];
+    let rows = hotspots
+        .iter()
+        .map(|hotspot| {
+            vec![
+                label_cell(&hotspot.path.display().to_string()),
+                generic_cell(&hotspot.lang),
+                count_cell(&format_count(hotspot.size, human)),
+                count_cell(&format_count(hotspot.commits, human)),
+                count_cell(&format!("{:.1}", hotspot.score)),
+            ]
+        })
+        .collect();
+    rows_table(titles, rows, style.transpose)
+}
+
+/// Build a table of the bus factor of each directory containing @files, which are counted by
+/// author: the fewest authors who wrote more than half of the tokens in the directory. See
+/// --bus-factor.
+pub fn bus_factor_table(files: &[FileCounts], style: TableStyle) -> Table {
+    let mut dirs: BTreeMap<&Path, HashMap<&str, u64>> = BTreeMap::new();
+    files.iter().for_each(|file| {
+        let dir = file.path.parent().unwrap_or_else(|| Path::new(""));
+        *dirs
+            .entry(dir)
+            .or_default()
+            .entry(file.group.as_str())
+            .or_insert(0) += file.counts.ntokens;
+    });
+
+    let titles = ["Directory", "Tokens", "Bus Factor", "Authors"]
+        .iter()
+        .map(|title| title.to_string())
+        .collect();
+    let mut rows = Vec::new();
+    for (dir, authors) in dirs {
+        let total: u64 = authors.values().sum();
+        if total == 0 {
+            continue;
+        }
+        let mut authors: Vec<(&str, u64)> = authors.into_iter().collect();
+        authors.sort_by(|(a1, n1), (a2, n2)| n2.cmp(n1).then(a1.cmp(a2)));
+        let mut owned = 0;
+        let owners: Vec<&str> = authors
+            .into_iter()
+            .take_while(|(_, ntokens)| {
+                let before = owned;
+                owned += ntokens;
+                before * 2 <= total
+            })
+            .map(|(author, _)| author)
+            .collect();
+        rows.push(vec![
+            label_cell(&dir.display().to_string()),
+            count_cell(&format_count(total, style.human)),
+            count_cell(&owners.len().to_string()),
+            label_cell(&owners.join(", ")),
+        ]);
+    }
+    rows_table(titles, rows, style.transpose)
+}
+
+/// Build a table comparing each group in @counts, and @totals, with @comparison. For each column
+/// shown in @options.layout there are columns for the old count, the new count, the change, and the
+/// percent change. Groups which aren't in the baseline are marked as new and groups which are only
+/// in the baseline are marked as removed.
+fn comparison_table(
+    options: &Options,
+    comparison: &Comparison,
+    counts: &[(String, Counts)],
+    totals: Option<&Counts>,
+) -> Table {
+    let layout = &options.layout;
+    let baseline = &comparison.baseline;
+    let mut table = Table::new();
+    table.set_format(format_builder().build());
+
+    table.set_titles(Row::new(
+        layout
+            .fields
+            .iter()
+            .flat_map(|field| match field {
+                Field::Group => vec![layout.group_title.clone()],
+                Field::Count(i) => {
+                    let title = &layout.titles[*i];
+                    vec![
+                        format!("{} old", title),
+                        format!("{} new", title),
+                        format!("{} Δ", title),
+                        format!("{} %", title),
+                    ]
+                }
+            })
+            .map(|title| title_cell(&title))
+            .collect(),
+    ));
+
+    // each row is the label, the old counts by key, and the new counts
+    let keys: Vec<String> = options.columns.iter().map(Column::key).collect();
+    let rows = counts
+        .iter()
+        .map(|(group, counts)| {
+            let label = if baseline.groups.iter().any(|(name, _)| name == group) {
+                group.clone()
+            } else {
+                format!("{} (new)", group)
+            };
+            let old: Vec<Option<u64>> = keys.iter().map(|key| baseline.get(group, key)).collect();
+            (label, old, Some(counts.values()))
+        })
+        .chain(comparison.removed.iter().map(|group| {
+            let old: Vec<Option<u64>> = keys.iter().map(|key| baseline.get(group, key)).collect();
+            (format!("{} (removed)", group), old, None)
+        }))
+        .chain(totals.map(|totals| {
+            let old: Vec<Option<u64>> = keys.iter().map(|key| baseline.total(key)).collect();
+            (String::from("TOTALS"), old, Some(totals.values()))
+        }));
+
+    rows.for_each(|(label, old, new)| {
+        let cells: Vec<Cell> = layout
+            .fields
+            .iter()
+            .flat_map(|field| match field {
+                Field::Group => vec![label_cell(&label)],
+                Field::Count(i) => {
+                    let old = old[*i];
+                    let new = new.as_ref().map(|new| new[*i]);
+                    let format = |n: Option<u64>| {
+                        n.map(|n| format_count(n, options.human))
+                            .unwrap_or_default()
+                    };
+                    let delta = format_delta(old.unwrap_or(0), new.unwrap_or(0), options.human);
+                    vec![
+                        count_cell(&format(old)),
+                        count_cell(&format(new)),
+                        count_cell(&delta),
+                        count_cell(&format_change(old, new.unwrap_or(0))),
+                    ]
+                }
+            })
+            .collect();
+        table.add_row(Row::new(cells));
+    });
+    table
+}
+
+pub fn print(
+    options: &Options,
+    counts: Vec<(String, Counts)>,
+    totals: Option<Counts>,
+    files: &[FileCounts],
+) {
+    let titles = &options.layout.titles;
+    let treemap_column = options.treemap_column;
+
+    match options.format {
         Format::Table => {
-            table.printstd();
+            if options.chart {
+                print!("{}", chart::language_share(files, chart::use_color()));
+            }
+            if let Some(comparison) = &options.comparison {
+                comparison_table(options, comparison, &counts, totals.as_ref()).printstd();
+            } else if options.transpose {
+                transposed_table(options, &counts, totals.as_ref()).printstd();
+            } else {
+                counts_table(options, &counts, totals.as_ref(), options.chart).printstd();
+            }
         }
-        Format::Csv => match table.to_csv(std::io::stdout()) {
-            Ok(_) => {}
-            Err(err) => eprintln!("{}", err),
-        },
+        Format::Csv => {
+            let table = if let Some(comparison) = &options.comparison {
+                comparison_table(options, comparison, &counts, totals.as_ref())
+            } else if options.transpose {
+                transposed_table(options, &counts, totals.as_ref())
+            } else {
+                counts_table(options, &counts, totals.as_ref(), false)
+            };
+            match table.to_csv(std::io::stdout()) {
+                Ok(_) => {}
+                Err(err) => eprintln!("{}", err),
+            }
+        }
+        Format::Html => {
+            let metrics = options
+                .transpose
+                .then(|| sorted_metrics(options, &counts, 0..titles.len()));
+            print!(
+                "{}",
+                html::render(
+                    titles,
+                    &counts,
+                    totals.as_ref(),
+                    files,
+                    treemap_column,
+                    metrics.as_deref()
+                )
+            );
+        }
+        Format::OpenMetrics => {
+            let metrics = options
+                .transpose
+                .then(|| sorted_metrics(options, &counts, 0..titles.len()));
+            print!(
+                "{}",
+                openmetrics::render(&counts, options.columns, metrics.as_deref())
+            );
+        }
+        Format::SvgTreemap => {
+            print!(
+                "{}",
+                treemap::document(
+                    files,
+                    treemap_column,
+                    &titles[treemap_column],
+                    options.transpose
+                )
+            );
+        }
+        Format::TokeiJson => print!(
+            "{}",
+            tokei::render(files, options.columns, options.transpose)
+        ),
+        Format::ClocYaml => print!(
+            "{}",
+            cloc::render(
+                files,
+                options.columns,
+                options.started.elapsed(),
+                options.transpose.then_some(options.sort_metrics)
+            )
+        ),
     }
 }
 
@@ -162,3 +1009,87 @@ pub fn print_languages(langs: Vec<(&Language, Vec<String>, &Vec<String>)>) {
 
     table.printstd();
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn format_counts_for_humans() {
+        assert_eq!("1234567", format_count(1234567, None));
+        assert_eq!("1,234,567", format_count(1234567, Some(Human::Thousands)));
+        assert_eq!("123", format_count(123, Some(Human::Thousands)));
+        assert_eq!("1.2M", format_count(1234567, Some(Human::Compact)));
+        assert_eq!("999", format_count(999, Some(Human::Compact)));
+        assert_eq!("12.3k", format_count(12345, Some(Human::Compact)));
+        assert_eq!("1.0M", format_count(999_999, Some(Human::Compact)));
+    }
+
+    #[test]
+    fn format_changes() {
+        assert_eq!("+1,000", format_delta(5, 1005, Some(Human::Thousands)));
+        assert_eq!("-3", format_delta(5, 2, None));
+        assert_eq!("0", format_delta(5, 5, None));
+        assert_eq!("+12.5%", format_change(Some(8), 9));
+        assert_eq!("-100.0%", format_change(Some(8), 0));
+        assert_eq!("", format_change(Some(0), 9));
+        assert_eq!("", format_change(None, 9));
+    }
+
+    #[test]
+    fn sort_metrics() {
+        let metrics = [("Tokens", 156), ("Files", 5), ("Query(unsafe)", 9)];
+        let sorted = |sort: SortMetrics| {
+            let mut sorted = metrics;
+            sort.sort(&mut sorted, |(name, total)| (name.to_string(), *total));
+            sorted.map(|(name, _)| name)
+        };
+        assert_eq!(
+            ["Tokens", "Files", "Query(unsafe)"],
+            sorted(SortMetrics::Columns)
+        );
+        assert_eq!(
+            ["Files", "Query(unsafe)", "Tokens"],
+            sorted(SortMetrics::Name)
+        );
+        assert_eq!(
+            ["Tokens", "Query(unsafe)", "Files"],
+            sorted(SortMetrics::Total)
+        );
+    }
+
+    #[test]
+    fn layout_selects_hides_and_renames() {
+        let columns = vec![
+            Column::Files,
+            Column::Tokens,
+            Column::Query(String::from("comment")),
+        ];
+        let layout = Layout::new(
+            &columns,
+            &[
+                String::from("query:comment"),
+                String::from("group"),
+                String::from("files"),
+            ],
+            &[String::from("files")],
+            &[(String::from("query:comment"), String::from("Comments"))],
+        )
+        .unwrap();
+        assert_eq!(vec![Field::Count(2), Field::Group], layout.fields);
+        assert_eq!("Comments", layout.title(Field::Count(2)));
+        assert_eq!("Group", layout.title(Field::Group));
+
+        let layout = Layout::new(&columns, &[], &[], &[]).unwrap();
+        assert_eq!(
+            vec![
+                Field::Group,
+                Field::Count(0),
+                Field::Count(1),
+                Field::Count(2)
+            ],
+            layout.fields
+        );
+        assert!(Layout::new(&columns, &[String::from("nope")], &[], &[]).is_err());
+    }
+}
@@ -0,0 +1,146 @@
+use crate::count::FileCounts;
+use crate::language::Language;
+use crate::output::language_tokens;
+use std::env;
+
+/// Width of the bars drawn next to each row of the table
+pub const BAR_WIDTH: usize = 20;
+/// Width of the stacked bar showing the share of each language
+const SHARE_WIDTH: usize = 60;
+/// Partial blocks used to draw the end of a bar, in eighths of a character
+const EIGHTHS: [char; 8] = [' ', '▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+/// Fills used to tell languages apart in the stacked bar when there is no colour
+const FILLS: [char; 4] = ['█', '▓', '▒', '░'];
+
+/// Whether the charts should be coloured. Colours are only used when stdout is a terminal and
+/// NO_COLOR isn't set (see https://no-color.org).
+pub fn use_color() -> bool {
+    atty::is(atty::Stream::Stdout) && env::var_os("NO_COLOR").is_none()
+}
+
+/// A horizontal bar for @value where @max fills @width characters. Non-zero values always get at
+/// least a sliver of a bar.
+pub fn bar(value: u64, max: u64, width: usize) -> String {
+    if max == 0 || value == 0 {
+        return String::new();
+    }
+    let eighths = ((value as u128 * width as u128 * 8 + max as u128 / 2) / max as u128) as usize;
+    let (full, partial) = (eighths / 8, eighths % 8);
+    let mut bar = "█".repeat(full);
+    if partial > 0 {
+        bar.push(EIGHTHS[partial]);
+    } else if full == 0 {
+        bar.push(EIGHTHS[1]);
+    }
+    bar
+}
+
+/// Wrap @s in the ANSI escape codes to set the foreground to the hex colour @hex
+fn paint(s: &str, hex: &str) -> String {
+    let channel = |i: usize| u8::from_str_radix(&hex[i..i + 2], 16).unwrap_or(0);
+    format!(
+        "\x1b[38;2;{};{};{}m{}\x1b[0m",
+        channel(1),
+        channel(3),
+        channel(5),
+        s
+    )
+}
+
+/// Split @width characters between @values proportionally using the largest remainder method so
+/// the widths always add up to @width.
+fn apportion(values: &[u64], width: usize) -> Vec<usize> {
+    let total: u64 = values.iter().sum();
+    if total == 0 {
+        return vec![0; values.len()];
+    }
+    let exact: Vec<f64> = values
+        .iter()
+        .map(|v| *v as f64 * width as f64 / total as f64)
+        .collect();
+    let mut widths: Vec<usize> = exact.iter().map(|w| w.floor() as usize).collect();
+    let mut by_remainder: Vec<usize> = (0..values.len()).collect();
+    by_remainder.sort_by(|i, j| {
+        (exact[*j] - exact[*j].floor())
+            .partial_cmp(&(exact[*i] - exact[*i].floor()))
+            .unwrap()
+            .then(i.cmp(j))
+    });
+    let remaining = width - widths.iter().sum::<usize>();
+    by_remainder
+        .into_iter()
+        .take(remaining)
+        .for_each(|i| widths[i] += 1);
+    widths
+}
+
+/// A stacked bar of the share of tokens for each language in @files followed by a legend, similar
+/// to the language bar GitHub shows for repositories.
+pub fn language_share(files: &[FileCounts], color: bool) -> String {
+    let langs: Vec<(Language, u64)> = language_tokens(files);
+    if langs.is_empty() {
+        return String::new();
+    }
+    let total: u64 = langs.iter().map(|(_, n)| n).sum();
+    let widths = apportion(
+        &langs.iter().map(|(_, n)| *n).collect::<Vec<u64>>(),
+        SHARE_WIDTH,
+    );
+
+    let (bar, legend): (Vec<String>, Vec<String>) = langs
+        .iter()
+        .zip(widths)
+        .enumerate()
+        .map(|(i, ((lang, ntokens), width))| {
+            let fill = if color {
+                FILLS[0]
+            } else {
+                FILLS[i % FILLS.len()]
+            };
+            let segment = fill.to_string().repeat(width);
+            let key = format!(
+                "{} {} {:.1}%",
+                fill,
+                lang,
+                *ntokens as f64 * 100.0 / total as f64
+            );
+            if color {
+                (
+                    paint(&segment, lang.color()),
+                    paint(&fill.to_string(), lang.color()) + &key[fill.len_utf8()..],
+                )
+            } else {
+                (segment, key)
+            }
+        })
+        .unzip();
+    format!(" {}\n {}\n", bar.join(""), legend.join("  "))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bar_scaled_to_max() {
+        assert_eq!("████████████████████", bar(10, 10, BAR_WIDTH));
+        assert_eq!("██████████", bar(5, 10, BAR_WIDTH));
+        assert_eq!("█▌", bar(3, 40, 20));
+        assert_eq!("▏", bar(1, 1000, 20));
+        assert_eq!("", bar(0, 10, 20));
+        assert_eq!("", bar(0, 0, 20));
+    }
+
+    #[test]
+    fn apportion_adds_up_to_width() {
+        assert_eq!(vec![37, 13, 10], apportion(&[156, 52, 43], 60));
+        assert_eq!(vec![1, 1, 1], apportion(&[1, 1, 1], 3));
+        assert_eq!(vec![2, 1, 0], apportion(&[5, 3, 1], 3));
+        assert_eq!(vec![0, 0], apportion(&[0, 0], 3));
+    }
+
+    #[test]
+    fn paint_with_hex_color() {
+        assert_eq!("\x1b[38;2;222;165;132mab\x1b[0m", paint("ab", "#dea584"));
+    }
+}
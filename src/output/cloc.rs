@@ -0,0 +1,143 @@
+use crate::count::{Counts, FileCounts};
+use crate::language::Language;
+use crate::output::{by_language, sum, Column, SortMetrics};
+use std::fmt::Write;
+use std::time::Duration;
+
+/// The version of cloc whose output is mirrored, for consumers which check it
+const CLOC_VERSION: &str = "1.96";
+
+/// Quote @key if it can't be used as a plain YAML scalar (e.g. "kind:line_comment")
+fn key(key: &str) -> String {
+    if key
+        .chars()
+        .all(|c| c.is_alphanumeric() || " +-_.".contains(c))
+    {
+        key.to_string()
+    } else {
+        format!("\"{}\"", key.replace('\\', r"\\").replace('"', "\\\""))
+    }
+}
+
+fn entry(out: &mut String, name: &str, columns: &[Column], counts: &Counts) {
+    let _ = writeln!(out, "{} :", key(name));
+    let _ = writeln!(out, "  nFiles: {}", counts.nfiles);
+    let _ = writeln!(out, "  blank: {}", counts.lines.blanks);
+    let _ = writeln!(out, "  comment: {}", counts.lines.comments);
+    let _ = writeln!(out, "  code: {}", counts.lines.code);
+    columns
+        .iter()
+        .zip(counts.values())
+        .skip(1)
+        .for_each(|(column, value)| {
+            let _ = writeln!(out, "  {}: {}", key(&column.key()), value);
+        });
+}
+
+/// Write an entry for each count of @langs, and of their @total, with the count in each language
+/// and the SUM, in the order from @sort. See --transpose.
+fn transposed(
+    out: &mut String,
+    langs: &[(Language, Counts, Vec<&FileCounts>)],
+    total: &Counts,
+    columns: &[Column],
+    sort: SortMetrics,
+) {
+    let rows: Vec<(String, &Counts)> = langs
+        .iter()
+        .map(|(lang, counts, _)| (lang.to_string(), counts))
+        .chain(std::iter::once((String::from("SUM"), total)))
+        .collect();
+    let values: Vec<Vec<u64>> = rows.iter().map(|(_, counts)| counts.values()).collect();
+    let lines = |counts: &Counts| {
+        [
+            counts.nfiles,
+            counts.lines.blanks,
+            counts.lines.comments,
+            counts.lines.code,
+        ]
+    };
+    // each count is its name and the value in each row, the last of which is the SUM
+    let mut metrics: Vec<(String, Vec<u64>)> = ["nFiles", "blank", "comment", "code"]
+        .iter()
+        .enumerate()
+        .map(|(j, name)| {
+            (
+                name.to_string(),
+                rows.iter().map(|(_, counts)| lines(counts)[j]).collect(),
+            )
+        })
+        .chain(columns.iter().enumerate().skip(1).map(|(i, column)| {
+            (
+                column.key(),
+                values.iter().map(|values| values[i]).collect(),
+            )
+        }))
+        .collect();
+    sort.sort(&mut metrics, |(name, values)| {
+        (name.clone(), values.last().copied().unwrap_or(0))
+    });
+    metrics.iter().for_each(|(name, values)| {
+        let _ = writeln!(out, "{} :", key(name));
+        rows.iter().zip(values).for_each(|((lang, _), value)| {
+            let _ = writeln!(out, "  {}: {}", key(lang), value);
+        });
+    });
+}
+
+/// Render @files in the same shape as `cloc --yaml`: a header followed by the files and lines of
+/// each language, ordered by lines of code, and then their SUM. cloc has no notion of tokens so
+/// every count other than the number of files is an extra field keyed by `Column::key`. The header
+/// has every field cloc's does, with the rates from @elapsed, and tcount's own fields beside them.
+/// With @transpose, the order of the counts, there's an entry for each count instead of each
+/// language.
+pub fn render(
+    files: &[FileCounts],
+    columns: &[Column],
+    elapsed: Duration,
+    transpose: Option<SortMetrics>,
+) -> String {
+    let mut langs = by_language(files);
+    langs.sort_by(|(l1, c1, _), (l2, c2, _)| c2.lines.code.cmp(&c1.lines.code).then(l1.cmp(l2)));
+    let total = sum(langs.iter().map(|(_, counts, _)| counts));
+
+    let nlines = total.lines.blanks + total.lines.comments + total.lines.code;
+    // cloc doesn't divide by zero either
+    let seconds = elapsed.as_secs_f64().max(f64::EPSILON);
+    let mut out = String::from("---\n# github.com/RRethy/tcount\nheader :\n");
+    let _ = writeln!(out, "  cloc_url : github.com/AlDanial/cloc");
+    let _ = writeln!(out, "  cloc_version : {}", CLOC_VERSION);
+    let _ = writeln!(out, "  elapsed_seconds : {}", elapsed.as_secs_f64());
+    let _ = writeln!(out, "  n_files : {}", total.nfiles);
+    let _ = writeln!(out, "  n_lines : {}", nlines);
+    let _ = writeln!(
+        out,
+        "  files_per_second : {}",
+        total.nfiles as f64 / seconds
+    );
+    let _ = writeln!(out, "  lines_per_second : {}", nlines as f64 / seconds);
+    let _ = writeln!(out, "  tcount_url : github.com/RRethy/tcount");
+    let _ = writeln!(out, "  tcount_version : {}", env!("CARGO_PKG_VERSION"));
+    if let Some(sort) = transpose {
+        transposed(&mut out, &langs, &total, columns, sort);
+        return out;
+    }
+    langs.iter().for_each(|(lang, counts, _)| {
+        entry(&mut out, &lang.to_string(), columns, counts);
+    });
+    entry(&mut out, "SUM", columns, &total);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn quote_keys() {
+        assert_eq!("Rust", key("Rust"));
+        assert_eq!("C++", key("C++"));
+        assert_eq!(r#""kind:line_comment""#, key("kind:line_comment"));
+        assert_eq!(r#""query:a\"b""#, key("query:a\"b"));
+    }
+}
@@ -0,0 +1,281 @@
+use crate::count::{Counts, FileCounts};
+use crate::output::{escape_xml, language_tokens, treemap};
+use std::f64::consts::PI;
+use std::fmt::Write;
+
+const STYLE: &str = r#"
+body { font-family: sans-serif; margin: 2em; color: #222; }
+h1 { font-size: 1.6em; }
+h2 { font-size: 1.2em; margin-top: 2em; }
+table { border-collapse: collapse; }
+th, td { padding: 2px 10px; border-bottom: 1px solid #ddd; }
+th { cursor: pointer; user-select: none; text-align: left; background: #f4f4f4; }
+th[data-order="asc"]::after { content: " \25B2"; }
+th[data-order="desc"]::after { content: " \25BC"; }
+td.num { text-align: right; font-variant-numeric: tabular-nums; }
+#groups tbody tr { cursor: pointer; }
+#groups tbody tr:hover, #groups tbody tr.selected { background: #eef4ff; }
+tfoot td { font-weight: bold; }
+.chart { display: flex; align-items: center; gap: 2em; }
+.legend span { display: inline-block; width: 0.8em; height: 0.8em; margin-right: 0.4em; }
+"#;
+
+const SCRIPT: &str = r#"
+document.querySelectorAll('table.sortable th').forEach(function (th) {
+  th.addEventListener('click', function () {
+    var table = th.closest('table');
+    var idx = Array.prototype.indexOf.call(th.parentNode.children, th);
+    var asc = th.dataset.order !== 'asc';
+    th.parentNode.querySelectorAll('th').forEach(function (h) { delete h.dataset.order; });
+    th.dataset.order = asc ? 'asc' : 'desc';
+    var body = table.tBodies[0];
+    var rows = Array.prototype.slice.call(body.rows);
+    rows.sort(function (a, b) {
+      var x = a.cells[idx], y = b.cells[idx];
+      var c = x.dataset.value !== undefined
+        ? Number(x.dataset.value) - Number(y.dataset.value)
+        : x.textContent.localeCompare(y.textContent);
+      return asc ? c : -c;
+    });
+    rows.forEach(function (row) { body.appendChild(row); });
+  });
+});
+
+var files = document.getElementById('files');
+var filter = document.getElementById('files-filter');
+document.querySelectorAll('#groups tbody tr').forEach(function (tr) {
+  tr.addEventListener('click', function () {
+    var group = files.dataset.group === tr.dataset.group ? '' : tr.dataset.group;
+    files.dataset.group = group;
+    document.querySelectorAll('#groups tbody tr').forEach(function (row) {
+      row.classList.toggle('selected', group !== '' && row === tr);
+    });
+    Array.prototype.forEach.call(files.tBodies[0].rows, function (row) {
+      row.hidden = group !== '' && row.dataset.group !== group;
+    });
+    filter.textContent = group === ''
+      ? 'Showing all files. Click a group to only show its files.'
+      : 'Showing files in ' + group + '. Click it again to show all files.';
+  });
+});
+"#;
+
+#[inline]
+fn header(titles: impl Iterator<Item = impl AsRef<str>>) -> String {
+    titles.fold(String::from("<tr>"), |mut acc, title| {
+        let _ = write!(acc, "<th>{}</th>", escape_xml(title.as_ref()));
+        acc
+    }) + "</tr>"
+}
+
+#[inline]
+fn count_cells(counts: &Counts) -> String {
+    counts.values().iter().fold(String::new(), |mut acc, n| {
+        let _ = write!(acc, r#"<td class="num" data-value="{0}">{0}</td>"#, n);
+        acc
+    })
+}
+
+/// Table with a row for each column of counts in @metrics, by its index in `Counts::values`, and a
+/// column for each group in @counts, followed by @totals. See --transpose.
+fn transposed_groups(
+    titles: &[String],
+    counts: &[(String, Counts)],
+    totals: Option<&Counts>,
+    metrics: &[usize],
+) -> String {
+    let mut html = format!(
+        r#"<table id="metrics" class="sortable">
+<thead>{}</thead>
+<tbody>
+"#,
+        header(
+            std::iter::once("Group")
+                .chain(counts.iter().map(|(group, _)| group.as_str()))
+                .chain(totals.map(|_| "TOTALS"))
+        ),
+    );
+    let values: Vec<Vec<u64>> = counts
+        .iter()
+        .map(|(_, counts)| counts)
+        .chain(totals)
+        .map(Counts::values)
+        .collect();
+    metrics.iter().for_each(|&i| {
+        let _ = writeln!(
+            html,
+            "<tr><td>{}</td>{}</tr>",
+            escape_xml(&titles[i]),
+            values.iter().fold(String::new(), |mut acc, values| {
+                let _ = write!(
+                    acc,
+                    r#"<td class="num" data-value="{0}">{0}</td>"#,
+                    values[i]
+                );
+                acc
+            }),
+        );
+    });
+    html.push_str("</tbody>\n</table>\n");
+    html
+}
+
+/// Pie chart of the number of tokens for each language in @files
+fn language_chart(files: &[FileCounts]) -> String {
+    let langs = language_tokens(files);
+    let total: u64 = langs.iter().map(|(_, n)| n).sum();
+    if total == 0 {
+        return String::from("<p>No tokens were counted.</p>");
+    }
+
+    let (r, c) = (90.0, 100.0);
+    let mut pie = String::from(r#"<svg width="200" height="200" viewBox="0 0 200 200">"#);
+    let mut legend = String::from(r#"<table class="legend">"#);
+    let mut start = -PI / 2.0;
+    langs.iter().for_each(|(lang, ntokens)| {
+        let share = *ntokens as f64 / total as f64;
+        let title = format!("{}: {} tokens ({:.1}%)", lang, ntokens, share * 100.0);
+        if langs.len() == 1 {
+            let _ = write!(
+                pie,
+                r#"<circle cx="{}" cy="{}" r="{}" fill="{}"><title>{}</title></circle>"#,
+                c,
+                c,
+                r,
+                lang.color(),
+                escape_xml(&title),
+            );
+        } else {
+            let end = start + share * 2.0 * PI;
+            let _ = write!(
+                pie,
+                r##"<path d="M{c},{c} L{:.2},{:.2} A{r},{r} 0 {} 1 {:.2},{:.2} Z" fill="{}" stroke="#fff"><title>{}</title></path>"##,
+                c + r * start.cos(),
+                c + r * start.sin(),
+                if share > 0.5 { 1 } else { 0 },
+                c + r * end.cos(),
+                c + r * end.sin(),
+                lang.color(),
+                escape_xml(&title),
+                c = c,
+                r = r,
+            );
+            start = end;
+        }
+        let _ = write!(
+            legend,
+            r#"<tr><td><span style="background: {}"></span>{}</td><td class="num">{}</td><td class="num">{:.1}%</td></tr>"#,
+            lang.color(),
+            escape_xml(&lang.to_string()),
+            ntokens,
+            share * 100.0,
+        );
+    });
+    pie.push_str("</svg>");
+    legend.push_str("</table>");
+    format!(r#"<div class="chart">{}{}</div>"#, pie, legend)
+}
+
+/// Render a self-contained HTML report with a sortable table of @counts, a table of every file in
+/// @files which can be filtered by group, a chart of the share of each language, and a treemap of
+/// the column at index @treemap_column in each directory. @titles are the titles of the columns
+/// from `Counts::values`. With @metrics the groups are transposed into a row for each of those
+/// columns, so they can't be clicked to filter the files.
+pub fn render(
+    titles: &[String],
+    counts: &[(String, Counts)],
+    totals: Option<&Counts>,
+    files: &[FileCounts],
+    treemap_column: usize,
+    metrics: Option<&[usize]>,
+) -> String {
+    let mut html = String::new();
+    let _ = write!(
+        html,
+        r#"<!DOCTYPE html>
+<html lang="en">
+<head>
+<meta charset="utf-8">
+<title>tcount report</title>
+<style>{}</style>
+</head>
+<body>
+<h1>tcount report</h1>
+"#,
+        STYLE
+    );
+
+    let _ = writeln!(html, "<h2>Languages</h2>\n{}", language_chart(files));
+
+    html.push_str("<h2>Groups</h2>\n");
+    if let Some(metrics) = metrics {
+        html.push_str(&transposed_groups(titles, counts, totals, metrics));
+    } else {
+        let _ = writeln!(
+            html,
+            r#"<table id="groups" class="sortable">
+<thead>{}</thead>
+<tbody>"#,
+            header(std::iter::once("Group").chain(titles.iter().map(String::as_str))),
+        );
+        counts.iter().for_each(|(group, counts)| {
+            let _ = writeln!(
+                html,
+                r#"<tr data-group="{0}"><td>{0}</td>{1}</tr>"#,
+                escape_xml(group),
+                count_cells(counts),
+            );
+        });
+        html.push_str("</tbody>\n");
+        if let Some(totals) = totals {
+            let _ = writeln!(
+                html,
+                "<tfoot><tr><td>TOTALS</td>{}</tr></tfoot>",
+                count_cells(totals)
+            );
+        }
+        html.push_str("</table>\n");
+    }
+
+    let mut sorted: Vec<&FileCounts> = files.iter().collect();
+    sorted.sort_by(|f1, f2| f1.path.cmp(&f2.path));
+    let _ = writeln!(
+        html,
+        r#"<h2>Files</h2>
+<p id="files-filter">{}</p>
+<table id="files" class="sortable" data-group="">
+<thead>{}</thead>
+<tbody>"#,
+        if metrics.is_some() {
+            "Showing all files."
+        } else {
+            "Showing all files. Click a group to only show its files."
+        },
+        header(
+            ["File", "Language", "Group"]
+                .iter()
+                .copied()
+                .chain(titles.iter().map(String::as_str))
+        ),
+    );
+    sorted.iter().for_each(|file| {
+        let _ = writeln!(
+            html,
+            r#"<tr data-group="{0}"><td>{1}</td><td>{2}</td><td>{0}</td>{3}</tr>"#,
+            escape_xml(&file.group),
+            escape_xml(&file.path.display().to_string()),
+            escape_xml(&file.lang.to_string()),
+            count_cells(&file.counts),
+        );
+    });
+    html.push_str("</tbody>\n</table>\n");
+
+    let _ = writeln!(
+        html,
+        "<h2>Directories</h2>\n{}",
+        treemap::svg(files, treemap_column, &titles[treemap_column], 960.0, 600.0)
+    );
+
+    let _ = write!(html, "<script>{}</script>\n</body>\n</html>\n", SCRIPT);
+    html
+}
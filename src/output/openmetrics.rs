@@ -0,0 +1,132 @@
+use crate::count::Counts;
+use crate::output::Column;
+use std::fmt::Write;
+
+const FAMILIES: [(&str, &str); 7] = [
+    ("files", "Number of files counted."),
+    ("tokens", "Number of tokens counted."),
+    (
+        "kind",
+        "Number of syntax tree nodes with a kind given by --kind.",
+    ),
+    (
+        "pattern",
+        "Number of syntax tree nodes with a kind matching a pattern given by --kind-pattern.",
+    ),
+    (
+        "query",
+        "Number of matches, or captures, of a Tree-sitter query given by --query.",
+    ),
+    (
+        "median_age_days",
+        "Median age in days of the tokens, from git blame with --groupby=author.",
+    ),
+    (
+        "old_tokens_percent",
+        "Percent of tokens more than a year old, from git blame with --groupby=author.",
+    ),
+];
+
+/// Escape a label value according to the OpenMetrics text format
+fn escape(s: &str) -> String {
+    s.replace('\\', r"\\")
+        .replace('"', "\\\"")
+        .replace('\n', r"\n")
+}
+
+/// The metric family and labels which identify @column
+fn metric(column: &Column) -> (&'static str, Vec<(&'static str, &String)>) {
+    match column {
+        Column::Files => ("files", Vec::new()),
+        Column::Tokens => ("tokens", Vec::new()),
+        Column::Kind(kind) => ("kind", vec![("kind", kind)]),
+        Column::Pattern(pattern) => ("pattern", vec![("pattern", pattern)]),
+        Column::Query(name) => ("query", vec![("query", name)]),
+        Column::Capture(name, capture) => ("query", vec![("query", name), ("capture", capture)]),
+        Column::MedianAge => ("median_age_days", Vec::new()),
+        Column::PercentOld => ("old_tokens_percent", Vec::new()),
+    }
+}
+
+/// Render @counts as a single gauge labelled by the key of each column and by the group, with the
+/// samples of each group together and its columns in the order of @metrics. See --transpose.
+fn transposed(counts: &[(String, Counts)], columns: &[Column], metrics: &[usize]) -> String {
+    let mut out = String::from(
+        "# HELP tcount Every count, labelled by its column and group.\n# TYPE tcount gauge\n",
+    );
+    counts.iter().for_each(|(group, counts)| {
+        let values = counts.values();
+        metrics.iter().for_each(|&i| {
+            let _ = writeln!(
+                out,
+                "tcount{{column=\"{}\",group=\"{}\"}} {}",
+                escape(&columns[i].key()),
+                escape(group),
+                values[i]
+            );
+        });
+    });
+    out.push_str("# EOF\n");
+    out
+}
+
+/// Render @counts in the OpenMetrics text exposition format. Each column of counts is a gauge
+/// labelled by the group from --groupby. Totals are left out since a sample without the group
+/// label would be counted twice by aggregations like `sum(tcount_tokens)`. With @metrics, the
+/// columns to show by their index in `Counts::values`, the counts are transposed.
+pub fn render(
+    counts: &[(String, Counts)],
+    columns: &[Column],
+    metrics: Option<&[usize]>,
+) -> String {
+    if let Some(metrics) = metrics {
+        return transposed(counts, columns, metrics);
+    }
+    let metrics: Vec<_> = columns.iter().map(metric).collect();
+    let rows: Vec<(&String, Vec<u64>)> = counts
+        .iter()
+        .map(|(group, counts)| (group, counts.values()))
+        .collect();
+
+    let mut out = String::new();
+    FAMILIES.iter().for_each(|(family, help)| {
+        if !metrics.iter().any(|(f, _)| f == family) {
+            return;
+        }
+        let _ = writeln!(out, "# HELP tcount_{} {}", family, help);
+        let _ = writeln!(out, "# TYPE tcount_{} gauge", family);
+        metrics
+            .iter()
+            .enumerate()
+            .filter(|(_, (f, _))| f == family)
+            .for_each(|(i, (_, labels))| {
+                rows.iter().for_each(|(group, values)| {
+                    let labels: Vec<String> = labels
+                        .iter()
+                        .copied()
+                        .chain(std::iter::once(("group", *group)))
+                        .map(|(name, value)| format!("{}=\"{}\"", name, escape(value)))
+                        .collect();
+                    let _ = writeln!(
+                        out,
+                        "tcount_{}{{{}}} {}",
+                        family,
+                        labels.join(","),
+                        values[i]
+                    );
+                });
+            });
+    });
+    out.push_str("# EOF\n");
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn escape_label_values() {
+        assert_eq!(r#"a\\b\"c\nd"#, escape("a\\b\"c\nd"));
+    }
+}
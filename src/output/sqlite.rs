@@ -0,0 +1,77 @@
+use crate::count::FileCounts;
+use crate::error::Result;
+use crate::output::Column;
+use rusqlite::{params, Connection};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS runs (
+    id         INTEGER PRIMARY KEY,
+    timestamp  INTEGER NOT NULL,
+    revision   TEXT,
+    args       TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS files (
+    id         INTEGER PRIMARY KEY,
+    run_id     INTEGER NOT NULL REFERENCES runs(id),
+    path       TEXT NOT NULL,
+    language   TEXT NOT NULL,
+    group_name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS counts (
+    file_id    INTEGER NOT NULL REFERENCES files(id),
+    name       TEXT NOT NULL,
+    value      INTEGER NOT NULL
+);
+CREATE INDEX IF NOT EXISTS files_run_id ON files(run_id);
+CREATE INDEX IF NOT EXISTS counts_file_id ON counts(file_id);
+";
+
+/// Append a run of tcount to the SQLite database at @path, creating it if it doesn't exist. The
+/// run is recorded with the time, the git @revision which was counted, and @args including the
+/// options from configuration files. Every file in @files is recorded along with a row in the
+/// counts table for each of its counts.
+pub fn write(
+    path: impl AsRef<Path>,
+    revision: Option<String>,
+    args: &str,
+    columns: &[Column],
+    files: &[FileCounts],
+) -> Result<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let timestamp = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0);
+    let tx = conn.transaction()?;
+    tx.execute(
+        "INSERT INTO runs (timestamp, revision, args) VALUES (?1, ?2, ?3)",
+        params![timestamp, revision, args],
+    )?;
+    let run_id = tx.last_insert_rowid();
+    {
+        let mut insert_file = tx.prepare(
+            "INSERT INTO files (run_id, path, language, group_name) VALUES (?1, ?2, ?3, ?4)",
+        )?;
+        let mut insert_count =
+            tx.prepare("INSERT INTO counts (file_id, name, value) VALUES (?1, ?2, ?3)")?;
+        for file in files {
+            insert_file.execute(params![
+                run_id,
+                file.path.display().to_string(),
+                file.lang.to_string(),
+                file.group,
+            ])?;
+            let file_id = tx.last_insert_rowid();
+            // the files column is always 1 for a single file so it isn't worth storing
+            for (column, value) in columns.iter().zip(file.counts.values()).skip(1) {
+                insert_count.execute(params![file_id, column.key(), value as i64])?;
+            }
+        }
+    }
+    tx.commit()?;
+    Ok(())
+}
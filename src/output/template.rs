@@ -0,0 +1,720 @@
+use crate::error::{Error, Result};
+use crate::output::{compact, thousands};
+use serde_json::{Map, Value};
+use std::fs;
+
+/// A template given by --template. Templates are text with tags in the style of Jinja:
+///
+///   {{ rows.0.group | pad(10) }}      print a value, passing it through filters
+///   {% for row in rows %}...{% endfor %}
+///   {% if not loop.last %}...{% else %}...{% endif %}
+///   {# a comment #}
+///
+/// Values are looked up in the context from `output::context` by a path of names, indexes, and
+/// bracketed lookups such as `row.counts[column.key]`. Inside of a for loop `loop.index`,
+/// `loop.index0`, `loop.first`, `loop.last`, and `loop.length` describe the current iteration.
+/// Adding a dash to a tag (e.g. `{%-` or `-%}`) trims the whitespace before or after it.
+#[derive(Debug)]
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Segment {
+    Name(String),
+    Lookup(Arg),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct Path {
+    root: String,
+    segments: Vec<Segment>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Arg {
+    Literal(Value),
+    Path(Path),
+}
+
+#[derive(Debug, PartialEq)]
+struct Filter {
+    name: String,
+    args: Vec<Arg>,
+}
+
+#[derive(Debug, PartialEq)]
+struct Expr {
+    arg: Arg,
+    filters: Vec<Filter>,
+}
+
+#[derive(Debug, PartialEq)]
+enum Node {
+    Text(String),
+    Print(Expr),
+    For {
+        var: String,
+        items: Expr,
+        body: Vec<Node>,
+    },
+    If {
+        negate: bool,
+        cond: Expr,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+/// Filters which can be used in templates along with how many arguments they take
+const FILTERS: [(&str, usize); 11] = [
+    ("pad", 1),
+    ("lpad", 1),
+    ("thousands", 0),
+    ("compact", 0),
+    ("percent", 1),
+    ("round", 1),
+    ("upper", 0),
+    ("lower", 0),
+    ("length", 0),
+    ("json", 0),
+    ("latex", 0),
+];
+
+/// A piece of the template source which is either text or the inside of a tag
+#[derive(Debug)]
+enum Piece<'a> {
+    Text(String),
+    Print(&'a str, usize),
+    Block(&'a str, usize),
+}
+
+/// Split @src into text and tags, trimming whitespace around tags with dashes
+fn pieces(src: &str) -> std::result::Result<Vec<Piece<'_>>, String> {
+    let mut pieces = Vec::new();
+    let mut rest = src;
+    let mut trim_next = false;
+    loop {
+        let start = ["{{", "{%", "{#"]
+            .iter()
+            .filter_map(|open| rest.find(open))
+            .min();
+        let text = &rest[..start.unwrap_or(rest.len())];
+        let text = if trim_next { text.trim_start() } else { text };
+        let start = match start {
+            Some(start) => start,
+            None => {
+                pieces.push(Piece::Text(text.to_string()));
+                return Ok(pieces);
+            }
+        };
+
+        let line = src[..src.len() - rest.len() + start].matches('\n').count() + 1;
+        let close = match &rest[start..start + 2] {
+            "{{" => "}}",
+            "{%" => "%}",
+            _ => "#}",
+        };
+        let end = rest[start..]
+            .find(close)
+            .map(|end| start + end)
+            .ok_or_else(|| format!("line {}: tag is never closed with {}", line, close))?;
+        let mut inner = &rest[start + 2..end];
+        if let Some(stripped) = inner.strip_prefix('-') {
+            inner = stripped;
+            pieces.push(Piece::Text(text.trim_end().to_string()));
+        } else {
+            pieces.push(Piece::Text(text.to_string()));
+        }
+        trim_next = false;
+        if let Some(stripped) = inner.strip_suffix('-') {
+            inner = stripped;
+            trim_next = true;
+        }
+        match close {
+            "}}" => pieces.push(Piece::Print(inner.trim(), line)),
+            "%}" => pieces.push(Piece::Block(inner.trim(), line)),
+            _ => {}
+        }
+        rest = &rest[end + 2..];
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Literal(Value),
+    Sym(char),
+}
+
+fn tokenize(s: &str) -> std::result::Result<Vec<Token>, String> {
+    let mut tokens = Vec::new();
+    let mut chars = s.chars().peekable();
+    while let Some(&c) = chars.peek() {
+        if c.is_whitespace() {
+            chars.next();
+        } else if c.is_ascii_digit() {
+            // numbers after a "." are indexes, e.g. rows.0.group, so they can't have a fraction
+            let mut fraction = tokens.last() != Some(&Token::Sym('.'));
+            let mut num = String::new();
+            while let Some(&c) = chars.peek() {
+                let digit_after = || matches!(chars.clone().nth(1), Some(c) if c.is_ascii_digit());
+                if c.is_ascii_digit() {
+                    num.push(c);
+                } else if c == '.' && fraction && digit_after() {
+                    num.push(c);
+                    fraction = false;
+                } else {
+                    break;
+                }
+                chars.next();
+            }
+            let value = match num.parse::<u64>() {
+                Ok(n) => Value::from(n),
+                Err(_) => num
+                    .parse::<f64>()
+                    .map(Value::from)
+                    .map_err(|_| format!("\"{}\" is not a number", num))?,
+            };
+            tokens.push(Token::Literal(value));
+        } else if c == '"' || c == '\'' {
+            chars.next();
+            let mut string = String::new();
+            loop {
+                match chars.next() {
+                    Some(q) if q == c => break,
+                    Some('\\') => string.extend(chars.next()),
+                    Some(ch) => string.push(ch),
+                    None => return Err(String::from("string is never closed")),
+                }
+            }
+            tokens.push(Token::Literal(Value::from(string)));
+        } else if c.is_alphanumeric() || c == '_' {
+            let mut ident = String::new();
+            while let Some(&c) = chars.peek() {
+                if c.is_alphanumeric() || c == '_' {
+                    ident.push(c);
+                    chars.next();
+                } else {
+                    break;
+                }
+            }
+            tokens.push(Token::Ident(ident));
+        } else if ".[]|(),".contains(c) {
+            tokens.push(Token::Sym(c));
+            chars.next();
+        } else {
+            return Err(format!("unexpected \"{}\"", c));
+        }
+    }
+    Ok(tokens)
+}
+
+/// Parses the tokens inside of a single tag
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn new(s: &str) -> std::result::Result<Parser, String> {
+        Ok(Parser {
+            tokens: tokenize(s)?,
+            pos: 0,
+        })
+    }
+
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, sym: char) -> bool {
+        if self.peek() == Some(&Token::Sym(sym)) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, sym: char) -> std::result::Result<(), String> {
+        if self.eat(sym) {
+            Ok(())
+        } else {
+            Err(format!("expected \"{}\"", sym))
+        }
+    }
+
+    fn ident(&mut self) -> std::result::Result<String, String> {
+        match self.next() {
+            Some(Token::Ident(ident)) => Ok(ident),
+            _ => Err(String::from("expected a name")),
+        }
+    }
+
+    fn keyword(&mut self, keyword: &str) -> bool {
+        if self.peek() == Some(&Token::Ident(keyword.to_string())) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn end(&self) -> std::result::Result<(), String> {
+        match self.peek() {
+            None => Ok(()),
+            Some(token) => Err(format!("unexpected {:?}", token)),
+        }
+    }
+
+    fn arg(&mut self) -> std::result::Result<Arg, String> {
+        match self.next() {
+            Some(Token::Literal(value)) => Ok(Arg::Literal(value)),
+            Some(Token::Ident(root)) => {
+                let mut segments = Vec::new();
+                loop {
+                    if self.eat('.') {
+                        match self.next() {
+                            Some(Token::Ident(name)) => segments.push(Segment::Name(name)),
+                            Some(Token::Literal(Value::Number(n))) => {
+                                segments.push(Segment::Name(n.to_string()))
+                            }
+                            _ => return Err(String::from("expected a name after \".\"")),
+                        }
+                    } else if self.eat('[') {
+                        segments.push(Segment::Lookup(self.arg()?));
+                        self.expect(']')?;
+                    } else {
+                        break;
+                    }
+                }
+                Ok(Arg::Path(Path { root, segments }))
+            }
+            _ => Err(String::from("expected a value")),
+        }
+    }
+
+    fn expr(&mut self) -> std::result::Result<Expr, String> {
+        let arg = self.arg()?;
+        let mut filters = Vec::new();
+        while self.eat('|') {
+            let name = self.ident()?;
+            let mut args = Vec::new();
+            if self.eat('(') && !self.eat(')') {
+                loop {
+                    args.push(self.arg()?);
+                    if self.eat(')') {
+                        break;
+                    }
+                    self.expect(',')?;
+                }
+            }
+            match FILTERS.iter().find(|(filter, _)| *filter == name) {
+                Some((_, nargs)) if *nargs == args.len() => {}
+                Some((_, nargs)) => {
+                    return Err(format!(
+                        "filter \"{}\" takes {} argument(s) but was given {}",
+                        name,
+                        nargs,
+                        args.len()
+                    ))
+                }
+                None => return Err(format!("unknown filter \"{}\"", name)),
+            }
+            filters.push(Filter { name, args });
+        }
+        Ok(Expr { arg, filters })
+    }
+}
+
+/// Parse nodes from @pieces until one of the blocks in @until, which is returned along with the
+/// nodes.
+fn parse_nodes<'a>(
+    pieces: &mut std::vec::IntoIter<Piece<'a>>,
+    until: &[&str],
+) -> std::result::Result<(Vec<Node>, Option<&'a str>), String> {
+    let mut nodes = Vec::new();
+    while let Some(piece) = pieces.next() {
+        let (tag, line) = match piece {
+            Piece::Text(text) => {
+                if !text.is_empty() {
+                    nodes.push(Node::Text(text));
+                }
+                continue;
+            }
+            Piece::Print(tag, line) => {
+                let mut parser = Parser::new(tag).map_err(|e| format!("line {}: {}", line, e))?;
+                let expr = parser
+                    .expr()
+                    .and_then(|expr| parser.end().map(|_| expr))
+                    .map_err(|e| format!("line {}: {}", line, e))?;
+                nodes.push(Node::Print(expr));
+                continue;
+            }
+            Piece::Block(tag, line) => (tag, line),
+        };
+        let keyword = tag.split_whitespace().next().unwrap_or("");
+        if until.contains(&keyword) {
+            return Ok((nodes, Some(tag)));
+        }
+        let err = |e: String| format!("line {}: {}", line, e);
+        let mut parser = Parser::new(tag).map_err(err)?;
+        match keyword {
+            "for" => {
+                parser.keyword("for");
+                let var = parser.ident().map_err(err)?;
+                if !parser.keyword("in") {
+                    return Err(err(String::from("expected \"in\"")));
+                }
+                let items = parser.expr().map_err(err)?;
+                parser.end().map_err(err)?;
+                let (body, end) = parse_nodes(pieces, &["endfor"])?;
+                if end.is_none() {
+                    return Err(err(String::from("for is never closed with endfor")));
+                }
+                nodes.push(Node::For { var, items, body });
+            }
+            "if" => {
+                parser.keyword("if");
+                let negate = parser.keyword("not");
+                let cond = parser.expr().map_err(err)?;
+                parser.end().map_err(err)?;
+                let (then, end) = parse_nodes(pieces, &["else", "endif"])?;
+                let otherwise = match end {
+                    Some("else") => match parse_nodes(pieces, &["endif"])? {
+                        (otherwise, Some(_)) => otherwise,
+                        _ => return Err(err(String::from("if is never closed with endif"))),
+                    },
+                    Some(_) => Vec::new(),
+                    None => return Err(err(String::from("if is never closed with endif"))),
+                };
+                nodes.push(Node::If {
+                    negate,
+                    cond,
+                    then,
+                    otherwise,
+                });
+            }
+            _ => return Err(err(format!("unexpected \"{}\"", tag))),
+        }
+    }
+    Ok((nodes, None))
+}
+
+/// Whether @value counts as true in an if
+fn truthy(value: &Value) -> bool {
+    match value {
+        Value::Null => false,
+        Value::Bool(b) => *b,
+        Value::Number(n) => n.as_f64().unwrap_or(0.0) != 0.0,
+        Value::String(s) => !s.is_empty(),
+        Value::Array(a) => !a.is_empty(),
+        Value::Object(o) => !o.is_empty(),
+    }
+}
+
+/// How @value is printed
+fn text(value: &Value) -> String {
+    match value {
+        Value::Null => String::new(),
+        Value::String(s) => s.clone(),
+        _ => value.to_string(),
+    }
+}
+
+fn number(value: &Value) -> std::result::Result<f64, String> {
+    value
+        .as_f64()
+        .ok_or_else(|| format!("expected a number but got {}", value))
+}
+
+fn width(value: &Value) -> std::result::Result<usize, String> {
+    value
+        .as_u64()
+        .map(|n| n as usize)
+        .ok_or_else(|| format!("expected a width but got {}", value))
+}
+
+fn escape_latex(s: &str) -> String {
+    s.chars()
+        .fold(String::with_capacity(s.len()), |mut acc, c| {
+            match c {
+                '\\' => acc.push_str(r"\textbackslash{}"),
+                '~' => acc.push_str(r"\textasciitilde{}"),
+                '^' => acc.push_str(r"\textasciicircum{}"),
+                '&' | '%' | '$' | '#' | '_' | '{' | '}' => {
+                    acc.push('\\');
+                    acc.push(c);
+                }
+                _ => acc.push(c),
+            }
+            acc
+        })
+}
+
+fn filter(name: &str, value: Value, args: &[Value]) -> std::result::Result<Value, String> {
+    Ok(match name {
+        "pad" => Value::from(format!("{:<1$}", text(&value), width(&args[0])?)),
+        "lpad" => Value::from(format!("{:>1$}", text(&value), width(&args[0])?)),
+        "thousands" => match value.as_u64() {
+            Some(n) => Value::from(thousands(n)),
+            None => value,
+        },
+        "compact" => match value.as_u64() {
+            Some(n) => Value::from(compact(n)),
+            None => value,
+        },
+        "percent" => {
+            let total = number(&args[0])?;
+            let share = if total == 0.0 {
+                0.0
+            } else {
+                number(&value)? * 100.0 / total
+            };
+            Value::from(format!("{:.1}", share))
+        }
+        "round" => Value::from(format!("{:.1$}", number(&value)?, width(&args[0])?)),
+        "upper" => Value::from(text(&value).to_uppercase()),
+        "lower" => Value::from(text(&value).to_lowercase()),
+        "length" => Value::from(match &value {
+            Value::Array(a) => a.len(),
+            Value::Object(o) => o.len(),
+            _ => text(&value).chars().count(),
+        }),
+        "json" => Value::from(value.to_string()),
+        "latex" => Value::from(escape_latex(&text(&value))),
+        _ => unreachable!("filters are checked while parsing"),
+    })
+}
+
+/// The context which templates are rendered with along with the variables of the enclosing loops
+struct Scope<'a> {
+    context: &'a Value,
+    vars: Vec<(String, Value)>,
+}
+
+impl<'a> Scope<'a> {
+    fn lookup(&self, path: &Path) -> std::result::Result<Value, String> {
+        let mut value = self
+            .vars
+            .iter()
+            .rev()
+            .find(|(var, _)| *var == path.root)
+            .map(|(_, value)| value)
+            .or_else(|| self.context.get(&path.root))
+            .cloned()
+            .ok_or_else(|| format!("unknown variable \"{}\"", path.root))?;
+        for segment in &path.segments {
+            let key = match segment {
+                Segment::Name(name) => Value::from(name.as_str()),
+                Segment::Lookup(arg) => self.eval_arg(arg)?,
+            };
+            value = match (&value, &key) {
+                (Value::Object(o), _) => o.get(&text(&key)).cloned().unwrap_or(Value::Null),
+                (Value::Array(a), _) => text(&key)
+                    .parse::<usize>()
+                    .ok()
+                    .and_then(|i| a.get(i))
+                    .cloned()
+                    .unwrap_or(Value::Null),
+                _ => Value::Null,
+            };
+        }
+        Ok(value)
+    }
+
+    fn eval_arg(&self, arg: &Arg) -> std::result::Result<Value, String> {
+        match arg {
+            Arg::Literal(value) => Ok(value.clone()),
+            Arg::Path(path) => self.lookup(path),
+        }
+    }
+
+    fn eval(&self, expr: &Expr) -> std::result::Result<Value, String> {
+        expr.filters
+            .iter()
+            .try_fold(self.eval_arg(&expr.arg)?, |value, f| {
+                let args = f
+                    .args
+                    .iter()
+                    .map(|arg| self.eval_arg(arg))
+                    .collect::<std::result::Result<Vec<Value>, String>>()?;
+                filter(&f.name, value, &args)
+            })
+    }
+
+    fn render(&mut self, nodes: &[Node], out: &mut String) -> std::result::Result<(), String> {
+        for node in nodes {
+            match node {
+                Node::Text(s) => out.push_str(s),
+                Node::Print(expr) => out.push_str(&text(&self.eval(expr)?)),
+                Node::For { var, items, body } => {
+                    let items = match self.eval(items)? {
+                        Value::Array(a) => a,
+                        Value::Object(o) => o
+                            .into_iter()
+                            .map(|(key, value)| {
+                                let mut item = Map::new();
+                                item.insert("key".into(), key.into());
+                                item.insert("value".into(), value);
+                                Value::from(item)
+                            })
+                            .collect(),
+                        Value::Null => Vec::new(),
+                        value => return Err(format!("can't loop over {}", value)),
+                    };
+                    let length = items.len();
+                    for (i, item) in items.into_iter().enumerate() {
+                        let mut info = Map::new();
+                        info.insert("index".into(), (i + 1).into());
+                        info.insert("index0".into(), i.into());
+                        info.insert("first".into(), (i == 0).into());
+                        info.insert("last".into(), (i + 1 == length).into());
+                        info.insert("length".into(), length.into());
+                        self.vars.push((String::from("loop"), info.into()));
+                        self.vars.push((var.clone(), item));
+                        let res = self.render(body, out);
+                        self.vars.truncate(self.vars.len() - 2);
+                        res?;
+                    }
+                }
+                Node::If {
+                    negate,
+                    cond,
+                    then,
+                    otherwise,
+                } => {
+                    if truthy(&self.eval(cond)?) != *negate {
+                        self.render(then, out)?;
+                    } else {
+                        self.render(otherwise, out)?;
+                    }
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Template {
+    fn parse(src: &str) -> std::result::Result<Template, String> {
+        let mut pieces = pieces(src)?.into_iter();
+        match parse_nodes(&mut pieces, &[])? {
+            (nodes, None) => Ok(Template { nodes }),
+            (_, Some(tag)) => Err(format!("unexpected \"{}\"", tag)),
+        }
+    }
+
+    /// Read and parse the template at @path
+    pub fn from_path(path: impl AsRef<std::path::Path>) -> Result<Template> {
+        let src = fs::read_to_string(path.as_ref())?;
+        Template::parse(&src)
+            .map_err(|e| Error::Template(format!("{}: {}", path.as_ref().display(), e)))
+    }
+
+    /// Render the template with @context
+    pub fn render(&self, context: &Value) -> Result<String> {
+        let mut out = String::new();
+        Scope {
+            context,
+            vars: Vec::new(),
+        }
+        .render(&self.nodes, &mut out)
+        .map_err(Error::Template)?;
+        Ok(out)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn render(src: &str, context: Value) -> std::result::Result<String, String> {
+        let template = Template::parse(src)?;
+        let mut out = String::new();
+        Scope {
+            context: &context,
+            vars: Vec::new(),
+        }
+        .render(&template.nodes, &mut out)?;
+        Ok(out)
+    }
+
+    #[test]
+    fn render_loops_and_lookups() {
+        let context = json!({
+            "columns": [{"key": "files"}, {"key": "kind:line_comment"}],
+            "rows": [
+                {"group": "Rust", "counts": {"files": 5, "kind:line_comment": 9}},
+                {"group": "Go", "counts": {"files": 1, "kind:line_comment": 0}},
+            ],
+        });
+        let src = "{% for row in rows -%}
+{{ row.group }}{% for col in columns %} {{ row.counts[col.key] }}{% endfor %}
+{% endfor %}";
+        assert_eq!(
+            Ok(String::from("Rust 5 9\nGo 1 0\n")),
+            render(src, context.clone())
+        );
+        assert_eq!(
+            Ok(String::from("Go,kind:line_comment")),
+            render("{{ rows.1.group }},{{ columns[1].key }}", context)
+        );
+    }
+
+    #[test]
+    fn render_conditions() {
+        let context = json!({"items": ["a", "b", "c"], "empty": []});
+        assert_eq!(
+            Ok(String::from("[a, b, c]")),
+            render(
+                "[{% for i in items %}{{ i }}{% if not loop.last %}, {% endif %}{% endfor %}]",
+                context.clone()
+            )
+        );
+        assert_eq!(
+            Ok(String::from("none")),
+            render("{% if empty %}some{% else %}none{% endif %}", context)
+        );
+    }
+
+    #[test]
+    fn render_filters() {
+        let context = json!({"n": 12345, "name": "a_b", "total": 50000});
+        assert_eq!(
+            Ok(String::from("12,345|12.3k|24.7|  a_b|a_b  |A\\_B|\"a_b\"|3")),
+            render(
+                "{{ n | thousands }}|{{ n | compact }}|{{ n | percent(total) }}|{{ name | lpad(5) }}|{{ name | pad(5) }}|{{ name | upper | latex }}|{{ name | json }}|{{ name | length }}",
+                context
+            )
+        );
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(
+            Err(String::from("line 2: unknown filter \"nope\"")),
+            Template::parse("\n{{ a | nope }}").map(|_| ())
+        );
+        assert_eq!(
+            Err(String::from("line 1: for is never closed with endfor")),
+            Template::parse("{% for a in b %}").map(|_| ())
+        );
+        assert_eq!(
+            Err(String::from("line 1: tag is never closed with }}")),
+            Template::parse("{{ a").map(|_| ())
+        );
+        assert_eq!(
+            Err(String::from("unknown variable \"rowz\"")),
+            render("{{ rowz }}", json!({"rows": []}))
+        );
+    }
+}
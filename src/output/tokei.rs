@@ -0,0 +1,95 @@
+use crate::count::{Counts, FileCounts};
+use crate::language::Language;
+use crate::output::{by_language, sum, Column};
+use serde_json::{json, Map, Value};
+
+/// The counts in @counts other than the number of files and tokens, keyed by `Column::key`
+fn extra(columns: &[Column], counts: &Counts) -> Value {
+    columns
+        .iter()
+        .zip(counts.values())
+        .skip(2)
+        .map(|(column, value)| (column.key(), Value::from(value)))
+        .collect::<Map<String, Value>>()
+        .into()
+}
+
+fn stats(columns: &[Column], counts: &Counts) -> Map<String, Value> {
+    let mut stats = Map::new();
+    stats.insert("blanks".into(), counts.lines.blanks.into());
+    stats.insert("code".into(), counts.lines.code.into());
+    stats.insert("comments".into(), counts.lines.comments.into());
+    stats.insert("tokens".into(), counts.ntokens.into());
+    stats.insert("tcount".into(), extra(columns, counts));
+    stats
+}
+
+/// An object keyed by each count of @langs, and of their @total, with an object of the count in
+/// each language and the Total. See --transpose.
+fn transposed(
+    langs: &[(Language, Counts, Vec<&FileCounts>)],
+    total: &Counts,
+    columns: &[Column],
+) -> Value {
+    let rows: Vec<(String, &Counts)> = langs
+        .iter()
+        .map(|(lang, counts, _)| (lang.to_string(), counts))
+        .chain(std::iter::once((String::from("Total"), total)))
+        .collect();
+    let metric = |value: &dyn Fn(&Counts) -> u64| {
+        rows.iter()
+            .map(|(name, counts)| (name.clone(), Value::from(value(counts))))
+            .collect::<Map<String, Value>>()
+    };
+    let mut output = Map::new();
+    output.insert(
+        "blanks".into(),
+        metric(&|counts| counts.lines.blanks).into(),
+    );
+    output.insert("code".into(), metric(&|counts| counts.lines.code).into());
+    output.insert(
+        "comments".into(),
+        metric(&|counts| counts.lines.comments).into(),
+    );
+    columns.iter().enumerate().for_each(|(i, column)| {
+        output.insert(column.key(), metric(&|counts| counts.values()[i]).into());
+    });
+    output.into()
+}
+
+/// Render @files in the same shape as `tokei --output json`: an object keyed by language with the
+/// lines of each file in its reports, and a Total. tokei has no notion of tokens so the number of
+/// tokens is an extra "tokens" field and every other count is in an extra "tcount" object. With
+/// @transpose it's keyed by each count instead, and files aren't reported.
+pub fn render(files: &[FileCounts], columns: &[Column], transpose: bool) -> String {
+    let langs = by_language(files);
+    if transpose {
+        let total = sum(langs.iter().map(|(_, counts, _)| counts));
+        return transposed(&langs, &total, columns).to_string() + "\n";
+    }
+    let mut output = Map::new();
+    let mut children = Map::new();
+    langs.iter().for_each(|(lang, counts, files)| {
+        let reports: Vec<Value> = files
+            .iter()
+            .map(|file| {
+                let mut stats = stats(columns, &file.counts);
+                stats.insert("blobs".into(), json!({}));
+                json!({ "name": file.path.display().to_string(), "stats": stats })
+            })
+            .collect();
+        let mut language = stats(columns, counts);
+        language.insert("children".into(), json!({}));
+        language.insert("inaccurate".into(), false.into());
+        language.insert("reports".into(), reports.clone().into());
+        output.insert(lang.to_string(), language.into());
+        children.insert(lang.to_string(), reports.into());
+    });
+
+    let mut summary = stats(columns, &sum(langs.iter().map(|(_, counts, _)| counts)));
+    summary.insert("children".into(), children.into());
+    summary.insert("inaccurate".into(), false.into());
+    summary.insert("reports".into(), json!([]));
+    output.insert("Total".into(), summary.into());
+    Value::from(output).to_string() + "\n"
+}
@@ -0,0 +1,366 @@
+use crate::count::FileCounts;
+use crate::language::Language;
+use crate::output::escape_xml;
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::path::Component;
+
+/// Height of the label drawn at the top of each directory
+const HEADER: f64 = 14.0;
+/// Space between a directory's border and its children
+const PADDING: f64 = 2.0;
+/// Height of the legend below standalone treemaps
+const LEGEND: f64 = 24.0;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Rect {
+    pub x: f64,
+    pub y: f64,
+    pub w: f64,
+    pub h: f64,
+}
+
+/// A directory or file in the treemap. @file is only set for files.
+#[derive(Debug)]
+pub struct Cell<'a> {
+    pub rect: Rect,
+    pub path: String,
+    pub value: u64,
+    pub file: Option<&'a FileCounts>,
+}
+
+/// A directory tree built from the paths of the counted files
+#[derive(Default)]
+struct Node<'a> {
+    value: u64,
+    children: BTreeMap<String, Node<'a>>,
+    file: Option<&'a FileCounts>,
+}
+
+impl<'a> Node<'a> {
+    fn insert(&mut self, components: &[String], file: &'a FileCounts, value: u64) {
+        self.value += value;
+        match components.split_first() {
+            Some((name, [])) => {
+                let leaf = self.children.entry(name.clone()).or_default();
+                leaf.value += value;
+                leaf.file = Some(file);
+            }
+            Some((name, rest)) => self
+                .children
+                .entry(name.clone())
+                .or_default()
+                .insert(rest, file, value),
+            None => {}
+        }
+    }
+}
+
+/// Layout @files as a squarified treemap nested by directory inside a @width by @height area. The
+/// area of each file is the value of the column at index @column of `Counts::values`. Parents are
+/// always before their children in the result.
+pub fn layout(files: &[FileCounts], column: usize, width: f64, height: f64) -> Vec<Cell<'_>> {
+    let mut root = Node::default();
+    files.iter().for_each(|file| {
+        let components: Vec<String> = file
+            .path
+            .components()
+            .filter(|c| matches!(c, Component::Normal(_)))
+            .map(|c| c.as_os_str().to_string_lossy().to_string())
+            .collect();
+        let value = file.counts.values().get(column).copied().unwrap_or(0);
+        root.insert(&components, file, value);
+    });
+
+    let mut cells = Vec::new();
+    let rect = Rect {
+        x: 0.0,
+        y: 0.0,
+        w: width,
+        h: height,
+    };
+    layout_children(&root, rect, "", &mut cells);
+    cells
+}
+
+fn layout_children<'a>(node: &Node<'a>, rect: Rect, prefix: &str, cells: &mut Vec<Cell<'a>>) {
+    let mut children: Vec<(&String, &Node)> = node
+        .children
+        .iter()
+        .filter(|(_, child)| child.value > 0)
+        .collect();
+    // squarifying works best when laying out the largest areas first
+    children.sort_by_key(|(_, child)| Reverse(child.value));
+
+    let values: Vec<f64> = children
+        .iter()
+        .map(|(_, child)| child.value as f64)
+        .collect();
+    squarify(&values, rect)
+        .into_iter()
+        .zip(children)
+        .for_each(|(rect, (name, child))| {
+            let path = if prefix.is_empty() {
+                name.clone()
+            } else {
+                format!("{}/{}", prefix, name)
+            };
+            cells.push(Cell {
+                rect,
+                path: path.clone(),
+                value: child.value,
+                file: child.file,
+            });
+            if child.file.is_none() {
+                let inner = Rect {
+                    x: rect.x + PADDING,
+                    y: rect.y + HEADER,
+                    w: rect.w - 2.0 * PADDING,
+                    h: rect.h - HEADER - PADDING,
+                };
+                if inner.w > 0.0 && inner.h > 0.0 {
+                    layout_children(child, inner, &path, cells);
+                }
+            }
+        });
+}
+
+/// Split @rect into rectangles with areas proportional to @values using the squarified treemap
+/// algorithm from Bruls, Huizing, and van Wijk. @values should be sorted in descending order.
+fn squarify(values: &[f64], rect: Rect) -> Vec<Rect> {
+    let total: f64 = values.iter().sum();
+    if total <= 0.0 || rect.w <= 0.0 || rect.h <= 0.0 {
+        return Vec::new();
+    }
+    let scale = rect.w * rect.h / total;
+    let areas: Vec<f64> = values.iter().map(|v| v * scale).collect();
+
+    // the worst aspect ratio of a row of @areas laid out along a side of length @side
+    let worst = |areas: &[f64], side: f64| -> f64 {
+        let sum: f64 = areas.iter().sum();
+        let max = areas.iter().cloned().fold(f64::MIN, f64::max);
+        let min = areas.iter().cloned().fold(f64::MAX, f64::min);
+        f64::max(
+            side * side * max / (sum * sum),
+            sum * sum / (side * side * min),
+        )
+    };
+
+    let mut rects = Vec::with_capacity(areas.len());
+    let mut free = rect;
+    let mut start = 0;
+    while start < areas.len() {
+        let side = free.w.min(free.h);
+        let mut end = start + 1;
+        while end < areas.len()
+            && worst(&areas[start..=end], side) <= worst(&areas[start..end], side)
+        {
+            end += 1;
+        }
+
+        let row = &areas[start..end];
+        let sum: f64 = row.iter().sum();
+        if free.w >= free.h {
+            // lay the row out as a column on the left of the free space
+            let w = if free.h > 0.0 { sum / free.h } else { 0.0 };
+            let mut y = free.y;
+            row.iter().for_each(|area| {
+                let h = if w > 0.0 { area / w } else { 0.0 };
+                rects.push(Rect { x: free.x, y, w, h });
+                y += h;
+            });
+            free.x += w;
+            free.w -= w;
+        } else {
+            // lay the row out along the top of the free space
+            let h = if free.w > 0.0 { sum / free.w } else { 0.0 };
+            let mut x = free.x;
+            row.iter().for_each(|area| {
+                let w = if h > 0.0 { area / h } else { 0.0 };
+                rects.push(Rect { x, y: free.y, w, h });
+                x += w;
+            });
+            free.y += h;
+            free.h -= h;
+        }
+        start = end;
+    }
+    rects
+}
+
+/// The rectangles, labels, and tooltips of the treemap for @files
+fn cells(files: &[FileCounts], column: usize, title: &str, width: f64, height: f64) -> String {
+    let mut svg = String::new();
+    layout(files, column, width, height)
+        .iter()
+        .for_each(|cell| {
+            let Rect { x, y, w, h } = cell.rect;
+            let tooltip = format!("{}\n{}: {}", cell.path, title, cell.value);
+            match cell.file {
+                Some(file) => {
+                    let _ = writeln!(
+                        svg,
+                        r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="{}" stroke="#fff" stroke-width="0.5"><title>{} ({})</title></rect>"##,
+                        x,
+                        y,
+                        w,
+                        h,
+                        file.lang.color(),
+                        escape_xml(&tooltip),
+                        escape_xml(&file.lang.to_string()),
+                    );
+                }
+                None => {
+                    let _ = writeln!(
+                        svg,
+                        r##"<rect x="{:.1}" y="{:.1}" width="{:.1}" height="{:.1}" fill="#f4f4f4" stroke="#999" stroke-width="0.5"><title>{}</title></rect>"##,
+                        x,
+                        y,
+                        w,
+                        h,
+                        escape_xml(&tooltip),
+                    );
+                    if h > HEADER && w > 30.0 {
+                        // roughly the number of characters which fit in the header
+                        let max_chars = ((w - 2.0 * PADDING) / 6.5) as usize;
+                        let name: String = cell
+                            .path
+                            .rsplit('/')
+                            .next()
+                            .unwrap_or(&cell.path)
+                            .chars()
+                            .take(max_chars)
+                            .collect();
+                        let _ = writeln!(
+                            svg,
+                            r#"<text x="{:.1}" y="{:.1}">{}</text>"#,
+                            x + PADDING + 1.0,
+                            y + HEADER - 3.0,
+                            escape_xml(&name),
+                        );
+                    }
+                }
+            }
+        });
+    svg
+}
+
+/// Render @files as an SVG treemap where each file is coloured by its language and has a tooltip
+/// with its path and value. @title is the title of the column at index @column.
+pub fn svg(files: &[FileCounts], column: usize, title: &str, width: f64, height: f64) -> String {
+    format!(
+        r#"<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">
+{}</svg>
+"#,
+        cells(files, column, title, width, height),
+        w = width,
+        h = height,
+    )
+}
+
+/// Render @files as a standalone SVG image of a treemap with a legend of the languages below it.
+/// With @transpose the treemap is taller than it's wide. See `svg`.
+pub fn document(files: &[FileCounts], column: usize, title: &str, transpose: bool) -> String {
+    let (width, height) = if transpose {
+        (800.0, 1280.0)
+    } else {
+        (1280.0, 800.0)
+    };
+    let mut langs: Vec<&Language> = files
+        .iter()
+        .filter(|file| file.counts.values().get(column).copied().unwrap_or(0) > 0)
+        .map(|file| &file.lang)
+        .collect();
+    langs.sort();
+    langs.dedup();
+
+    let mut legend = String::new();
+    let mut x = 0.0;
+    langs.iter().for_each(|lang| {
+        let name = lang.to_string();
+        let _ = writeln!(
+            legend,
+            r#"<rect x="{:.1}" y="{:.1}" width="10" height="10" fill="{}"/><text x="{:.1}" y="{:.1}">{}</text>"#,
+            x,
+            height + LEGEND / 2.0 - 5.0,
+            lang.color(),
+            x + 14.0,
+            height + LEGEND / 2.0 + 4.0,
+            escape_xml(&name),
+        );
+        // roughly the width of the name followed by some space before the next language
+        x += 14.0 + name.chars().count() as f64 * 6.5 + 16.0;
+    });
+
+    format!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="{w}" height="{h}" viewBox="0 0 {w} {h}" font-family="sans-serif" font-size="11">
+<title>{}</title>
+<rect width="100%" height="100%" fill="white"/>
+{}{}</svg>
+"#,
+        escape_xml(&format!("{} by directory", title)),
+        cells(files, column, title, width, height),
+        legend,
+        w = width,
+        h = height + LEGEND,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn area(rect: &Rect) -> f64 {
+        rect.w * rect.h
+    }
+
+    #[test]
+    fn squarify_fills_rect_proportionally() {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 6.0,
+            h: 4.0,
+        };
+        let rects = squarify(&[6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0], rect);
+        assert_eq!(7, rects.len());
+        let expected = [6.0, 6.0, 4.0, 3.0, 2.0, 2.0, 1.0];
+        rects
+            .iter()
+            .zip(expected.iter())
+            .for_each(|(rect, expected)| assert!((area(rect) - expected).abs() < 1e-9));
+        // the first row from the paper is two rectangles stacked on the left side
+        assert_eq!(
+            Rect {
+                x: 0.0,
+                y: 0.0,
+                w: 3.0,
+                h: 2.0
+            },
+            rects[0]
+        );
+        assert_eq!(
+            Rect {
+                x: 0.0,
+                y: 2.0,
+                w: 3.0,
+                h: 2.0
+            },
+            rects[1]
+        );
+    }
+
+    #[test]
+    fn squarify_nothing() {
+        let rect = Rect {
+            x: 0.0,
+            y: 0.0,
+            w: 6.0,
+            h: 4.0,
+        };
+        assert!(squarify(&[], rect).is_empty());
+        assert!(squarify(&[0.0], rect).is_empty());
+    }
+}
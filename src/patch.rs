@@ -0,0 +1,338 @@
+use std::path::PathBuf;
+
+/// A hunk of a unified diff. Each line keeps its newline, except for the last line of a file
+/// without one.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct Hunk {
+    /// Line the hunk starts at in the pre-image, starting from 1
+    pub old_start: usize,
+    /// Lines in the pre-image, which are the context and removed lines
+    pub old: Vec<String>,
+    /// Lines in the post-image, which are the context and added lines
+    pub new: Vec<String>,
+}
+
+/// The changes to a single file in a unified diff. A file which is added has no @old path and a
+/// file which is deleted has no @new path.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub struct FilePatch {
+    pub old: Option<PathBuf>,
+    pub new: Option<PathBuf>,
+    pub hunks: Vec<Hunk>,
+    /// Binary files can't be rebuilt from the diff
+    pub binary: bool,
+}
+
+impl Hunk {
+    /// Drop the newline from the last line of the side, or sides, which a line of @kind is on for
+    /// "\\ No newline at end of file"
+    fn end_without_newline(&mut self, kind: char) {
+        let sides = match kind {
+            '-' => [Some(&mut self.old), None],
+            '+' => [None, Some(&mut self.new)],
+            _ => [Some(&mut self.old), Some(&mut self.new)],
+        };
+        for lines in sides.into_iter().flatten() {
+            if let Some(last) = lines.last_mut() {
+                if last.ends_with('\n') {
+                    last.pop();
+                }
+            }
+        }
+    }
+}
+
+impl FilePatch {
+    /// The path the file has after the patch, or before it if it's deleted
+    pub fn path(&self) -> Option<&PathBuf> {
+        self.new.as_ref().or(self.old.as_ref())
+    }
+}
+
+/// The path in a ---/+++ line of a diff, without a timestamp. /dev/null means the file doesn't
+/// exist.
+fn header_path(line: &str) -> Option<String> {
+    let path = line[4..].split('\t').next().unwrap_or_default().trim_end();
+    // git quotes paths with unusual characters
+    let path = path
+        .strip_prefix('"')
+        .and_then(|path| path.strip_suffix('"'))
+        .unwrap_or(path);
+    if path == "/dev/null" {
+        None
+    } else {
+        Some(path.to_string())
+    }
+}
+
+/// Parse the start and length of the pre-image in a hunk header, e.g. "@@ -12,7 +12,8 @@"
+fn hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    let mut ranges = line.strip_prefix("@@ ")?.split(' ');
+    let range = |s: Option<&str>, sign: char| -> Option<(usize, usize)> {
+        let s = s?.strip_prefix(sign)?;
+        match s.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((s.parse().ok()?, 1)),
+        }
+    };
+    let (old_start, old_len) = range(ranges.next(), '-')?;
+    let (_, new_len) = range(ranges.next(), '+')?;
+    Some((old_start, old_len, new_len))
+}
+
+/// Parse every file in the unified diff @text. The a/ and b/ prefixes git adds to paths are
+/// removed. Anything which isn't part of a file's changes, like commit messages, is skipped.
+pub fn parse(text: &str) -> Result<Vec<FilePatch>, String> {
+    let mut files: Vec<FilePatch> = Vec::new();
+    let mut lines = text.split_inclusive('\n').enumerate().peekable();
+    while let Some((n, line)) = lines.next() {
+        let trimmed = line.trim_end_matches(['\n', '\r']);
+        if trimmed.starts_with("diff --git ") {
+            files.push(FilePatch::default());
+        } else if trimmed.starts_with("Binary files ") || trimmed == "GIT binary patch" {
+            if let Some(file) = files.last_mut() {
+                file.binary = true;
+            }
+        } else if let Some(path) = trimmed.strip_prefix("rename from ") {
+            if let Some(file) = files.last_mut() {
+                file.old = Some(PathBuf::from(path));
+            }
+        } else if let Some(path) = trimmed.strip_prefix("rename to ") {
+            if let Some(file) = files.last_mut() {
+                file.new = Some(PathBuf::from(path));
+            }
+        } else if trimmed.starts_with("--- ")
+            && matches!(lines.peek(), Some((_, next)) if next.starts_with("+++ "))
+        {
+            let old = header_path(trimmed);
+            let new = lines
+                .next()
+                .and_then(|(_, next)| header_path(next.trim_end_matches(['\n', '\r'])));
+            // git's a/ and b/ prefixes, the same as `patch -p1`
+            let (old, new) = match (&old, &new) {
+                (Some(o), Some(n)) if o.starts_with("a/") && n.starts_with("b/") => {
+                    (Some(o[2..].to_string()), Some(n[2..].to_string()))
+                }
+                (Some(o), None) if o.starts_with("a/") => (Some(o[2..].to_string()), None),
+                (None, Some(n)) if n.starts_with("b/") => (None, Some(n[2..].to_string())),
+                _ => (old, new),
+            };
+            // git diffs already started the file with "diff --git"
+            match files.last_mut() {
+                Some(file) if file.hunks.is_empty() && file.old.is_none() && file.new.is_none() => {
+                }
+                _ => files.push(FilePatch::default()),
+            }
+            let file = files.last_mut().unwrap();
+            file.old = old.map(PathBuf::from);
+            file.new = new.map(PathBuf::from);
+        } else if trimmed.starts_with("@@ ") {
+            let file = files
+                .last_mut()
+                .ok_or_else(|| format!("line {}: hunk without a file", n + 1))?;
+            let (old_start, mut old_len, mut new_len) = hunk_header(trimmed)
+                .ok_or_else(|| format!("line {}: invalid hunk header \"{}\"", n + 1, trimmed))?;
+            let mut hunk = Hunk {
+                old_start,
+                ..Hunk::default()
+            };
+            // "\ No newline at end of file" applies to the line before it, which is on one side
+            // or both
+            let mut last = ' ';
+            while old_len > 0 || new_len > 0 {
+                let (n, line) = lines
+                    .next()
+                    .ok_or_else(|| format!("line {}: hunk ends early", n + 1))?;
+                // some tools drop the space from empty context lines
+                let (kind, content) = match line.chars().next() {
+                    Some('\n') | Some('\r') => (' ', line),
+                    Some(c) => (c, &line[c.len_utf8()..]),
+                    None => (' ', line),
+                };
+                match kind {
+                    ' ' if old_len > 0 && new_len > 0 => {
+                        hunk.old.push(content.to_string());
+                        hunk.new.push(content.to_string());
+                        old_len -= 1;
+                        new_len -= 1;
+                    }
+                    '-' if old_len > 0 => {
+                        hunk.old.push(content.to_string());
+                        old_len -= 1;
+                    }
+                    '+' if new_len > 0 => {
+                        hunk.new.push(content.to_string());
+                        new_len -= 1;
+                    }
+                    '\\' => {
+                        hunk.end_without_newline(last);
+                        continue;
+                    }
+                    _ => return Err(format!("line {}: unexpected line in hunk", n + 1)),
+                }
+                last = kind;
+            }
+            while let Some((_, line)) = lines.peek() {
+                if !line.starts_with('\\') {
+                    break;
+                }
+                lines.next();
+                hunk.end_without_newline(last);
+            }
+            file.hunks.push(hunk);
+        }
+    }
+    files.retain(|file| file.old.is_some() || file.new.is_some());
+    Ok(files)
+}
+
+/// Apply @hunks to @pre, the pre-image of a file, to build its post-image. Hunks are found near
+/// the line they say they start at if lines were added or removed above them, the same as `patch`.
+pub fn apply(pre: &str, hunks: &[Hunk]) -> Result<String, String> {
+    let lines: Vec<&str> = pre.split_inclusive('\n').collect();
+    // lines are compared without their line endings since a hunk for the last line of a file
+    // which is missing a newline might not say so
+    let same =
+        |a: &str, b: &str| a.trim_end_matches(['\n', '\r']) == b.trim_end_matches(['\n', '\r']);
+    let matches_at = |start: usize, hunk: &Hunk| {
+        start + hunk.old.len() <= lines.len()
+            && hunk
+                .old
+                .iter()
+                .zip(&lines[start..])
+                .all(|(a, b)| same(a, b))
+    };
+
+    let mut post = String::with_capacity(pre.len());
+    let mut next = 0;
+    let mut offset: isize = 0;
+    for hunk in hunks {
+        // an empty pre-image starts at line 0
+        let expected =
+            (hunk.old_start.saturating_sub(1) as isize + offset).max(next as isize) as usize;
+        let start = (0..lines.len() + 1)
+            .flat_map(|distance| [expected + distance, expected.wrapping_sub(distance)])
+            .filter(|start| *start >= next && *start <= lines.len())
+            .find(|start| matches_at(*start, hunk))
+            .ok_or_else(|| format!("hunk at line {} doesn't apply", hunk.old_start))?;
+        offset = start as isize - hunk.old_start.saturating_sub(1) as isize;
+        lines[next..start]
+            .iter()
+            .for_each(|line| post.push_str(line));
+        hunk.new.iter().for_each(|line| post.push_str(line));
+        next = start + hunk.old.len();
+    }
+    lines[next..].iter().for_each(|line| post.push_str(line));
+    Ok(post)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DIFF: &str = "\
+commit 1234
+Author: someone
+
+diff --git a/src/lib.rs b/src/lib.rs
+index 1111111..2222222 100644
+--- a/src/lib.rs
++++ b/src/lib.rs
+@@ -1,3 +1,3 @@
+ fn a() {}
+-fn b() {}
++fn c() {}
+ fn d() {}
+diff --git a/new.rs b/new.rs
+new file mode 100644
+--- /dev/null
++++ b/new.rs
+@@ -0,0 +1 @@
++fn e() {}
+\\ No newline at end of file
+diff --git a/old.go b/new.go
+similarity index 100%
+rename from old.go
+rename to new.go
+";
+
+    #[test]
+    fn parse_unified_diff() {
+        let files = parse(DIFF).unwrap();
+        assert_eq!(3, files.len());
+        assert_eq!(
+            FilePatch {
+                old: Some(PathBuf::from("src/lib.rs")),
+                new: Some(PathBuf::from("src/lib.rs")),
+                hunks: vec![Hunk {
+                    old_start: 1,
+                    old: vec!["fn a() {}\n", "fn b() {}\n", "fn d() {}\n"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                    new: vec!["fn a() {}\n", "fn c() {}\n", "fn d() {}\n"]
+                        .into_iter()
+                        .map(String::from)
+                        .collect(),
+                }],
+                binary: false,
+            },
+            files[0]
+        );
+        assert_eq!(None, files[1].old);
+        assert_eq!(vec![String::from("fn e() {}")], files[1].hunks[0].new);
+        assert_eq!(Some(&PathBuf::from("new.go")), files[2].path());
+        assert!(files[2].hunks.is_empty());
+
+        assert!(parse("--- a\n+++ b\n@@ -1 +1 @@\n").is_err());
+    }
+
+    #[test]
+    fn parse_missing_newlines() {
+        // only the old file is missing its newline
+        let files =
+            parse("--- a/a.rs\n+++ b/a.rs\n@@ -1 +1 @@\n-a\n\\ No newline at end of file\n+a\n")
+                .unwrap();
+        assert_eq!(vec!["a"], files[0].hunks[0].old);
+        assert_eq!(vec!["a\n"], files[0].hunks[0].new);
+        // only the new file is missing its newline
+        let files =
+            parse("--- a/a.rs\n+++ b/a.rs\n@@ -1 +1 @@\n-a\n+b\n\\ No newline at end of file\n")
+                .unwrap();
+        assert_eq!(vec!["a\n"], files[0].hunks[0].old);
+        assert_eq!(vec!["b"], files[0].hunks[0].new);
+        assert_eq!(Ok(String::from("b")), apply("a\n", &files[0].hunks));
+        // context is the same in both
+        let files = parse(
+            "--- a/a.rs\n+++ b/a.rs\n@@ -1,2 +1,2 @@\n-a\n+b\n c\n\\ No newline at end of file\n",
+        )
+        .unwrap();
+        assert_eq!(vec!["a\n", "c"], files[0].hunks[0].old);
+        assert_eq!(vec!["b\n", "c"], files[0].hunks[0].new);
+    }
+
+    #[test]
+    fn parse_non_ascii_lines() {
+        let files = parse("--- a/a.md\n+++ b/a.md\n@@ -1,2 +1,2 @@\n é\n-…\n+ü\n").unwrap();
+        assert_eq!(vec!["é\n", "…\n"], files[0].hunks[0].old);
+        assert_eq!(vec!["é\n", "ü\n"], files[0].hunks[0].new);
+        // a context line which lost its space can't be told apart from an unexpected line
+        assert_eq!(
+            Err(String::from("line 4: unexpected line in hunk")),
+            parse("--- a/a.md\n+++ b/a.md\n@@ -1 +1 @@\né\n")
+        );
+    }
+
+    #[test]
+    fn apply_hunks() {
+        let files = parse(DIFF).unwrap();
+        // lines added above the hunk move it down
+        let pre = "fn z() {}\nfn a() {}\nfn b() {}\nfn d() {}\n";
+        assert_eq!(
+            Ok(String::from("fn z() {}\nfn a() {}\nfn c() {}\nfn d() {}\n")),
+            apply(pre, &files[0].hunks)
+        );
+        assert_eq!(Ok(String::from("fn e() {}")), apply("", &files[1].hunks));
+        assert!(apply("fn x() {}\n", &files[0].hunks).is_err());
+    }
+}
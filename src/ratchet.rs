@@ -0,0 +1,157 @@
+use crate::baseline::Baseline;
+use std::collections::{BTreeSet, HashMap};
+
+/// A value which is more than its budget
+#[derive(Debug, PartialEq, Eq)]
+pub struct Exceeded {
+    pub group: String,
+    /// Key of the column, see `Column::key`
+    pub key: String,
+    pub value: u64,
+    pub budget: u64,
+}
+
+/// Every column which has a budget in @budget, in order
+pub fn keys(budget: &Baseline) -> Vec<String> {
+    budget
+        .groups
+        .iter()
+        .flat_map(|(_, counts)| counts.keys())
+        .collect::<BTreeSet<_>>()
+        .into_iter()
+        .cloned()
+        .collect()
+}
+
+/// The values in @current for each of @keys which are more than their budget in @budget. Values
+/// without a budget, like the ones of a group which is new, have a budget of 0.
+pub fn exceeded(budget: &Baseline, current: &Baseline, keys: &[String]) -> Vec<Exceeded> {
+    current
+        .groups
+        .iter()
+        .flat_map(|(group, counts)| {
+            keys.iter().filter_map(move |key| {
+                let value = counts.get(key).copied().unwrap_or(0);
+                let limit = budget.get(group, key).unwrap_or(0);
+                (value > limit).then(|| Exceeded {
+                    group: group.clone(),
+                    key: key.clone(),
+                    value,
+                    budget: limit,
+                })
+            })
+        })
+        .collect()
+}
+
+/// @budget with the budget of each of @keys lowered to its value in @current when it dropped.
+/// Values without a budget are added at their current value and groups which aren't in @current
+/// anymore are removed, but a budget is never raised. Also returns how many budgets were lowered.
+pub fn update(budget: &Baseline, current: &Baseline, keys: &[String]) -> (Baseline, usize) {
+    let mut lowered = 0;
+    let mut groups: Vec<(String, HashMap<String, u64>)> = current
+        .groups
+        .iter()
+        .map(|(group, counts)| {
+            let mut budgets: HashMap<String, u64> = budget
+                .groups
+                .iter()
+                .find(|(name, _)| name == group)
+                .map(|(_, budgets)| budgets.clone())
+                .unwrap_or_default();
+            keys.iter().for_each(|key| {
+                let value = counts.get(key).copied().unwrap_or(0);
+                match budgets.get_mut(key) {
+                    Some(limit) if value < *limit => {
+                        *limit = value;
+                        lowered += 1;
+                    }
+                    Some(_) => {}
+                    None => {
+                        budgets.insert(key.clone(), value);
+                    }
+                }
+            });
+            (group.clone(), budgets)
+        })
+        .collect();
+    // sorted so the budget file has small diffs when it's committed
+    groups.sort_by(|(g1, _), (g2, _)| g1.cmp(g2));
+    (
+        Baseline {
+            groupby: budget.groupby.clone(),
+            groups,
+        },
+        lowered,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn baseline(groups: &[(&str, &[(&str, u64)])]) -> Baseline {
+        Baseline {
+            groupby: String::from("file"),
+            groups: groups
+                .iter()
+                .map(|(group, counts)| {
+                    (
+                        group.to_string(),
+                        counts
+                            .iter()
+                            .map(|(key, value)| (key.to_string(), *value))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn ratcheting_budgets() {
+        let budget = baseline(&[
+            ("a.rs", &[("tokens", 100), ("query:unsafe", 2)]),
+            ("b.rs", &[("tokens", 50), ("query:unsafe", 0)]),
+            ("gone.rs", &[("tokens", 10), ("query:unsafe", 0)]),
+        ]);
+        let current = baseline(&[
+            ("a.rs", &[("tokens", 90), ("query:unsafe", 3)]),
+            ("b.rs", &[("tokens", 50), ("query:unsafe", 0)]),
+            ("new.rs", &[("tokens", 20), ("query:unsafe", 0)]),
+        ]);
+        let keys = keys(&budget);
+        assert_eq!(
+            vec![String::from("query:unsafe"), String::from("tokens")],
+            keys
+        );
+        assert_eq!(
+            vec![
+                Exceeded {
+                    group: String::from("a.rs"),
+                    key: String::from("query:unsafe"),
+                    value: 3,
+                    budget: 2,
+                },
+                Exceeded {
+                    group: String::from("new.rs"),
+                    key: String::from("tokens"),
+                    value: 20,
+                    budget: 0,
+                },
+            ],
+            exceeded(&budget, &current, &keys)
+        );
+        assert_eq!(
+            (
+                baseline(&[
+                    ("a.rs", &[("tokens", 90), ("query:unsafe", 2)]),
+                    ("b.rs", &[("tokens", 50), ("query:unsafe", 0)]),
+                    ("new.rs", &[("tokens", 20), ("query:unsafe", 0)]),
+                ]),
+                1
+            ),
+            update(&budget, &current, &keys)
+        );
+    }
+}
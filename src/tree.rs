@@ -7,7 +7,12 @@ pub struct TreeIterator<'a> {
 
 impl<'a> TreeIterator<'a> {
     pub fn new(tree: &'a Tree) -> Self {
-        let cursor = tree.walk();
+        Self::from_node(tree.root_node())
+    }
+
+    /// Iterate over @node and its descendants only
+    pub fn from_node(node: Node<'a>) -> Self {
+        let cursor = node.walk();
         Self {
             next: Some(cursor.node()),
             cursor,
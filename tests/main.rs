@@ -219,3 +219,1171 @@ Go,1,52
         )
         .success();
 }
+
+#[test]
+fn test_sqlite() {
+    let db = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_sqlite.db");
+    let _ = std::fs::remove_file(&db);
+    for _ in 0..2 {
+        tcount()
+            .current_dir("tests/fixtures/")
+            .args(
+                [
+                    "--kind",
+                    "line_comment",
+                    "--whitelist",
+                    "Go",
+                    "Ruby",
+                    "--sqlite",
+                ]
+                .iter(),
+            )
+            .arg(&db)
+            .assert()
+            .success();
+    }
+
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let nruns: i64 = conn
+        .query_row("SELECT COUNT(*) FROM runs", rusqlite::NO_PARAMS, |row| {
+            row.get(0)
+        })
+        .unwrap();
+    assert_eq!(2, nruns);
+    let mut stmt = conn
+        .prepare(
+            "SELECT files.path, files.language, counts.name, counts.value
+            FROM files JOIN counts ON counts.file_id = files.id
+            WHERE files.run_id = 2
+            ORDER BY files.path, counts.name",
+        )
+        .unwrap();
+    let rows: Vec<(String, String, String, i64)> = stmt
+        .query_map(rusqlite::NO_PARAMS, |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?))
+        })
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    assert_eq!(
+        vec![
+            (
+                "./go1.go".into(),
+                "Go".into(),
+                "kind:line_comment".into(),
+                0
+            ),
+            ("./go1.go".into(), "Go".into(), "tokens".into(), 52),
+            (
+                "./ruby.rb".into(),
+                "Ruby".into(),
+                "kind:line_comment".into(),
+                0
+            ),
+            ("./ruby.rb".into(), "Ruby".into(), "tokens".into(), 10),
+            (
+                "./ruby1.rb".into(),
+                "Ruby".into(),
+                "kind:line_comment".into(),
+                0
+            ),
+            ("./ruby1.rb".into(), "Ruby".into(), "tokens".into(), 33),
+        ],
+        rows
+    );
+}
+
+#[test]
+fn test_sqlite_revision() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_sqlite_revision");
+    let repo = git_repo(&dir);
+    let first = git_commit(&repo, &[("a.rs", "fn a() {}\n")], 0);
+    let second = git_commit(&repo, &[("a.rs", "fn b() {}\n")], 86400);
+    std::fs::write(dir.join(".tcount.toml"), "kind = [\"line_comment\"]\n").unwrap();
+    let db = dir.join("tcount.db");
+
+    for args in [vec!["--rev", "HEAD~"], vec!["--staged"], vec![]] {
+        tcount()
+            .current_dir(&dir)
+            .env("XDG_CONFIG_HOME", &dir)
+            .args(args)
+            .arg("--sqlite")
+            .arg(&db)
+            .assert()
+            .success();
+    }
+
+    let conn = rusqlite::Connection::open(&db).unwrap();
+    let mut stmt = conn
+        .prepare("SELECT revision, args FROM runs ORDER BY id")
+        .unwrap();
+    let runs: Vec<(String, String)> = stmt
+        .query_map(rusqlite::NO_PARAMS, |row| Ok((row.get(0)?, row.get(1)?)))
+        .unwrap()
+        .map(Result::unwrap)
+        .collect();
+    let db = db.display();
+    assert_eq!(
+        vec![
+            (
+                first.to_string(),
+                format!("--kind=line_comment --rev HEAD~ --sqlite {}", db)
+            ),
+            (
+                second.to_string(),
+                format!("--kind=line_comment --staged --sqlite {}", db)
+            ),
+            (
+                second.to_string(),
+                format!("--kind=line_comment --sqlite {}", db)
+            ),
+        ],
+        runs
+    );
+}
+
+#[test]
+fn test_baseline() {
+    let baseline = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_baseline.json");
+    tcount()
+        .current_dir("tests/fixtures/")
+        .args(["--whitelist", "Rust", "Ruby", "--save-baseline"].iter())
+        .arg(&baseline)
+        .assert()
+        .success();
+
+    let expected = r"Group,Tokens old,Tokens new,Tokens Δ,Tokens %
+Rust,156,156,0,+0.0%
+Go (new),,52,+52,
+Ruby (removed),43,,-43,-100.0%
+TOTALS,199,208,+9,+4.5%
+";
+    tcount()
+        .current_dir("tests/fixtures/")
+        .args(
+            [
+                "--format=csv",
+                "--show-totals",
+                "--hide=files",
+                "--whitelist",
+                "Rust",
+                "Go",
+                "--compare",
+            ]
+            .iter(),
+        )
+        .arg(&baseline)
+        .assert()
+        .stdout(expected)
+        .success();
+
+    // baselines can only be compared with the same --groupby
+    tcount()
+        .current_dir("tests/fixtures/")
+        .args(["--groupby=file", "--compare"].iter())
+        .arg(&baseline)
+        .assert()
+        .failure();
+}
+
+/// Create an empty git repository at @dir
+fn git_repo(dir: &std::path::Path) -> git2::Repository {
+    let _ = std::fs::remove_dir_all(dir);
+    git2::Repository::init(dir).unwrap()
+}
+
+/// Write @files to the working directory of @repo and commit them on top of HEAD at @time, in
+/// seconds since the Unix epoch. Files are committed even if they're ignored.
+fn git_commit(repo: &git2::Repository, files: &[(&str, &str)], time: i64) -> git2::Oid {
+    git_commit_as(repo, "tcount", files, time)
+}
+
+/// Commit @files like `git_commit` with @author as the author and committer
+fn git_commit_as(
+    repo: &git2::Repository,
+    author: &str,
+    files: &[(&str, &str)],
+    time: i64,
+) -> git2::Oid {
+    let dir = repo.workdir().unwrap();
+    let mut index = repo.index().unwrap();
+    for (path, text) in files {
+        let full = dir.join(path);
+        std::fs::create_dir_all(full.parent().unwrap()).unwrap();
+        std::fs::write(&full, text).unwrap();
+        index.add_path(std::path::Path::new(path)).unwrap();
+    }
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig =
+        git2::Signature::new(author, "tcount@example.com", &git2::Time::new(time, 0)).unwrap();
+    let parent = repo.head().ok().map(|head| head.peel_to_commit().unwrap());
+    repo.commit(
+        Some("HEAD"),
+        &sig,
+        &sig,
+        "commit",
+        &tree,
+        parent.iter().collect::<Vec<_>>().as_slice(),
+    )
+    .unwrap()
+}
+
+#[test]
+fn test_rev() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_rev");
+    let rust = std::fs::read_to_string("tests/fixtures/rust1.rs").unwrap();
+    let repo = git_repo(&dir);
+    let commit = git_commit(
+        &repo,
+        &[
+            ("main.go", "package main\n"),
+            ("src/.gitignore", "gen/\n"),
+            ("src/lib.rs", &rust),
+            ("src/gen/generated.rs", &rust),
+        ],
+        0,
+    );
+    repo.tag_lightweight("v1", &repo.find_object(commit, None).unwrap(), false)
+        .unwrap();
+
+    // changes to the working directory aren't counted
+    std::fs::write(dir.join("src/lib.rs"), rust.clone() + "fn f() {}\n").unwrap();
+    std::fs::write(dir.join("lib.rb"), "puts 1\n").unwrap();
+    std::fs::remove_file(dir.join("main.go")).unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "--rev=v1"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+Rust,1,33
+Go,1,3
+",
+        )
+        .success();
+
+    // paths are relative to the current directory and ignore files from parents are used
+    tcount()
+        .current_dir(dir.join("src"))
+        .args(["--format=csv", "--rev=HEAD", "--groupby=file", "."].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./lib.rs,1,33
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--rev=HEAD",
+                "--no-git",
+                "--sort-by=group",
+                "--groupby=file",
+                "src",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+src/gen/generated.rs,1,33
+src/lib.rs,1,33
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--rev=missing"].iter())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_history() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_history");
+    let rust = std::fs::read_to_string("tests/fixtures/rust1.rs").unwrap();
+    let day = 24 * 60 * 60;
+    let repo = git_repo(&dir);
+    let first = git_commit(
+        &repo,
+        &[("lib.rs", &rust), ("main.go", "package main\n")],
+        0,
+    );
+    let second = git_commit(
+        &repo,
+        &[("main.go", "package main\n\nfunc main() {}\n")],
+        day,
+    );
+    let third = git_commit(&repo, &[("src/lib.rs", &rust)], 8 * day);
+
+    tcount()
+        .current_dir(&dir)
+        .args(["history"].iter())
+        .assert()
+        .stdout(format!(
+            r"Commit,Date,Group,Files,Tokens
+{first},1970-01-01T00:00:00Z,Rust,1,33
+{first},1970-01-01T00:00:00Z,Go,1,3
+{second},1970-01-02T00:00:00Z,Rust,1,33
+{second},1970-01-02T00:00:00Z,Go,1,10
+{third},1970-01-09T00:00:00Z,Rust,2,66
+{third},1970-01-09T00:00:00Z,Go,1,10
+",
+            first = first,
+            second = second,
+            third = third,
+        ))
+        .success();
+
+    // samples are at least a week apart starting from the newest commit
+    tcount()
+        .current_dir(&dir)
+        .args(["--whitelist=Rust", "history", "--every=1w", "--format=json"].iter())
+        .assert()
+        .stdout(format!(
+            r#"[{{"commit":"{second}","date":"1970-01-02T00:00:00Z","groups":[{{"counts":{{"files":1,"tokens":33}},"group":"Rust"}}]}},{{"commit":"{third}","date":"1970-01-09T00:00:00Z","groups":[{{"counts":{{"files":2,"tokens":66}},"group":"Rust"}}]}}]
+"#,
+            second = second,
+            third = third,
+        ))
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--groupby=arg",
+                "history",
+                "--since=1970-01-02",
+                "--commits=1",
+                "src",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(format!(
+            r"Commit,Date,Group,Files,Tokens
+{third},1970-01-09T00:00:00Z,src,1,33
+",
+            third = third,
+        ))
+        .success();
+}
+
+#[test]
+fn test_diff() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_diff");
+    let repo = git_repo(&dir);
+    git_commit(&repo, &[("src/main.rs", "fn main() { let x = 1; }\n")], 0);
+    // reformatting doesn't change any tokens
+    git_commit(
+        &repo,
+        &[
+            ("src/main.rs", "fn main() {\n    let x = 1;\n}\n"),
+            ("main.go", "package main\n"),
+        ],
+        1,
+    );
+    git_commit(
+        &repo,
+        &[("src/main.rs", "fn main() {\n    let x = 2;\n}\n")],
+        2,
+    );
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "diff", "HEAD~2..HEAD~1", "--format=csv"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Added,Removed,Net
+Go,1,3,0,+3
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--groupby=file",
+                "--show-totals",
+                "diff",
+                "HEAD~2",
+                "--format=csv",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Added,Removed,Net
+./main.go,1,3,0,+3
+./src/main.rs,1,1,1,0
+TOTALS,2,4,1,+3
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=arg", "diff", "HEAD~1..", "--format=json", "src"].iter())
+        .assert()
+        .stdout(
+            r#"{"files":[{"added":1,"group":"src","language":"Rust","path":"src/main.rs","removed":1}],"from":"HEAD~1","groups":[{"added":1,"files":1,"group":"src","removed":1}],"to":"HEAD"}
+"#,
+        )
+        .success();
+}
+
+#[test]
+fn test_changed_since() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_changed_since");
+    let repo = git_repo(&dir);
+    let base = git_commit(
+        &repo,
+        &[
+            ("a.rs", "fn a() {}\n"),
+            ("b.go", "package b\n"),
+            ("c.rs", "fn c() {}\n"),
+        ],
+        0,
+    );
+    repo.branch("main", &repo.find_commit(base).unwrap(), false)
+        .unwrap();
+    // committed, unstaged, and untracked changes are all counted
+    git_commit(&repo, &[("a.rs", "fn a() { b(); }\n")], 1);
+    std::fs::write(dir.join("b.go"), "package b\n\nfunc b() {}\n").unwrap();
+    std::fs::write(dir.join("d.rs"), "fn d() {}\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--sort-by=group",
+                "--changed-since=main",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./a.rs,1,10
+./b.go,1,10
+./d.rs,1,6
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--sort-by=group",
+                "--hide=files",
+                "--changed-since=main",
+                "--compare-base",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Tokens old,Tokens new,Tokens Δ,Tokens %
+./a.rs,6,10,+4,+66.7%
+./b.go,3,10,+7,+233.3%
+./d.rs (new),,6,+6,
+",
+        )
+        .success();
+
+    // with --rev only the commits are compared
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--changed-since=main",
+                "--rev=HEAD",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./a.rs,1,10
+",
+        )
+        .success();
+}
+
+#[test]
+fn test_staged() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_staged");
+    let repo = git_repo(&dir);
+    git_commit(
+        &repo,
+        &[("a.rs", "fn a() {}\n"), ("b.rs", "fn b() {}\n")],
+        0,
+    );
+    // stage a change to a.rs and a new file then edit a.rs again without staging it
+    let mut index = repo.index().unwrap();
+    std::fs::write(dir.join("a.rs"), "fn a() { b(); }\n").unwrap();
+    std::fs::write(dir.join("c.rs"), "fn c() {}\n").unwrap();
+    index.add_path(std::path::Path::new("a.rs")).unwrap();
+    index.add_path(std::path::Path::new("c.rs")).unwrap();
+    std::fs::create_dir_all(dir.join("src/e")).unwrap();
+    std::fs::write(dir.join("src/e/f.rs"), "fn f() {}\n").unwrap();
+    index.add_path(std::path::Path::new("src/e/f.rs")).unwrap();
+    index.write().unwrap();
+    std::fs::write(dir.join("a.rs"), "fn a() { b(); c(); }\n").unwrap();
+    std::fs::write(dir.join("d.rs"), "fn d() {}\n").unwrap();
+    let objects = || {
+        let mut n = 0;
+        repo.odb()
+            .unwrap()
+            .foreach(|_| {
+                n += 1;
+                true
+            })
+            .unwrap();
+        n
+    };
+    let nobjects = objects();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--sort-by=group",
+                "--staged",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./a.rs,1,10
+./b.rs,1,6
+./c.rs,1,6
+./src/e/f.rs,1,6
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(dir.join("src"))
+        .args(["--format=csv", "--groupby=file", "--staged", "e"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+e/f.rs,1,6
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--sort-by=group",
+                "--staged",
+                "--changed-since=HEAD",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./a.rs,1,10
+./c.rs,1,6
+./src/e/f.rs,1,6
+",
+        )
+        .success();
+    // counting the index doesn't write anything to the repository
+    assert_eq!(nobjects, objects());
+}
+
+#[test]
+fn test_patch() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_patch");
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(dir.join("src")).unwrap();
+    std::fs::write(dir.join("src/main.rs"), "fn main() {\n    let x = 1;\n}\n").unwrap();
+    std::fs::write(dir.join("old.go"), "package main\n").unwrap();
+    let patch = "\
+diff --git a/src/main.rs b/src/main.rs
+--- a/src/main.rs
++++ b/src/main.rs
+@@ -1,3 +1,4 @@
+ fn main() {
+-    let x = 1;
++    // two
++    let x = 2;
+ }
+diff --git a/old.go b/old.go
+deleted file mode 100644
+--- a/old.go
++++ /dev/null
+@@ -1 +0,0 @@
+-package main
+";
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--groupby=file",
+                "--kind=line_comment",
+                "--show-totals",
+                "--sort-by=group",
+                "patch",
+                "--format=csv",
+            ]
+            .iter(),
+        )
+        .write_stdin(patch)
+        .assert()
+        .stdout(
+            r"Group,Files,Added,Removed,Net,Kind(line_comment) Δ
+old.go,1,0,3,-3,0
+src/main.rs,1,1,1,0,+1
+TOTALS,2,1,4,-3,+1
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=arg", "patch", "--format=json", "src"].iter())
+        .write_stdin(patch)
+        .assert()
+        .stdout(
+            r#"{"files":[{"added":1,"group":"src","language":"Rust","path":"src/main.rs","removed":1}],"groups":[{"added":1,"files":1,"group":"src","removed":1}]}
+"#,
+        )
+        .success();
+
+    // the working directory is unchanged
+    assert_eq!(
+        "package main\n",
+        std::fs::read_to_string(dir.join("old.go")).unwrap()
+    );
+}
+
+#[test]
+fn test_groupby_author() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_groupby_author");
+    let repo = git_repo(&dir);
+    let now = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as i64;
+    git_commit_as(
+        &repo,
+        "Ann",
+        &[
+            ("src/lib.rs", "fn a() {}\n"),
+            // files in unsupported languages belong to whoever wrote most of their lines too
+            ("notes.abc", "a\nb\n"),
+        ],
+        0,
+    );
+    git_commit_as(
+        &repo,
+        "Bob",
+        &[
+            ("src/lib.rs", "fn a() {}\n// b\nfn b() {}\n"),
+            ("main.go", "package main\n"),
+        ],
+        now,
+    );
+    // changes which aren't committed belong to nobody yet
+    std::fs::write(
+        dir.join("src/lib.rs"),
+        "fn a() {}\n// b\nfn b() {}\nfn c() {}\n",
+    )
+    .unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--groupby=author",
+                "--kind=line_comment",
+                // Ann's median age depends on today's date
+                "--hide=age:median",
+                "--format=csv",
+                "--sort-by=group",
+                "--show-totals",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens,Kind(line_comment),Older Than 1y (%)
+Ann,1,6,0,100
+Bob,2,9,1,0
+Not Committed Yet,0,6,0,0
+TOTALS,3,21,1,28
+",
+        )
+        .success();
+
+    // each file is counted once, for the author of most of its lines, so the totals are the same
+    // as for any other grouping
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--kind=line_comment",
+                "--format=csv",
+                "--show-totals",
+                "--sort-by=group",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens,Kind(line_comment)
+Go,1,3,0
+Rust,1,18,1
+Unsupported,1,0,0
+TOTALS,3,21,1
+",
+        )
+        .success();
+}
+
+#[test]
+fn test_hotspots() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_hotspots");
+    let repo = git_repo(&dir);
+    git_commit(
+        &repo,
+        &[
+            ("big.rs", "fn a() {}\nfn b() {}\nfn c() {}\n"),
+            ("hot.go", "package main\n"),
+        ],
+        0,
+    );
+    git_commit(&repo, &[("hot.go", "package hot\n")], 100 * 86400);
+    git_commit(&repo, &[("hot.go", "package main\n")], 200 * 86400);
+
+    tcount()
+        .current_dir(&dir)
+        .args(["hotspots", "--format=csv"].iter())
+        .assert()
+        .stdout(
+            r"File,Language,Tokens,Commits,Score
+./big.rs,Rust,18,1,33.3
+./hot.go,Go,3,3,16.7
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--transpose", "hotspots", "--format=csv"].iter())
+        .assert()
+        .stdout(
+            r"File,./big.rs,./hot.go
+Language,Rust,Go
+Tokens,18,3
+Commits,1,3
+Score,33.3,16.7
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["hotspots", "--since=1970-02-01", "--format=json"].iter())
+        .assert()
+        .stdout(
+            r#"[{"commits":2,"language":"Go","path":"./hot.go","score":100.0,"size":{"tokens":3}}]
+"#,
+        )
+        .success();
+
+    // sizes are only counted from the working directory or --rev
+    for flag in ["--staged", "--changed-since=HEAD", "--git-files"] {
+        tcount()
+            .current_dir(&dir)
+            .args([flag, "hotspots"].iter())
+            .assert()
+            .failure();
+    }
+}
+
+#[test]
+fn test_git_files() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_git_files");
+    let repo = git_repo(&dir);
+    git_commit(
+        &repo,
+        &[
+            (".gitignore", "gen/\n"),
+            ("src/lib.rs", "fn a() {}\n"),
+            // tracked files are counted even if they're ignored
+            ("src/gen/tracked.rs", "fn b() {}\n"),
+        ],
+        0,
+    );
+    std::fs::write(dir.join("untracked.go"), "package main\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "--format=csv", "--sort-by=group"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./src/lib.rs,1,6
+./untracked.go,1,3
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--git-files",
+                "--groupby=file",
+                "--format=csv",
+                "--sort-by=group",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./src/gen/tracked.rs,1,6
+./src/lib.rs,1,6
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(dir.join("src"))
+        .args(["--git-files", "--groupby=arg", "--format=csv", "gen"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+gen,1,6
+",
+        )
+        .success();
+}
+
+#[test]
+fn test_check() {
+    tcount()
+        .current_dir("tests/fixtures/")
+        .args(
+            [
+                "--kind=line_comment",
+                "--check=max tokens per file = 40",
+                "--check=max kind:line_comment per group in Rust = 5",
+                "--check=max tokens per file in *.rb = 40",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"go1.go: 52 tokens is more than the maximum of 40 (max tokens per file = 40)
+rust3.rs: 73 tokens is more than the maximum of 40 (max tokens per file = 40)
+Rust: 9 kind:line_comment is more than the maximum of 5 (max kind:line_comment per group in Rust = 5)
+",
+        )
+        .failure();
+
+    tcount()
+        .current_dir("tests/fixtures/")
+        .args(["--check=max tokens per file = 100"].iter())
+        .assert()
+        .stdout("All rules passed.\n")
+        .success();
+
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_check");
+    let queries = dir.join(".tcount_queries/rust");
+    std::fs::create_dir_all(&queries).unwrap();
+    std::fs::write(
+        queries.join("definition.scm"),
+        "(function_item name: (identifier) @name) @definition\n",
+    )
+    .unwrap();
+    std::fs::write(queries.join("branch.scm"), "(if_expression)\n").unwrap();
+    std::fs::write(
+        dir.join("lib.rs"),
+        "fn a() {}\n\nfn b(x: u8) {\n    if x > 1 {\n        if x > 2 {}\n    }\n}\n",
+    )
+    .unwrap();
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--query=branch",
+                "--check=max query:branch per definition = 1",
+                "--check-format=github",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            "::error file=lib.rs,line=3,title=max query%3Abranch per definition = 1::2 query:branch in b is more than the maximum of 1\n",
+        )
+        .failure();
+}
+
+#[test]
+fn test_ratchet() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_ratchet");
+    std::fs::create_dir_all(&dir).unwrap();
+    let _ = std::fs::remove_file(dir.join(".tcount-budget.json"));
+    std::fs::write(dir.join("a.rs"), "fn a() {}\nfn b() {}\n").unwrap();
+    std::fs::write(dir.join("b.go"), "package main\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "ratchet"].iter())
+        .assert()
+        .failure();
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "ratchet", "--update"].iter())
+        .assert()
+        .stdout("Lowered 0 budgets in .tcount-budget.json\n")
+        .success();
+
+    std::fs::write(dir.join("a.rs"), "fn a() {}\nfn b() {}\nfn c() {}\n").unwrap();
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "ratchet"].iter())
+        .assert()
+        .stdout("./a.rs: 18 tokens is more than its budget of 12\n")
+        .failure();
+
+    // lowering a.rs doesn't let b.go grow
+    std::fs::write(dir.join("a.rs"), "fn a() {}\n").unwrap();
+    std::fs::write(dir.join("b.go"), "package main\nfunc b() {}\n").unwrap();
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "ratchet", "--update"].iter())
+        .assert()
+        .stdout(
+            r"Lowered 1 budgets in .tcount-budget.json
+./b.go: 10 tokens is more than its budget of 3
+",
+        )
+        .failure();
+    std::fs::write(dir.join("b.go"), "package main\n").unwrap();
+    tcount()
+        .current_dir(&dir)
+        .args(["--groupby=file", "ratchet"].iter())
+        .assert()
+        .stdout("Every count is within its budget.\n")
+        .success();
+}
+
+#[test]
+fn test_config() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_config");
+    let user = dir.join("user");
+    std::fs::create_dir_all(user.join("tcount")).unwrap();
+    std::fs::create_dir_all(dir.join("project/src")).unwrap();
+    std::fs::write(
+        user.join("tcount/config.toml"),
+        "format = \"csv\"\ngroupby = \"language\"\nshow_totals = true\n",
+    )
+    .unwrap();
+    std::fs::write(
+        dir.join("project/.tcount.toml"),
+        r#"# the project wins over the user
+groupby = "file"
+kind = ["line_comment"]
+show_totals = false
+
+[profile.totals]
+show_totals = true
+hide = ["files", "kind:line_comment"]
+
+[profile.baseline]
+save_baseline = "baseline.json"
+"#,
+    )
+    .unwrap();
+    std::fs::write(dir.join("project/src/a.rs"), "// a\nfn a() {}\n").unwrap();
+
+    // the closest .tcount.toml is found from subdirectories
+    tcount()
+        .current_dir(dir.join("project/src"))
+        .env("XDG_CONFIG_HOME", &user)
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens,Kind(line_comment)
+./a.rs,1,6,1
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(dir.join("project/src"))
+        .env("XDG_CONFIG_HOME", &user)
+        .args(["--profile=totals", "--groupby=language"].iter())
+        .assert()
+        .stdout(
+            r"Group,Tokens
+Rust,6
+TOTALS,6
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(dir.join("project/src"))
+        .env("XDG_CONFIG_HOME", &user)
+        .args(["--no-config", "--format=csv"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+Rust,1,6
+",
+        )
+        .success();
+
+    // paths are relative to the file they're set in
+    let _ = std::fs::remove_file(dir.join("project/baseline.json"));
+    tcount()
+        .current_dir(dir.join("project/src"))
+        .env("XDG_CONFIG_HOME", &user)
+        .args(["--profile=baseline"].iter())
+        .assert()
+        .success();
+    assert!(dir.join("project/baseline.json").is_file());
+    assert!(!dir.join("project/src/baseline.json").exists());
+
+    tcount()
+        .current_dir(dir.join("project/src"))
+        .args(["--profile=missing"].iter())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_map() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_map");
+    std::fs::create_dir_all(dir.join("include")).unwrap();
+    std::fs::write(dir.join("index.tpl"), "<p>hi</p>\n").unwrap();
+    std::fs::write(dir.join("include/a.h"), "class A {};\n").unwrap();
+    std::fs::write(dir.join("b.h"), "int b;\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(
+            [
+                "--format=csv",
+                "--groupby=file",
+                "--sort-by=group",
+                "--map=ext:tpl=html",
+                "--map=glob:include/**/*.h=cpp",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./b.h,1,3
+./include/a.h,1,5
+./index.tpl,1,8
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "--whitelist=C++", "--map=glob:**/*.h=C++"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+C++,2,8
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--map=ext:tpl=nope"].iter())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_scripts() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_scripts");
+    std::fs::create_dir_all(dir.join("bin")).unwrap();
+    std::fs::write(dir.join("bin/deploy"), "#!/usr/bin/env bash\necho hi\n").unwrap();
+    std::fs::write(dir.join("bin/serve"), "#!/usr/bin/python3\nprint(1)\n").unwrap();
+    std::fs::write(dir.join("bin/notes"), "nothing to see here\n").unwrap();
+    std::fs::write(dir.join("Rakefile"), "task :a\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "--groupby=file", "--sort-by=group"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files,Tokens
+./Rakefile,1,2
+./bin/deploy,1,11
+./bin/notes,1,0
+./bin/serve,1,12
+",
+        )
+        .success();
+}
+
+#[test]
+fn test_ambiguous_extensions() {
+    let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join("test_ambiguous_extensions");
+    std::fs::create_dir_all(&dir).unwrap();
+    std::fs::write(dir.join("a.h"), "namespace a {\nclass A;\n}\n").unwrap();
+    std::fs::write(dir.join("b.h"), "#include <vector>\n").unwrap();
+    std::fs::write(dir.join("c.h"), "#define C 1\n").unwrap();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "--hide=tokens", "--sort-by=group"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files
+C,1
+C++,2
+",
+        )
+        .success();
+
+    tcount()
+        .current_dir(&dir)
+        .args(["--format=csv", "--hide=tokens", "--map=ext:h=c"].iter())
+        .assert()
+        .stdout(
+            r"Group,Files
+C,3
+",
+        )
+        .success();
+}
@@ -37,3 +37,478 @@ fn test_format_table() {
         .stdout(expected)
         .success();
 }
+
+#[test]
+fn test_format_html() {
+    let output = tcount()
+        .current_dir("tests/fixtures")
+        .args(["--format", "html", "--show-totals"].iter())
+        .output()
+        .unwrap();
+    assert!(output.status.success());
+    let html = String::from_utf8(output.stdout).unwrap();
+    assert!(html.starts_with("<!DOCTYPE html>"));
+    // no external assets are loaded
+    assert!(!html.contains("src=\"http"));
+    assert!(!html.contains("href=\"http"));
+    assert!(html.contains(r#"<tr data-group="Rust"><td>Rust</td><td class="num" data-value="5">5</td><td class="num" data-value="156">156</td></tr>"#));
+    assert!(html.contains(r#"<tr data-group="Go"><td>./go1.go</td><td>Go</td><td>Go</td><td class="num" data-value="1">1</td><td class="num" data-value="52">52</td></tr>"#));
+    assert!(html.contains(r#"<tfoot><tr><td>TOTALS</td><td class="num" data-value="9">9</td><td class="num" data-value="251">251</td></tr></tfoot>"#));
+    assert!(html.contains("<title>Rust: 156 tokens (62.2%)</title>"));
+    assert!(html.contains("<title>go1.go\nTokens: 52 (Go)</title>"));
+}
+
+#[test]
+fn test_format_openmetrics() {
+    let expected = r#"# HELP tcount_files Number of files counted.
+# TYPE tcount_files gauge
+tcount_files{group="Rust"} 5
+tcount_files{group="Go"} 1
+# HELP tcount_tokens Number of tokens counted.
+# TYPE tcount_tokens gauge
+tcount_tokens{group="Rust"} 156
+tcount_tokens{group="Go"} 52
+# HELP tcount_kind Number of syntax tree nodes with a kind given by --kind.
+# TYPE tcount_kind gauge
+tcount_kind{kind="line_comment",group="Rust"} 9
+tcount_kind{kind="line_comment",group="Go"} 0
+# EOF
+"#;
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--format",
+                "openmetrics",
+                "--show-totals",
+                "--kind",
+                "line_comment",
+                "--whitelist",
+                "Rust",
+                "Go",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_chart() {
+    let expected = " █████████████████████████████████████▓▓▓▓▓▓▓▓▓▓▓▓▓▒▒▒▒▒▒▒▒▒▒
+ █ Rust 62.2%  ▓ Go 20.7%  ▒ Ruby 17.1%
+──────────────────────────────────────────────────
+ Group        Files  Tokens   
+──────────────────────────────────────────────────
+ Rust             5     156  ████████████████████ 
+ Go               1      52  ██████▋ 
+ Ruby             2      43  █████▌ 
+ Unsupported      1       0   
+ TOTALS           9     251   
+──────────────────────────────────────────────────
+";
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(["--chart", "--show-totals"].iter())
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_format_svg_treemap() {
+    let expected = r##"<?xml version="1.0" encoding="UTF-8"?>
+<svg xmlns="http://www.w3.org/2000/svg" width="1280" height="824" viewBox="0 0 1280 824" font-family="sans-serif" font-size="11">
+<title>Pattern(.*comment.*) by directory</title>
+<rect width="100%" height="100%" fill="white"/>
+<rect x="0.0" y="0.0" width="640.0" height="800.0" fill="#f4f4f4" stroke="#999" stroke-width="0.5"><title>foo
+Pattern(.*comment.*): 1</title></rect>
+<text x="3.0" y="11.0">foo</text>
+<rect x="2.0" y="14.0" width="636.0" height="784.0" fill="#701516" stroke="#fff" stroke-width="0.5"><title>foo/ruby.rb
+Pattern(.*comment.*): 1 (Ruby)</title></rect>
+<rect x="640.0" y="0.0" width="640.0" height="800.0" fill="#701516" stroke="#fff" stroke-width="0.5"><title>ruby.rb
+Pattern(.*comment.*): 1 (Ruby)</title></rect>
+<rect x="0.0" y="807.0" width="10" height="10" fill="#701516"/><text x="14.0" y="816.0">Ruby</text>
+</svg>
+"##;
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--format",
+                "svg-treemap",
+                "--no-dot-ignore",
+                "--whitelist",
+                "Ruby",
+                "--kind-pattern",
+                ".*comment.*",
+                "--treemap-column",
+                "pattern:.*comment.*",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_unknown_treemap_column() {
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(["--format", "svg-treemap", "--treemap-column", "kind:foo"].iter())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_format_tokei_json() {
+    let expected = r#"{"Go":{"blanks":0,"children":{},"code":10,"comments":0,"inaccurate":false,"reports":[{"name":"go1.go","stats":{"blanks":0,"blobs":{},"code":10,"comments":0,"tcount":{"kind:line_comment":0},"tokens":52}}],"tcount":{"kind:line_comment":0},"tokens":52},"Rust":{"blanks":1,"children":{},"code":5,"comments":6,"inaccurate":false,"reports":[{"name":"rust1.rs","stats":{"blanks":1,"blobs":{},"code":5,"comments":6,"tcount":{"kind:line_comment":3},"tokens":33}}],"tcount":{"kind:line_comment":3},"tokens":33},"Total":{"blanks":1,"children":{"Go":[{"name":"go1.go","stats":{"blanks":0,"blobs":{},"code":10,"comments":0,"tcount":{"kind:line_comment":0},"tokens":52}}],"Rust":[{"name":"rust1.rs","stats":{"blanks":1,"blobs":{},"code":5,"comments":6,"tcount":{"kind:line_comment":3},"tokens":33}}]},"code":15,"comments":6,"inaccurate":false,"reports":[],"tcount":{"kind:line_comment":3},"tokens":85}}
+"#;
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--format",
+                "tokei-json",
+                "rust1.rs",
+                "go1.go",
+                "--kind",
+                "line_comment",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_format_cloc_yaml() {
+    let expected = r#"---
+# github.com/RRethy/tcount
+header :
+  cloc_url : github.com/AlDanial/cloc
+  cloc_version : 1.96
+  elapsed_seconds : _
+  n_files : 7
+  n_lines : 70
+  files_per_second : _
+  lines_per_second : _
+  tcount_url : github.com/RRethy/tcount
+  tcount_version : 0.1.0
+Rust :
+  nFiles: 5
+  blank: 6
+  comment: 18
+  code: 28
+  tokens: 156
+  "kind:line_comment": 9
+Ruby :
+  nFiles: 2
+  blank: 1
+  comment: 1
+  code: 16
+  tokens: 43
+  "kind:line_comment": 0
+SUM :
+  nFiles: 7
+  blank: 7
+  comment: 19
+  code: 44
+  tokens: 199
+  "kind:line_comment": 9
+"#;
+    let assert = tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--format",
+                "cloc-yaml",
+                "--kind",
+                "line_comment",
+                "--whitelist",
+                "Rust",
+                "Ruby",
+            ]
+            .iter(),
+        )
+        .assert()
+        .success();
+    // Timings differ between runs so only check that they're numbers
+    let stdout = String::from_utf8_lossy(&assert.get_output().stdout).into_owned();
+    let actual = stdout
+        .lines()
+        .map(|line| match line.split_once(" : ") {
+            Some((key, value))
+                if [
+                    "  elapsed_seconds",
+                    "  files_per_second",
+                    "  lines_per_second",
+                ]
+                .contains(&key) =>
+            {
+                assert!(value.parse::<f64>().is_ok(), "{}", line);
+                format!("{} : _\n", key)
+            }
+            _ => format!("{}\n", line),
+        })
+        .collect::<String>();
+    assert_eq!(actual, expected);
+}
+
+#[test]
+fn test_template() {
+    let expected = r"\begin{tabular}{lrrr}
+Group & Files & Tokens & Pattern(.*\_comment) \\
+\hline
+Rust & 5 & 156 & 12 \\
+Go & 1 & 52 & 0 \\
+\hline
+Total & 6 & 208 & 12 \\
+\end{tabular}
+";
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--template",
+                "../templates/latex.tmpl",
+                "--kind-pattern",
+                ".*_comment",
+                "--whitelist",
+                "Rust",
+                "Go",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_template_totals_without_show_totals() {
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(["--template", "../templates/badge.tmpl"].iter())
+        .assert()
+        .stdout(
+            r#"{"schemaVersion": 1, "label": "tokens", "message": "251", "color": "blue"}
+"#,
+        )
+        .success();
+}
+
+#[test]
+fn test_template_error() {
+    let output = tcount()
+        .current_dir("tests/fixtures")
+        .args(["--template", "../templates/missing.tmpl"].iter())
+        .output()
+        .unwrap();
+    assert!(!output.status.success());
+    assert!(output.stdout.is_empty());
+}
+
+#[test]
+fn test_columns_hide_and_header() {
+    let expected = r"Language,Comments,Tokens
+Rust,12,156
+Go,0,52
+TOTALS,12,208
+";
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--format=csv",
+                "--show-totals",
+                "--kind-pattern=.*comment",
+                "--columns=group,files,pattern:.*comment,tokens",
+                "--hide=files",
+                "--header=pattern:.*comment=Comments",
+                "--header=group=Language",
+                "--whitelist",
+                "Rust",
+                "Go",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_unknown_column() {
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(["--columns=group,nope"].iter())
+        .assert()
+        .failure();
+}
+
+#[test]
+fn test_transpose() {
+    let expected = r"──────────────────────────────────────
+ Group               Rust  Go  TOTALS 
+──────────────────────────────────────
+ Tokens               156  52     208 
+ Kind(line_comment)     9   0       9 
+ Files                  5   1       6 
+──────────────────────────────────────
+";
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--transpose",
+                "--show-totals",
+                "--kind=line_comment",
+                "--columns=tokens,kind:line_comment,files",
+                "--whitelist",
+                "Rust",
+                "Go",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_template_metrics() {
+    let expected = r"Files: 5 1 = 6
+Kind(line_comment): 9 0 = 9
+";
+    tcount()
+        .current_dir("tests/fixtures")
+        .args(
+            [
+                "--template=../templates/metrics.tmpl",
+                "--kind=line_comment",
+                "--hide=tokens",
+                "--whitelist",
+                "Rust",
+                "Go",
+            ]
+            .iter(),
+        )
+        .assert()
+        .stdout(expected)
+        .success();
+}
+
+#[test]
+fn test_template_transpose() {
+    let render = |transpose: bool| {
+        tcount()
+            .current_dir("tests/fixtures")
+            .args(
+                [
+                    "--template=../templates/table.tmpl",
+                    "--whitelist",
+                    "Rust",
+                    "Go",
+                ]
+                .iter(),
+            )
+            .args(transpose.then_some("--transpose"))
+            .assert()
+            .success()
+    };
+    render(false).stdout("Rust: 5 156\nGo: 1 52\n");
+    render(true).stdout("Files: 5 1\nTokens: 156 52\n");
+}
+
+#[test]
+fn test_transpose_formats() {
+    let render = |args: &[&str]| {
+        let output = tcount()
+            .current_dir("tests/fixtures")
+            .args(["--transpose", "--kind=line_comment"].iter())
+            .args(args.iter())
+            .args(["--whitelist", "Rust", "Go"].iter())
+            .output()
+            .unwrap();
+        assert!(output.status.success());
+        String::from_utf8(output.stdout).unwrap()
+    };
+    assert_eq!(
+        render(&["--format=openmetrics"]),
+        r#"# HELP tcount Every count, labelled by its column and group.
+# TYPE tcount gauge
+tcount{column="files",group="Rust"} 5
+tcount{column="tokens",group="Rust"} 156
+tcount{column="kind:line_comment",group="Rust"} 9
+tcount{column="files",group="Go"} 1
+tcount{column="tokens",group="Go"} 52
+tcount{column="kind:line_comment",group="Go"} 0
+# EOF
+"#
+    );
+    // the header is followed by an entry for each count, the biggest SUM first
+    assert!(
+        render(&["--format=cloc-yaml", "--sort-metrics=total"]).ends_with(
+            r#"  tcount_version : 0.1.0
+tokens :
+  Rust: 156
+  Go: 52
+  SUM: 208
+code :
+  Rust: 28
+  Go: 10
+  SUM: 38
+comment :
+  Rust: 18
+  Go: 0
+  SUM: 18
+"kind:line_comment" :
+  Rust: 9
+  Go: 0
+  SUM: 9
+nFiles :
+  Rust: 5
+  Go: 1
+  SUM: 6
+blank :
+  Rust: 6
+  Go: 0
+  SUM: 6
+"#
+        )
+    );
+    assert_eq!(
+        render(&["--format=csv", "--sort-metrics=name", "--show-totals"]),
+        r"Group,Rust,Go,TOTALS
+Files,5,1,6
+Kind(line_comment),9,0,9
+Tokens,156,52,208
+"
+    );
+    let html = render(&["--format=html"]);
+    assert!(html.contains(r#"<table id="metrics" class="sortable">"#));
+    assert!(html.contains("Showing all files."));
+}
+
+#[test]
+fn test_transpose_conflicts() {
+    for args in [
+        &["--transpose", "--chart"][..],
+        &["--transpose", "--compare=baseline.json"],
+        &["--transpose", "--compare-base"],
+        &["--transpose", "history", "--format=json"],
+        &["--transpose", "ratchet"],
+        &["--sort-metrics=name"],
+        &["--transpose", "--sort-metrics=name", "history"],
+    ] {
+        tcount()
+            .current_dir("tests/fixtures")
+            .args(args.iter())
+            .assert()
+            .failure();
+    }
+}
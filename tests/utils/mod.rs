@@ -1,3 +1,9 @@
 pub fn tcount() -> assert_cmd::Command {
-    assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap()
+    let mut cmd = assert_cmd::Command::cargo_bin(env!("CARGO_PKG_NAME")).unwrap();
+    // the configuration file of whoever runs the tests shouldn't change their results
+    cmd.env(
+        "XDG_CONFIG_HOME",
+        concat!(env!("CARGO_TARGET_TMPDIR"), "/xdg_config_home"),
+    );
+    cmd
 }